@@ -0,0 +1,276 @@
+//! Module providing implementations of the `Allocator`-traits of the unstable
+//! [`allocator_api`][core-api] and of the [`allocator-api2`][api2] crate for
+//! [`Allocator`].
+//!
+//! The [`core::alloc::Allocator`] trait is only available with the
+//! `allocator_api` feature on a nightly compiler. The `allocator-api2` crate
+//! provides the same trait (and collections using it) on stable compilers and
+//! is enabled by the `allocator-api2` feature. Both allow to use dedicated heaps
+//! for individual collections, e.g. via `Vec::new_in(&HEAP)`, next to (or
+//! instead of) the global allocator.
+//!
+//! [core-api]: https://doc.rust-lang.org/core/alloc/trait.Allocator.html
+//! [api2]: https://docs.rs/allocator-api2
+use crate::{Allocator, Header, Strategy};
+
+use core::alloc::Layout;
+use core::mem::MaybeUninit;
+use core::ptr::{self, NonNull};
+
+/// Split the memory of a raw allocation into its pointer and its size.
+fn raw_parts(memory: &mut [MaybeUninit<u8>]) -> (NonNull<u8>, usize) {
+    let size = memory.len();
+    (NonNull::from(memory).cast(), size)
+}
+
+/// Create the pointer type of the `Allocator`-traits from the pointer and the
+/// size of a memory block.
+fn slice_of((ptr, size): (NonNull<u8>, usize)) -> NonNull<[u8]> {
+    let slice = ptr::slice_from_raw_parts_mut(ptr.as_ptr(), size);
+    // SAFETY: the slice starts at a non-null pointer
+    unsafe { NonNull::new_unchecked(slice) }
+}
+
+impl<const N: usize, S: Strategy, H: Header> Allocator<N, S, H> {
+    /// Allocate a memory block for the given layout, that is optionally
+    /// filled with zeroes.
+    ///
+    /// The pointer and the usable size of the block are returned.
+    fn allocate_block(&self, layout: Layout, zeroed: bool) -> Option<(NonNull<u8>, usize)> {
+        // if the heap is exhausted, the reclaim hooks are called and the
+        // allocation is retried.
+        crate::reclaim::with_reclaim(&self.reclaimers, layout, || {
+            let mut raw = self.raw.lock();
+            let memory = if zeroed {
+                // the raw allocator knows, which parts of the heap are still
+                // zero, so only the remaining bytes of the memory block are
+                // cleared.
+                raw.alloc_zeroed(layout.size(), layout.align())
+            } else {
+                raw.alloc(layout.size(), layout.align())
+            };
+            memory.map(raw_parts).ok_or(raw)
+        })
+        .map_err(|mut raw| raw.record_failure())
+        .ok()
+    }
+
+    /// Free the memory block at the given pointer, which was allocated with
+    /// the given layout.
+    fn deallocate_block(&self, ptr: NonNull<u8>, layout: Layout) {
+        // errors cannot be returned, see `GlobalAlloc::dealloc()`
+        let result = self.raw.lock().free(ptr.as_ptr());
+        if let Err(error) = result {
+            crate::free_error::handle(&self.free_errors, ptr.as_ptr(), layout, error);
+        }
+    }
+
+    /// Resize the allocation at `ptr` to the `new` layout.
+    ///
+    /// The block is resized in place, if it is suitably aligned for the new
+    /// layout and the raw allocator can resize it. Otherwise a new block is
+    /// allocated, the contents of the old one are copied and the old block is
+    /// freed. In both cases the memory after the old size is uninitialized.
+    /// The pointer and the usable size of the block are returned.
+    ///
+    /// # Safety
+    /// The pointer has to be allocated by this allocator and valid for `old`.
+    unsafe fn reallocate(
+        &self,
+        ptr: NonNull<u8>,
+        old: Layout,
+        new: Layout,
+    ) -> Option<(NonNull<u8>, usize)> {
+        let aligned = ptr.as_ptr() as usize % new.align() == 0;
+        if aligned && self.raw.lock().resize(ptr.as_ptr(), new.size()) {
+            return Some((ptr, new.size()));
+        }
+
+        let (new_ptr, size) = self.allocate_block(new, false)?;
+        // SAFETY: both blocks are valid for the smaller of the two sizes and
+        // the old block is still allocated, so they cannot overlap.
+        unsafe {
+            ptr::copy_nonoverlapping(ptr.as_ptr(), new_ptr.as_ptr(), old.size().min(new.size()));
+        }
+        self.deallocate_block(ptr, old);
+        Some((new_ptr, size))
+    }
+
+    /// Grow the allocation at `ptr` to the `new` layout and clear the memory
+    /// after the old size.
+    ///
+    /// # Safety
+    /// The pointer has to be allocated by this allocator and valid for `old`,
+    /// which must not be larger than `new`.
+    unsafe fn reallocate_zeroed(
+        &self,
+        ptr: NonNull<u8>,
+        old: Layout,
+        new: Layout,
+    ) -> Option<(NonNull<u8>, usize)> {
+        // SAFETY: the caller upholds the safety contract
+        let (new_ptr, size) = unsafe { self.reallocate(ptr, old, new) }?;
+        // SAFETY: the block is valid for its size, which is at least as large
+        // as the old size (guaranteed by the caller).
+        unsafe {
+            new_ptr
+                .as_ptr()
+                .add(old.size())
+                .write_bytes(0x00, size - old.size());
+        }
+        Some((new_ptr, size))
+    }
+}
+
+/// Implement an `Allocator`-trait with the given error type for `&Allocator`.
+///
+/// The trait of the unstable `allocator_api` and the one of `allocator-api2`
+/// have the same methods, so their implementations are identical.
+macro_rules! impl_allocator {
+    ($allocator:path, $error:path) => {
+        // SAFETY: the memory blocks are valid until they are deallocated (or
+        // the allocator is dropped, which cannot happen while it is borrowed).
+        // Blocks are only ever moved by `grow()` and `shrink()`, which return
+        // the new pointer.
+        unsafe impl<const N: usize, S: Strategy, H: Header> $allocator for &Allocator<N, S, H> {
+            fn allocate(&self, layout: Layout) -> Result<NonNull<[u8]>, $error> {
+                self.allocate_block(layout, false)
+                    .map(slice_of)
+                    .ok_or($error)
+            }
+
+            fn allocate_zeroed(&self, layout: Layout) -> Result<NonNull<[u8]>, $error> {
+                self.allocate_block(layout, true)
+                    .map(slice_of)
+                    .ok_or($error)
+            }
+
+            unsafe fn deallocate(&self, ptr: NonNull<u8>, layout: Layout) {
+                self.deallocate_block(ptr, layout);
+            }
+
+            unsafe fn grow(
+                &self,
+                ptr: NonNull<u8>,
+                old_layout: Layout,
+                new_layout: Layout,
+            ) -> Result<NonNull<[u8]>, $error> {
+                // SAFETY: the caller guarantees, that the pointer is valid for
+                // the old layout.
+                unsafe { self.reallocate(ptr, old_layout, new_layout) }
+                    .map(slice_of)
+                    .ok_or($error)
+            }
+
+            unsafe fn grow_zeroed(
+                &self,
+                ptr: NonNull<u8>,
+                old_layout: Layout,
+                new_layout: Layout,
+            ) -> Result<NonNull<[u8]>, $error> {
+                // SAFETY: the caller guarantees, that the pointer is valid for
+                // the old layout, which is not larger than the new one.
+                unsafe { self.reallocate_zeroed(ptr, old_layout, new_layout) }
+                    .map(slice_of)
+                    .ok_or($error)
+            }
+
+            unsafe fn shrink(
+                &self,
+                ptr: NonNull<u8>,
+                old_layout: Layout,
+                new_layout: Layout,
+            ) -> Result<NonNull<[u8]>, $error> {
+                // SAFETY: the caller guarantees, that the pointer is valid for
+                // the old layout.
+                unsafe { self.reallocate(ptr, old_layout, new_layout) }
+                    .map(slice_of)
+                    .ok_or($error)
+            }
+        }
+    };
+}
+#[cfg(feature = "allocator_api")]
+impl_allocator!(core::alloc::Allocator, core::alloc::AllocError);
+#[cfg(feature = "allocator-api2")]
+impl_allocator!(
+    allocator_api2::alloc::Allocator,
+    allocator_api2::alloc::AllocError
+);
+
+#[cfg(all(test, feature = "allocator_api"))]
+#[allow(clippy::undocumented_unsafe_blocks)] // the tests use the `unsafe` allocator API
+mod tests {
+    use crate::Allocator;
+    use core::alloc::{Allocator as _, Layout};
+
+    #[test]
+    fn collections_in_dedicated_heap() {
+        let heap = Allocator::<1024>::new();
+
+        let mut vec = Vec::new_in(&heap);
+        vec.extend(0..100_u32);
+        let boxed = Box::new_in(42_u64, &heap);
+        assert_eq!(vec.iter().sum::<u32>(), 4950);
+        assert_eq!(*boxed, 42);
+    }
+
+    #[test]
+    fn exhausted_heap() {
+        let heap = Allocator::<64>::new();
+        let layout = Layout::from_size_align(64, 4).unwrap();
+        assert!((&heap).allocate(layout).is_err());
+
+        let mut vec = Vec::<u8, _>::new_in(&heap);
+        assert!(vec.try_reserve(100).is_err());
+    }
+
+    #[test]
+    fn grow_and_shrink_in_place() {
+        let heap = Allocator::<256>::new();
+        let small = Layout::from_size_align(8, 4).unwrap();
+        let large = Layout::from_size_align(64, 4).unwrap();
+
+        let ptr = (&heap).allocate(small).unwrap().cast::<u8>();
+        unsafe {
+            ptr.as_ptr().write_bytes(0xff, 8);
+            let grown = (&heap).grow_zeroed(ptr, small, large).unwrap();
+            assert_eq!(grown.cast::<u8>(), ptr);
+            assert_eq!(grown.len(), 64);
+            let memory = grown.as_ref();
+            assert!(memory[..8].iter().all(|byte| *byte == 0xff));
+            assert!(memory[8..].iter().all(|byte| *byte == 0x00));
+
+            let shrunk = (&heap).shrink(ptr, large, small).unwrap();
+            assert_eq!(shrunk.cast::<u8>(), ptr);
+            (&heap).deallocate(ptr, small);
+        }
+    }
+
+    #[test]
+    fn grow_moves_the_block() {
+        let heap = Allocator::<256>::new();
+        let small = Layout::from_size_align(8, 4).unwrap();
+        let large = Layout::from_size_align(64, 4).unwrap();
+
+        let ptr = (&heap).allocate(small).unwrap().cast::<u8>();
+        let _blocker = (&heap).allocate(small).unwrap();
+        unsafe {
+            ptr.as_ptr().write_bytes(0xaa, 8);
+            let grown = (&heap).grow(ptr, small, large).unwrap();
+            assert_ne!(grown.cast::<u8>(), ptr);
+            assert!(grown.as_ref()[..8].iter().all(|byte| *byte == 0xaa));
+        }
+    }
+
+    #[test]
+    fn grow_to_larger_alignment() {
+        let heap = Allocator::<256>::new();
+        let old = Layout::from_size_align(8, 4).unwrap();
+        let new = Layout::from_size_align(16, 64).unwrap();
+
+        let ptr = (&heap).allocate(old).unwrap().cast::<u8>();
+        let grown = unsafe { (&heap).grow(ptr, old, new) }.unwrap();
+        assert_eq!(grown.cast::<u8>().as_ptr() as usize % 64, 0);
+    }
+}
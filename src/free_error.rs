@@ -0,0 +1,316 @@
+//! Module providing the handling of invalid frees, i.e. of deallocations of
+//! pointers, that are not allocated.
+//!
+//! The raw allocator detects double frees, pointers, that do not belong to any
+//! allocation, and overwritten red zones (see [`FreeError`]). Since `dealloc()` of the
+//! [`GlobalAlloc`](core::alloc::GlobalAlloc)-trait cannot return an error,
+//! those are ignored by default. The [`FreePolicy`] of an allocator selects a
+//! different reaction, e.g. recording the errors into a [`FreeErrorLog`],
+//! that can be queried later.
+use crate::raw_allocator::FreeError;
+use crate::AbortOnUnwind;
+
+use core::alloc::Layout;
+
+/// A function called for each invalid free, see [`FreePolicy::Hook`].
+///
+/// The function receives the freed pointer, the layout passed to the
+/// deallocation and the detected error. If the function panics, the program
+/// is aborted, since the allocator must not unwind.
+pub type FreeHook = fn(*mut u8, Layout, FreeError);
+
+/// The reaction of an allocator to invalid frees.
+///
+/// The policy is selected via `set_free_policy()` of the allocator, e.g.
+/// [`Allocator::set_free_policy()`](crate::Allocator::set_free_policy). The
+/// default policy is [`FreePolicy::Ignore`].
+#[derive(Debug, Clone, Copy)]
+pub enum FreePolicy {
+    /// Ignore invalid frees, i.e. leave the heap unchanged (except for freeing
+    /// an allocation with overwritten red zones).
+    Ignore,
+    /// Panic on an invalid free and abort the program.
+    ///
+    /// The [`GlobalAlloc`](core::alloc::GlobalAlloc)-trait does not allow the
+    /// allocator to unwind, so the panic is turned into an abort: the panic
+    /// handler is called with a message describing the invalid free, but the
+    /// program is aborted instead of unwinding (also with `panic = "unwind"`).
+    Panic,
+    /// Record invalid frees into the [`FreeErrorLog`] of the allocator.
+    Record,
+    /// Call the given function for each invalid free.
+    ///
+    /// The function is called without holding the lock of the allocator, so
+    /// it may use the allocator itself.
+    Hook(FreeHook),
+}
+
+/// An invalid free, which was detected by an allocator.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidFree {
+    /// The address, that was freed.
+    pub address: usize,
+    /// The size of the layout passed to the deallocation.
+    pub size: usize,
+    /// The alignment of the layout passed to the deallocation.
+    pub align: usize,
+    /// The detected error.
+    pub error: FreeError,
+}
+impl InvalidFree {
+    /// An invalid free consisting of zeroes only, which fills the unused
+    /// entries of an empty log.
+    const ZERO: Self = Self {
+        address: 0,
+        size: 0,
+        align: 0,
+        error: FreeError::DoubleFreeDetected,
+    };
+}
+
+/// The log of the most recent invalid frees of an allocator.
+///
+/// The log keeps the last [`CAPACITY`](Self::CAPACITY) invalid frees, older
+/// ones are overwritten. The total number of recorded invalid frees is kept
+/// nevertheless.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FreeErrorLog {
+    /// The invalid frees used as a ring buffer.
+    entries: [InvalidFree; Self::CAPACITY],
+    /// The total number of recorded invalid frees.
+    count: usize,
+}
+impl FreeErrorLog {
+    /// The number of invalid frees kept by the log.
+    pub const CAPACITY: usize = 8;
+
+    /// The empty log.
+    const EMPTY: Self = Self {
+        entries: [InvalidFree::ZERO; Self::CAPACITY],
+        count: 0,
+    };
+
+    /// Query the total number of recorded invalid frees.
+    ///
+    /// This includes the invalid frees, that were overwritten already.
+    #[must_use]
+    pub const fn count(&self) -> usize {
+        self.count
+    }
+
+    /// Iterate over the invalid frees kept by the log, from the oldest to the
+    /// most recent one.
+    pub fn iter(&self) -> impl Iterator<Item = InvalidFree> + '_ {
+        let kept = self.count.min(Self::CAPACITY);
+        (self.count - kept..self.count).map(move |index| self.entries[index % Self::CAPACITY])
+    }
+
+    /// Query the most recent invalid free, if there is any.
+    #[must_use]
+    pub fn last(&self) -> Option<InvalidFree> {
+        self.iter().last()
+    }
+
+    /// Add the given invalid free to the log.
+    fn record(&mut self, invalid_free: InvalidFree) {
+        self.entries[self.count % Self::CAPACITY] = invalid_free;
+        self.count = self.count.saturating_add(1);
+    }
+}
+
+/// The mode of a [`FreePolicy`] without the function of a hook.
+///
+/// The hook is stored separately, so that the state of a new allocator
+/// consists of zeroes only and is placed in the `.bss`-section.
+#[derive(Clone, Copy)]
+enum Mode {
+    /// See [`FreePolicy::Ignore`].
+    Ignore,
+    /// See [`FreePolicy::Panic`].
+    Panic,
+    /// See [`FreePolicy::Record`].
+    Record,
+    /// See [`FreePolicy::Hook`].
+    Hook,
+}
+
+/// The handling of invalid frees of an allocator.
+pub struct FreeErrors {
+    /// The mode of the policy.
+    mode: Mode,
+    /// The function of the hook policy.
+    hook: Option<FreeHook>,
+    /// The log of the recorded invalid frees.
+    log: FreeErrorLog,
+}
+impl FreeErrors {
+    /// The handling of a new allocator, which ignores invalid frees.
+    pub const NEW: Self = Self {
+        mode: Mode::Ignore,
+        hook: None,
+        log: FreeErrorLog::EMPTY,
+    };
+
+    /// Select the policy for future invalid frees.
+    pub fn set_policy(&mut self, policy: FreePolicy) {
+        let (mode, hook) = match policy {
+            FreePolicy::Ignore => (Mode::Ignore, None),
+            FreePolicy::Panic => (Mode::Panic, None),
+            FreePolicy::Record => (Mode::Record, None),
+            FreePolicy::Hook(hook) => (Mode::Hook, Some(hook)),
+        };
+        self.mode = mode;
+        self.hook = hook;
+    }
+
+    /// Query the log of the recorded invalid frees.
+    pub const fn log(&self) -> FreeErrorLog {
+        self.log
+    }
+
+    /// Record the invalid free, if requested by the policy, which is returned.
+    fn report(&mut self, invalid_free: InvalidFree) -> FreePolicy {
+        match (self.mode, self.hook) {
+            (Mode::Ignore, _) => FreePolicy::Ignore,
+            (Mode::Panic, _) => FreePolicy::Panic,
+            (Mode::Record, _) => {
+                self.log.record(invalid_free);
+                FreePolicy::Record
+            }
+            (Mode::Hook, hook) => hook.map_or(FreePolicy::Ignore, FreePolicy::Hook),
+        }
+    }
+}
+
+/// Handle the invalid free of `ptr` according to the policy of the allocator.
+///
+/// The lock of the handling is released before panicking or calling the hook,
+/// so that the hook can use the allocator. Neither the panic nor a panicking
+/// hook unwind out of this function, the program is aborted instead.
+pub fn handle(
+    free_errors: &spin::Mutex<FreeErrors>,
+    ptr: *mut u8,
+    layout: Layout,
+    error: FreeError,
+) {
+    let invalid_free = InvalidFree {
+        address: ptr as usize,
+        size: layout.size(),
+        align: layout.align(),
+        error,
+    };
+    let policy = free_errors.lock().report(invalid_free);
+    let abort = AbortOnUnwind;
+    match policy {
+        FreePolicy::Ignore | FreePolicy::Record => {}
+        FreePolicy::Panic => panic!("invalid free of {:p}: {:?}", ptr, error),
+        FreePolicy::Hook(hook) => hook(ptr, layout, error),
+    }
+    abort.disarm();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{FreeError, FreeErrorLog, FreeErrors, FreePolicy, InvalidFree};
+    use core::alloc::Layout;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    fn invalid_free(address: usize) -> InvalidFree {
+        InvalidFree {
+            address,
+            size: 4,
+            align: 4,
+            error: FreeError::AllocationNotFound,
+        }
+    }
+
+    #[test]
+    fn log_keeps_the_most_recent_entries() {
+        let mut log = FreeErrorLog::EMPTY;
+        assert_eq!(log.count(), 0);
+        assert_eq!(log.last(), None);
+
+        for address in 0..10 {
+            log.record(invalid_free(address));
+        }
+        assert_eq!(log.count(), 10);
+        assert_eq!(log.last(), Some(invalid_free(9)));
+        let addresses: Vec<_> = log.iter().map(|entry| entry.address).collect();
+        assert_eq!(addresses, [2, 3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[test]
+    fn policies() {
+        let free_errors = spin::Mutex::new(FreeErrors::NEW);
+        let layout = Layout::new::<u32>();
+        let ptr = 0x1234 as *mut u8;
+        super::handle(&free_errors, ptr, layout, FreeError::AllocationNotFound);
+        assert_eq!(free_errors.lock().log().count(), 0);
+
+        free_errors.lock().set_policy(FreePolicy::Record);
+        super::handle(&free_errors, ptr, layout, FreeError::DoubleFreeDetected);
+        let expected = InvalidFree {
+            address: 0x1234,
+            size: 4,
+            align: 4,
+            error: FreeError::DoubleFreeDetected,
+        };
+        assert_eq!(free_errors.lock().log().last(), Some(expected));
+
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        free_errors
+            .lock()
+            .set_policy(FreePolicy::Hook(|ptr, layout, error| {
+                assert_eq!(ptr as usize, 0x1234);
+                assert_eq!(layout, Layout::new::<u32>());
+                assert_eq!(error, FreeError::AllocationNotFound);
+                CALLS.fetch_add(1, Ordering::Relaxed);
+            }));
+        super::handle(&free_errors, ptr, layout, FreeError::AllocationNotFound);
+        assert_eq!(CALLS.load(Ordering::Relaxed), 1);
+        assert_eq!(free_errors.lock().log().count(), 1);
+    }
+
+    /// Run the test with the given name in a child process and return its
+    /// output, if the test is not run in a child process already.
+    fn run_in_child(test: &str) -> Option<std::process::Output> {
+        if std::env::var_os("EMBALLOC_CHILD").is_some() {
+            return None;
+        }
+        let output = std::process::Command::new(std::env::current_exe().unwrap())
+            .args(["--exact", test, "--nocapture", "--test-threads=1"])
+            .env("EMBALLOC_CHILD", "1")
+            .output()
+            .unwrap();
+        Some(output)
+    }
+
+    #[test]
+    #[cfg_attr(miri, ignore)] // spawns a process
+    fn panic_policy_aborts() {
+        let output = match run_in_child("free_error::tests::panic_policy_aborts") {
+            Some(output) => output,
+            None => {
+                let free_errors = spin::Mutex::new(FreeErrors::NEW);
+                free_errors.lock().set_policy(FreePolicy::Panic);
+                let ptr = 0x1234 as *mut u8;
+                let layout = Layout::new::<u32>();
+                let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+                    super::handle(&free_errors, ptr, layout, FreeError::AllocationNotFound);
+                }));
+                unreachable!("the panic must not unwind");
+            }
+        };
+
+        // the child process was aborted after the panic message
+        let stderr = String::from_utf8_lossy(&output.stderr);
+        assert!(stderr.contains("invalid free of 0x1234: AllocationNotFound"));
+        assert!(!stderr.contains("the panic must not unwind"));
+        assert!(!output.status.success());
+        #[cfg(unix)]
+        {
+            use std::os::unix::process::ExitStatusExt;
+            assert_eq!(output.status.signal(), Some(6), "aborted by SIGABRT");
+        }
+    }
+}
@@ -0,0 +1,257 @@
+//! Module providing the handle-based API of the [`Allocator`].
+//!
+//! Allocations via the [`GlobalAlloc`](core::alloc::GlobalAlloc)-trait are
+//! referenced by pointers, so their memory blocks can never be moved. If many
+//! long-lived allocations are freed in arbitrary order, the heap can become
+//! fragmented over time. Blocks allocated via a [`Handle`] are referenced
+//! indirectly instead, so the allocator is free to move them while they are
+//! not locked. This way the fragmentation can be repaired on demand by calling
+//! [`Allocator::compact()`].
+use crate::{free_error, Allocator, FreeError, Header, Strategy};
+
+use core::alloc::Layout;
+use core::ptr;
+
+/// A reference to a movable memory block allocated by
+/// [`Allocator::alloc_handle()`].
+///
+/// The memory of the block can only be accessed, while the handle is locked
+/// via [`Allocator::lock()`]. The handle is released with
+/// [`Allocator::free_handle()`]. A handle is only valid for the allocator,
+/// that created it: it is tagged with that allocator, so that it is rejected
+/// by all other allocators.
+#[derive(Debug, PartialEq, Eq)]
+pub struct Handle {
+    /// The index of the slot in the table of handles of the allocator.
+    index: usize,
+    /// The tag of the allocator, that created this handle.
+    tag: usize,
+}
+
+impl<const N: usize, S: Strategy, H: Header> Allocator<N, S, H> {
+    /// Allocate a movable memory block for the given layout.
+    ///
+    /// The memory block shares the heap with the allocations via the
+    /// [`GlobalAlloc`](core::alloc::GlobalAlloc)-trait. In contrast to those,
+    /// the block is referenced by the returned [`Handle`], so that it can be
+    /// moved by [`compact()`](Self::compact). The handles are stored in a table
+    /// inside of the heap, which is allocated on the first call and grows on
+    /// demand (each handle occupies two headers).
+    ///
+    /// The alignment of the layout must not exceed the granule of the header
+    /// format (`4` by default), since the blocks are moved to arbitrary
+    /// multiples of the granule. If the allocation fails, `None` is returned.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::Layout;
+    ///
+    /// let allocator = emballoc::Allocator::<256>::new();
+    /// let handle = allocator.alloc_handle(Layout::new::<u32>()).unwrap();
+    ///
+    /// let ptr = allocator.lock(&handle).cast::<u32>();
+    /// // SAFETY: the block is locked and large enough for a `u32`
+    /// unsafe { ptr.write(42) };
+    /// allocator.unlock(&handle);
+    ///
+    /// allocator.compact(); // might move the block
+    /// let ptr = allocator.lock(&handle).cast::<u32>();
+    /// // SAFETY: the block is locked and was initialized above
+    /// assert_eq!(unsafe { ptr.read() }, 42);
+    /// allocator.unlock(&handle);
+    ///
+    /// allocator.free_handle(handle);
+    /// ```
+    pub fn alloc_handle(&self, layout: Layout) -> Option<Handle> {
+        let mut raw = self.raw.lock();
+        let index = raw
+            .alloc_handle(layout.size(), layout.align())
+            .or_else(|| {
+                raw.record_failure();
+                None
+            })?;
+        let tag = raw.handle_tag();
+        Some(Handle { index, tag })
+    }
+
+    /// Lock the block of the given handle and obtain a pointer to its memory.
+    ///
+    /// The block is not moved while it is locked, so the pointer stays valid
+    /// until the handle is unlocked via [`unlock()`](Self::unlock). Locks can
+    /// be nested: the block can be moved again after it was unlocked as often
+    /// as it was locked. If the handle does not belong to this allocator or if
+    /// it is locked 65535 times already, a null pointer is returned.
+    pub fn lock(&self, handle: &Handle) -> *mut u8 {
+        let mut raw = self.raw.lock();
+        if !raw.has_handle_tag(handle.tag) {
+            return ptr::null_mut();
+        }
+        raw.lock(handle.index)
+            .map_or(ptr::null_mut(), |memory| memory.as_mut_ptr().cast())
+    }
+
+    /// Unlock the block of the given handle.
+    ///
+    /// Pointers obtained by [`lock()`](Self::lock) must not be used afterwards
+    /// (unless the handle is still locked by another call). Unlocking a handle,
+    /// that is not locked or that belongs to another allocator, does nothing.
+    pub fn unlock(&self, handle: &Handle) {
+        let mut raw = self.raw.lock();
+        if raw.has_handle_tag(handle.tag) {
+            // unlocking an unlocked handle is harmless, so the error is ignored
+            let _was_locked = raw.unlock(handle.index);
+        }
+    }
+
+    /// Free the block of the given handle.
+    ///
+    /// The block is freed even if it is still locked, so all pointers obtained
+    /// by [`lock()`](Self::lock) become invalid. A handle of another allocator
+    /// is rejected, its block is not freed. Like with
+    /// [`dealloc()`](core::alloc::GlobalAlloc::dealloc), errors are handled
+    /// according to the [`FreePolicy`](crate::FreePolicy). Since a handle has
+    /// neither a pointer nor a layout, the errors are reported with the layout
+    /// of `()` and with the pointer to the memory of the block, if it is known
+    /// (i.e. for a [`FreeError::RedZoneViolation`]). An invalid handle (e.g. of
+    /// another allocator) is reported as [`FreeError::InvalidHandle`] with a
+    /// null pointer, so that it can be told apart from freeing a null pointer.
+    #[allow(clippy::needless_pass_by_value)] // the handle is invalid afterwards
+    pub fn free_handle(&self, handle: Handle) {
+        // the lock is released before handling the error, so that a hook can
+        // use the allocator.
+        let result = {
+            let mut raw = self.raw.lock();
+            if raw.has_handle_tag(handle.tag) {
+                raw.free_handle(handle.index)
+            } else {
+                Err(FreeError::InvalidHandle)
+            }
+        };
+        if let Err(error) = result {
+            let ptr = match error {
+                FreeError::RedZoneViolation { address, .. } => address as *mut u8,
+                _ => ptr::null_mut(),
+            };
+            free_error::handle(&self.free_errors, ptr, Layout::new::<()>(), error);
+        }
+    }
+
+    /// Compact the heap by moving the blocks of all unlocked handles.
+    ///
+    /// The blocks are moved towards the start of the heap, so that the free
+    /// memory between them is merged into larger free blocks. Blocks allocated
+    /// via the [`GlobalAlloc`](core::alloc::GlobalAlloc)-trait and locked
+    /// blocks are never moved, so the free memory is collected in front of
+    /// those. The runtime depends on the number of handles and the amount of
+    /// moved memory, so this should be called at a convenient point in time
+    /// (e.g. in an idle loop) rather than on every allocation.
+    pub fn compact(&self) {
+        self.raw.lock().compact();
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::undocumented_unsafe_blocks)] // the tests use the `unsafe` allocator API
+mod tests {
+    use crate::{Allocator, FreeError, FreePolicy};
+    use core::alloc::{GlobalAlloc, Layout};
+
+    #[test]
+    fn compaction_repairs_fragmentation() {
+        let allocator = Allocator::<256>::new();
+        let layout = Layout::from_size_align(40, 4).unwrap();
+        let mut handles: Vec<_> = (0..4)
+            .map(|_| allocator.alloc_handle(layout).unwrap())
+            .collect();
+        let _pinned = unsafe { allocator.alloc(Layout::new::<u32>()) };
+
+        // free every second block: there are now two free blocks of 40 bytes
+        // in between the remaining ones, but no room for 80 bytes
+        allocator.free_handle(handles.remove(2));
+        allocator.free_handle(handles.remove(0));
+        let large = Layout::from_size_align(80, 4).unwrap();
+        assert_eq!(allocator.alloc_handle(large), None);
+
+        allocator.compact();
+        assert!(allocator.alloc_handle(large).is_some());
+    }
+
+    #[test]
+    fn locked_blocks_stay_in_place() {
+        let allocator = Allocator::<256>::new();
+        let layout = Layout::new::<[u32; 2]>();
+        let first = allocator.alloc_handle(layout).unwrap();
+        let second = allocator.alloc_handle(layout).unwrap();
+
+        let ptr = allocator.lock(&second);
+        assert!(!ptr.is_null());
+        allocator.free_handle(first);
+        allocator.compact();
+        assert_eq!(allocator.lock(&second), ptr);
+        allocator.unlock(&second);
+        allocator.unlock(&second);
+
+        allocator.compact();
+        assert_ne!(allocator.lock(&second), ptr);
+    }
+
+    #[test]
+    fn handles_of_other_allocators_are_rejected() {
+        let allocator = Allocator::<256>::new();
+        let other = Allocator::<256>::new();
+        let layout = Layout::new::<u32>();
+        let handle = allocator.alloc_handle(layout).unwrap();
+        let foreign = other.alloc_handle(layout).unwrap();
+
+        // both handles use the first slot of their table
+        let ptr = allocator.lock(&handle);
+        assert!(allocator.lock(&foreign).is_null());
+        allocator.unlock(&foreign);
+        allocator.set_free_policy(FreePolicy::Record);
+        allocator.free_handle(foreign);
+        let log = allocator.free_error_log();
+        assert_eq!(log.last().unwrap().error, FreeError::InvalidHandle);
+        allocator.compact();
+        assert_eq!(allocator.lock(&handle), ptr);
+        allocator.unlock(&handle);
+        allocator.unlock(&handle);
+
+        // the tag is kept, if the allocator is moved
+        let moved = allocator;
+        assert!(!moved.lock(&handle).is_null());
+        moved.unlock(&handle);
+        moved.free_handle(handle);
+    }
+
+    #[test]
+    fn red_zone_violations_report_the_block() {
+        let allocator = Allocator::<256>::new();
+        allocator.set_free_policy(FreePolicy::Record);
+        assert!(allocator.enable_red_zones());
+        let handle = allocator.alloc_handle(Layout::new::<u32>()).unwrap();
+
+        let ptr = allocator.lock(&handle);
+        unsafe { ptr.add(4).write(0) }; // a buffer overflow
+        allocator.unlock(&handle);
+        allocator.free_handle(handle);
+
+        let invalid_free = allocator.free_error_log().last().unwrap();
+        assert_eq!(invalid_free.address, ptr as usize);
+        assert_eq!(
+            invalid_free.error,
+            FreeError::RedZoneViolation {
+                address: ptr as usize,
+                size: 4
+            }
+        );
+    }
+
+    #[test]
+    fn larger_alignment_than_the_granule() {
+        let allocator = Allocator::<256>::new();
+        assert_eq!(
+            allocator.alloc_handle(Layout::from_size_align(8, 8).unwrap()),
+            None
+        );
+    }
+}
@@ -23,17 +23,98 @@
 //! normally provided by the `std`.
 //!
 //! Note, that the usable dynamic memory is less than the total heap size `N`
-//! due to the management of the individual allocations. Each allocation will
-//! require a constant memory overhead of _4 bytes_[^note-header-size]. This
-//! implies, that more allocations will result in less usable space in the heap.
-//! The minimal buffer size is `8`, which would allow exactly one allocation of
-//! size up to 4 at a time. Adjust the size as necessary, e.g. by doing a worst
-//! case calculation and potentially adding some backup space of 10% (for
-//! example).
+//! due to the management of the individual allocations. Each allocation
+//! requires a header, whose size depends on the [`Header`] format of the
+//! allocator (see [`Allocator`]):
+//! - [`Header32`] (the default) uses a constant memory overhead of _4 bytes_
+//!   per allocation[^note-header-size]. The size of each allocation is rounded
+//!   up to a multiple of 4.
+//! - [`Header16`] uses 2 bytes per allocation. The sizes are rounded up, so
+//!   that the header and the allocation fill a multiple of 4 bytes.
+//! - [`Header64`] uses 8 bytes per allocation. The sizes are rounded up to a
+//!   multiple of 8.
+//! - [`AutoHeader`] behaves like [`Header16`] for heaps of up to 64KiB and like
+//!   [`Header32`] for larger ones.
+//!
+//! A larger granule (e.g. `Header32<16>`) rounds the header and the allocation
+//! up to a multiple of the granule instead. Furthermore the heap contains a
+//! bitmap with a bit per granule (i.e. a 32nd of the heap by default), which
+//! is used for freeing. If red zones are enabled (see
+//! [`Allocator::enable_red_zones()`]), each allocation additionally needs two
+//! words of metadata and at least 16 guard bytes on each side, i.e. 40 bytes on
+//! 32-bit targets and 48 bytes on 64-bit targets (more for large alignments).
+//! This implies, that more allocations will result in less usable space in the
+//! heap. The minimal buffer size is `8`, which only allows
+//! allocations of size `0`: a single allocation of size up to 4 needs a buffer
+//! of 12 bytes with the default format. Adjust the size as necessary, e.g. by
+//! doing a worst case calculation and potentially adding some backup space of
+//! 10% (for example). The actual usage (including the peak usage) can be
+//! monitored at runtime via [`Allocator::stats()`] and the individual blocks of
+//! the heap can be inspected via [`Allocator::walk()`]. The consistency of the
+//! heap (e.g. after a suspected buffer overflow) can be verified via
+//! [`Allocator::check()`] or incrementally via [`Allocator::check_step()`].
+//! Invalid frees (e.g. double frees) are ignored by default, but can be
+//! recorded or reported instead, see [`Allocator::set_free_policy()`]. If the
+//! heap is exhausted, the program can free memory (e.g. caches) on demand via
+//! [`Allocator::register_reclaim()`], before the allocation fails. While
+//! debugging memory corruption, the allocations can be surrounded by guard
+//! bytes, which detect buffer overflows, see [`Allocator::enable_red_zones()`].
 //!
 //! [^note-header-size]: this value is critical for worst-case calculations and
-//! therefore part of the stability guarantees of this crate. Changing it will
-//! be a breaking change and thus requires a major version bump.
+//! therefore part of the stability guarantees of this crate for the default
+//! header format. Changing it will be a breaking change and thus requires a
+//! major version bump. The overhead of the other formats follows from their
+//! header size and granule, but is not guaranteed in the same way.
+//!
+//! The way the allocator chooses the memory for an allocation can be selected
+//! with the second type parameter of [`Allocator`], the placement [`Strategy`]:
+//! - [`BestFit`] (the default) uses the smallest free block, that is large
+//!   enough. It searches through all free blocks for each allocation.
+//! - [`FirstFit`] uses the free block with the lowest address, that is large
+//!   enough. The search stops at the first suitable block.
+//! - [`NextFit`] works like [`FirstFit`], but continues the search after the
+//!   previous allocation, wrapping around at the end of the heap.
+//! - [`WorstFit`] always uses the largest free block.
+//! - [`Tlsf`] ("Two-Level Segregated Fit") keeps the free blocks in many lists
+//!   sorted by size classes, so that a suitable block can be found in constant
+//!   time. Together with the constant-time lookup of a freed block (see below)
+//!   this bounds the worst-case execution time of allocating and freeing,
+//!   which is important for hard real-time systems. In exchange, each allocation
+//!   occupies at least 12 bytes plus the header and there are some additional
+//!   bookkeeping data (less than 2KiB on 32-bit targets, about 7.5KiB on
+//!   64-bit targets).
+//!
+//! Programs with lots of small allocations (e.g. `Box<Node>` or short
+//! `String`s) can put a [`Slab`] in front of the allocator. It serves requests
+//! of up to 64 bytes from pages dedicated to a few size classes, so that those
+//! don't need a header per allocation:
+//! ```no_run
+//! #[global_allocator]
+//! static ALLOCATOR: emballoc::Slab<emballoc::Allocator<4096>> =
+//!     emballoc::Slab::new(emballoc::Allocator::new());
+//! ```
+//!
+//! On a nightly compiler the `allocator_api` feature implements the unstable
+//! [`core::alloc::Allocator`]-trait for `&Allocator`. This allows to use
+//! dedicated heaps for individual collections (e.g. `Vec::new_in(&HEAP)` or
+//! `Box::new_in(value, &HEAP)`) in addition to the global allocator.
+//! The `allocator-api2` feature does the same for the `Allocator`-trait of the
+//! [`allocator-api2`](https://docs.rs/allocator-api2) crate, so that its
+//! collections can use dedicated heaps on stable compilers.
+//!
+//! Memory allocated via pointers can never be moved, so the heap might become
+//! fragmented over time. Long-lived buffers can be allocated via a [`Handle`]
+//! instead (see [`Allocator::alloc_handle()`]). The handle has to be locked to
+//! access the memory, but in return the allocator can move the memory of all
+//! unlocked handles, so that the fragmentation is repaired on demand with
+//! [`Allocator::compact()`].
+//!
+//! The heap of an [`Allocator`] is part of the allocator, so its size has to
+//! be known at compile time. If the heap should span memory, that is only
+//! known at runtime (e.g. the RAM between linker symbols), the
+//! [`RegionAllocator`] can be used instead. It is created empty and is
+//! initialized with a memory region early in the program. It can manage
+//! several disjoint regions and the heap can be extended at runtime.
 //!
 //! The allocator itself is thread-safe, as there is no potentially unsafe
 //! [`Cell<T>`]-action done in this crate. Instead it uses the popular [`spin`]
@@ -90,8 +171,11 @@
 //! different metrics per file.
 //!
 //! # Implementation
-//! This algorithm does a linear scan for free blocks. The basic algorithm is as
-//! follows:
+//! This algorithm does a linear scan for free blocks. To not visit the used
+//! blocks as well, all free blocks are connected in a list: each free block
+//! stores the position of the next free block in its memory (which is unused
+//! anyway). Therefore only the free blocks are scanned. The basic algorithm is
+//! as follows:
 //! 1.  We start with an empty buffer.
 //!     ```text
 //!     xxxx 0000 0000 0000 0000 0000 0000 0000
@@ -99,7 +183,8 @@
 //!     FREE size = 28
 //!     ```
 //!     There is a single entry, which spans all the remaining buffer bytes
-//!     (after the entry itself, which is always 4 bytes).
+//!     (after the entry itself, which is 4 bytes with the default header
+//!     format, and before the bitmap described below, which is left out here).
 //! 2.  A block of 8 is allocated.
 //!     ```text
 //!     xxxx 0000 0000 yyyy 0000 0000 0000 0000
@@ -188,20 +273,46 @@
 //!     This is just an intermediate step without any issues.
 //! 12. The remaining used block is freed.
 //!     ```text
-//!     xxxx 0000 0000 yyyy 0000 0000 0000 0000
-//!     ^--- ^-------- ^--- ^------------------
-//!     FREE size = 8  FREE size = 16
+//!     xxxx 0000 0000 0000 0000 0000 0000 0000
+//!     ^--- ^---------------------------------
+//!     FREE size = 28
 //!     ```
-//!     Now there are two(!) free blocks, since the concatenation described in
-//!     step 10 does only happen to the right side of the freed block. Since the
-//!     left block has an unknown size, it is not possible to find the header
-//!     (except for linearly scanning the memory from the beginning). Therefore
-//!     it is easier to just live with that fragmentation.
+//!     Now the freed block is surrounded by two free blocks, so all three are
+//!     connected into a single block, restoring the picture of step 1. The
+//!     connection to the right side works like in step 10. For the left side
+//!     the header of the preceding block has to be found, which has an unknown
+//!     size. Therefore each free block stores a copy of its header in its last
+//!     bytes (a so called _boundary tag_) and each header contains a flag,
+//!     whether the block in front of it is free. If that flag is set, the
+//!     header-sized bytes directly before the header contain the size of the
+//!     left block and thus its header can be found without linearly scanning
+//!     the memory from the beginning. The flag is stored in the header next to
+//!     the used/free state: since all sizes are multiples of 4, the lowest two
+//!     bits of the size are always zero and can be used for that.
+//!
+//!     This ensures, that the heap always returns to a single free block, once
+//!     all allocations are freed.
+//!
+//! When a block is freed, its header is not simply trusted: the bytes in front
+//! of the pointer might belong to an allocation and contain data, that looks
+//! like a header. Therefore the end of the heap memory holds a bitmap with a
+//! bit for each 4 bytes, which marks the offsets, where a block starts. Only
+//! a header marked in the bitmap is read, so freeing a pointer returned by the
+//! allocator takes constant time, while invalid and double frees are still
+//! detected reliably. The bitmap takes a 32nd of the heap memory (or less, if
+//! the blocks are multiples of a larger granule, see [`Allocator`]).
 //!
-//!     Something interesting here is, that one could check for such conditions
-//!     from time to time and fix them during that scan. Doing it this way does
-//!     not come with a constant time penalty when deallocating. Furthermore it
-//!     lets the user decide, whether that feature is necessary or not.
+//! Reallocations (e.g. a growing `Vec`) are done in place, if possible: when
+//! shrinking a block, its tail is split off as a new free block (or merged with
+//! the following free block). A growing block absorbs the following free block,
+//! if that is large enough. Only if this is not possible, a new block is
+//! allocated and the contents are copied. This way the memory of the old and
+//! the new allocation is not needed at the same time in most cases.
+//!
+//! The heap memory is zero initially. The allocator keeps track of the part of
+//! the heap, that was never written to (neither by a user of an allocation nor
+//! by the allocator itself). Zeroed allocations (e.g. `vec![0; n]`) only clear
+//! the bytes outside of that area, which saves time on startup.
 //!
 //! [alloc]: https://doc.rust-lang.org/alloc/index.html
 //! [gist_hosted-test]: https://gist.github.com/jfrimmel/61943f9879adfbe760a78efa17a0ecaa
@@ -209,14 +320,163 @@
 //! [codecov]: https://codecov.io/gh/jfrimmel/emballoc
 //! [ci-logs]: https://app.circleci.com/pipelines/github/jfrimmel/emballoc
 #![cfg_attr(not(test), no_std)]
+#![cfg_attr(feature = "allocator_api", feature(allocator_api))]
 #![warn(unsafe_op_in_unsafe_fn)]
 #![warn(clippy::undocumented_unsafe_blocks)]
 
+/// Implement the [`GlobalAlloc`](core::alloc::GlobalAlloc)-trait for an
+/// allocator type.
+///
+/// The allocator types only differ in the memory of their heap, so they all
+/// forward the requests to the raw allocator stored in their `raw` field. The
+/// invalid frees are handled by the policy stored in their `free_errors` field
+/// and failed allocations call the hooks stored in their `reclaimers` field.
+macro_rules! impl_global_alloc {
+    ([$($generics:tt)*] $allocator:ty) => {
+        // SAFETY: the safety contracts of global allocator is a bit lengthy,
+        // but in short: the implementation does not unwind and it actually
+        // adheres to the layout requirements (ensured by tests). It does not
+        // panic on purpose (if it would, there is a bug), except for the
+        // `FreePolicy::Panic` and panicking hooks: those are turned into an
+        // abort by an `AbortOnUnwind` guard.
+        unsafe impl<$($generics)*> core::alloc::GlobalAlloc for $allocator {
+            unsafe fn alloc(&self, layout: core::alloc::Layout) -> *mut u8 {
+                // allocate a suitably aligned memory block. The raw allocator
+                // places the block at an aligned address, so no over-allocation
+                // is necessary. If the heap is exhausted, the reclaim hooks
+                // may free some memory, so the allocation is retried.
+                $crate::reclaim::with_reclaim(&self.reclaimers, layout, || {
+                    let mut raw = self.raw.lock();
+                    match raw.alloc(layout.size(), layout.align()) {
+                        Some(memory) => Ok(memory.as_mut_ptr().cast()),
+                        None => Err(raw),
+                    }
+                })
+                .unwrap_or_else(|mut raw| {
+                    raw.record_failure();
+                    core::ptr::null_mut()
+                })
+            }
+
+            unsafe fn alloc_zeroed(&self, layout: core::alloc::Layout) -> *mut u8 {
+                // the raw allocator knows, which parts of the heap are still
+                // zero, so only the remaining bytes of the memory block are
+                // cleared.
+                $crate::reclaim::with_reclaim(&self.reclaimers, layout, || {
+                    let mut raw = self.raw.lock();
+                    match raw.alloc_zeroed(layout.size(), layout.align()) {
+                        Some(memory) => Ok(memory.as_mut_ptr().cast()),
+                        None => Err(raw),
+                    }
+                })
+                .unwrap_or_else(|mut raw| {
+                    raw.record_failure();
+                    core::ptr::null_mut()
+                })
+            }
+
+            unsafe fn dealloc(&self, ptr: *mut u8, layout: core::alloc::Layout) {
+                // alignment is irrelevant here, as `RawAllocator::free` can
+                // handle any pointer in an entry's memory, so simply forward
+                // the pointer. The `free()`-method might detect errors, which
+                // cannot be returned. Those are handled by the `FreePolicy`
+                // of the allocator (ignoring them by default). Note, that the
+                // lock is released before, so that a hook can use the
+                // allocator.
+                let result = self.raw.lock().free(ptr.cast());
+                if let Err(error) = result {
+                    $crate::free_error::handle(&self.free_errors, ptr, layout, error);
+                }
+            }
+
+            unsafe fn realloc(
+                &self,
+                ptr: *mut u8,
+                layout: core::alloc::Layout,
+                new_size: usize,
+            ) -> *mut u8 {
+                // try to shrink or grow the allocation in place first. The
+                // pointer does not change in that case, so the alignment is
+                // still fulfilled and no memory has to be copied. Note, that
+                // the lock is released afterwards.
+                let resized = self.raw.lock().resize(ptr, new_size);
+                if resized {
+                    return ptr;
+                }
+
+                // the allocation has to be moved. Allocating with the original
+                // alignment ensures, that the new pointer is suitably aligned
+                // as well.
+                // SAFETY: the caller guarantees, that `new_size` does not
+                // overflow when rounded up to the alignment, which is a power
+                // of two as it comes from the original layout.
+                let new_layout = unsafe {
+                    core::alloc::Layout::from_size_align_unchecked(new_size, layout.align())
+                };
+                // SAFETY: the caller guarantees, that `new_size` is greater
+                // than zero.
+                let new_ptr = unsafe { self.alloc(new_layout) };
+                if !new_ptr.is_null() {
+                    // SAFETY: both blocks are valid for the smaller of the two
+                    // sizes and the old block is still allocated, so they
+                    // cannot overlap.
+                    unsafe {
+                        core::ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size));
+                    }
+                    // SAFETY: the caller guarantees, that `ptr` was allocated
+                    // by this allocator with the given `layout`.
+                    unsafe { self.dealloc(ptr, layout) };
+                }
+                new_ptr
+            }
+        }
+    };
+}
+pub(crate) use impl_global_alloc;
+
+/// A guard, that aborts the program, if it is dropped while unwinding.
+///
+/// The allocator traits do not allow to unwind, so panics (e.g. of the hooks
+/// provided by the user) must not leave the allocator. The guard is created in
+/// front of the code, that might panic, and disarmed afterwards. If it is
+/// dropped due to a panic instead, it panics again, which aborts the program
+/// (a panic while panicking). With `panic = "abort"` the first panic aborts
+/// already.
+pub(crate) struct AbortOnUnwind;
+impl AbortOnUnwind {
+    /// Disarm the guard after the code, that might panic, returned normally.
+    pub(crate) const fn disarm(self) {
+        core::mem::forget(self);
+    }
+}
+impl Drop for AbortOnUnwind {
+    fn drop(&mut self) {
+        panic!("aborting, since the allocator must not unwind");
+    }
+}
+
+#[cfg(any(feature = "allocator_api", feature = "allocator-api2"))]
+mod allocator_api;
+mod free_error;
+mod handle;
 mod raw_allocator;
-use raw_allocator::RawAllocator;
+mod reclaim;
+mod region;
+mod slab;
+pub use free_error::{FreeErrorLog, FreeHook, FreePolicy, InvalidFree};
+pub use handle::Handle;
+pub use raw_allocator::{
+    AllocError, AutoHeader, BestFit, BlockInfo, BlockState, CheckError, FirstFit, FreeError,
+    Header, Header16, Header32, Header64, NextFit, Stats, Strategy, Tlsf, WorstFit,
+};
+use raw_allocator::{Array, RawAllocator};
+pub use reclaim::{ReclaimHook, MAX_RECLAIM_HOOKS};
+pub use region::RegionAllocator;
+pub use slab::{ClassUsage, Slab};
 
-use core::alloc::{GlobalAlloc, Layout};
-use core::ptr;
+use core::alloc::Layout;
+use core::mem::MaybeUninit;
+use core::ptr::NonNull;
 
 /// The memory allocator for embedded systems.
 ///
@@ -236,16 +496,66 @@ use core::ptr;
 /// ```
 /// Also please refer to the [crate-level](crate)-documentation for
 /// recommendations on the buffer size and general usage.
-pub struct Allocator<const N: usize> {
+///
+/// The second type parameter selects the placement [`Strategy`], which decides
+/// the free memory block used for an allocation. By default the [`BestFit`]
+/// strategy is used. Systems with hard real-time requirements should consider
+/// the [`Tlsf`] strategy, which allocates and frees memory in constant time
+/// (see its documentation for the exceptions):
+/// ```no_run
+/// #[global_allocator]
+/// static ALLOCATOR: emballoc::Allocator<4096, emballoc::Tlsf> = emballoc::Allocator::new();
+/// ```
+///
+/// The third type parameter selects the [`Header`] format of the memory blocks.
+/// The default [`Header32`] uses 4 bytes per block and limits the heap to 4GiB.
+/// Larger heaps (e.g. when simulating an embedded system on a 64-bit PC) need
+/// the [`Header64`] format, which uses 8 bytes per block:
+/// ```no_run
+/// use emballoc::{Allocator, BestFit, Header64};
+///
+/// #[global_allocator]
+/// static ALLOCATOR: Allocator<4096, BestFit, Header64> = Allocator::new();
+/// ```
+/// Heaps of up to 64KiB can use the compact [`Header16`] format instead, which
+/// saves two bytes per block. The [`AutoHeader`] format chooses the compact
+/// format automatically, if the heap is small enough:
+/// ```no_run
+/// use emballoc::{Allocator, AutoHeader, BestFit};
+///
+/// #[global_allocator]
+/// static ALLOCATOR: Allocator<4096, BestFit, AutoHeader> = Allocator::new();
+/// ```
+/// The header formats take the _granule_ as an optional parameter: all memory
+/// blocks including their header occupy a multiple of the granule and the
+/// memory of all blocks is aligned to it. The default granule is `4` (`8` for
+/// [`Header64`]). Allocations with a larger alignment need some padding in
+/// front of them, which costs time and memory. Therefore a granule of `8` or
+/// `16` is recommended, if most of the allocations have such an alignment, e.g.
+/// on 64-bit targets or if the allocated types contain `u64` or `f64` fields:
+/// ```no_run
+/// use emballoc::{Allocator, BestFit, Header32};
+///
+/// #[global_allocator]
+/// static ALLOCATOR: Allocator<4096, BestFit, Header32<8>> = Allocator::new();
+/// ```
+pub struct Allocator<const N: usize, S = BestFit, H: Header = Header32> {
     /// The internal raw allocator.
     ///
-    /// The raw allocator handles allocations of contiguous byte slices without
-    /// needing to worry about alignment. The raw allocator is protected by a
+    /// The raw allocator handles allocations of contiguous and aligned byte
+    /// slices without needing to worry about layouts. It is protected by a
     /// `spin::Mutex` to make it usable with shared references (requirement of
     /// [`GlobalAlloc`]).
-    raw: spin::Mutex<RawAllocator<N>>,
+    raw: spin::Mutex<RawAllocator<Array<N>, S, H>>,
+    /// The handling of invalid frees, see [`FreePolicy`].
+    ///
+    /// This has its own lock, so that the raw allocator is not locked while
+    /// reacting to an invalid free.
+    free_errors: spin::Mutex<free_error::FreeErrors>,
+    /// The hooks to reclaim memory, if an allocation fails.
+    reclaimers: spin::Mutex<reclaim::Reclaimers>,
 }
-impl<const N: usize> Allocator<N> {
+impl<const N: usize, S: Strategy, H: Header> Allocator<N, S, H> {
     /// Create a new [`Allocator`] with exactly `N` bytes heap space.
     ///
     /// Note, that the usable size is less than the heap size, since there is
@@ -277,7 +587,9 @@ impl<const N: usize> Allocator<N> {
     ///
     /// # Panics
     /// This function will panic, if the supplied buffer size, i.e. `N`, is less
-    /// than `8` or not divisible by `4`.
+    /// than `8` or not divisible by `4`. With the [`Header64`] format, the size
+    /// has to be at least `16` and divisible by `8`. The [`Header16`] format
+    /// supports sizes up to 64KiB only.
     /// ```should_panic
     /// emballoc::Allocator::<63>::new(); // not divisible by 4
     /// ```
@@ -288,70 +600,358 @@ impl<const N: usize> Allocator<N> {
     #[allow(clippy::new_without_default)] // this could be added, but not now
     pub const fn new() -> Self {
         let raw = spin::Mutex::new(RawAllocator::new());
-        Self { raw }
+        let free_errors = spin::Mutex::new(free_error::FreeErrors::NEW);
+        let reclaimers = spin::Mutex::new(reclaim::Reclaimers::NEW);
+        Self {
+            raw,
+            free_errors,
+            reclaimers,
+        }
     }
 
-    /// Align a given pointer to the specified alignment.
+    /// Allocate memory for the given layout or report, why this failed.
+    ///
+    /// This works like [`GlobalAlloc::alloc()`] (including the reclaiming of
+    /// memory, see [`register_reclaim()`]), but instead of a null pointer an
+    /// [`AllocError`] is returned on failure. It tells, whether the heap is
+    /// exhausted, too fragmented or whether the padding needed for the
+    /// alignment prevents the allocation. The error contains the total number
+    /// of free bytes and the size of the largest free block, so that the caller
+    /// can degrade gracefully, e.g. by using a smaller buffer.
+    ///
+    /// The returned memory is uninitialized and has (at least) the size of the
+    /// layout. It has to be freed via [`GlobalAlloc::dealloc()`].
+    ///
+    /// # Errors
+    /// The reason of the failure is returned, if the allocation fails.
     ///
-    /// # Safety
-    /// This function requires `align` to be a power of two and requires the
-    /// `ptr` to point to a memory region, that is large enough, so that the
-    /// aligned pointer is still in that memory region.
-    unsafe fn align_to(ptr: *mut u8, align: usize) -> *mut u8 {
-        let addr = ptr as usize;
-        let mismatch = addr & (align - 1);
-        let offset = if mismatch == 0 { 0 } else { align - mismatch };
-        // SAFETY: "in-bound"-requirement is part of the safety-contract of this
-        // function, therefore the caller is responsible for it
-        unsafe { ptr.add(offset) }
+    /// # Example
+    /// ```
+    /// use core::alloc::Layout;
+    /// use emballoc::AllocError;
+    ///
+    /// let allocator = emballoc::Allocator::<64>::new();
+    /// let layout = Layout::new::<[u8; 64]>();
+    /// let buffer = match allocator.try_alloc(layout) {
+    ///     Ok(buffer) => buffer,
+    ///     Err(AllocError::OutOfMemory { largest_free, .. }) => {
+    ///         // fall back to the largest possible buffer
+    ///         let layout = Layout::from_size_align(largest_free, 1).unwrap();
+    ///         allocator.try_alloc(layout).unwrap()
+    ///     }
+    ///     Err(error) => panic!("unexpected error: {error:?}"),
+    /// };
+    /// assert_eq!(buffer.len(), 56);
+    /// ```
+    ///
+    /// [`GlobalAlloc::alloc()`]: core::alloc::GlobalAlloc::alloc
+    /// [`GlobalAlloc::dealloc()`]: core::alloc::GlobalAlloc::dealloc
+    /// [`register_reclaim()`]: Self::register_reclaim
+    pub fn try_alloc(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let memory = reclaim::with_reclaim(&self.reclaimers, layout, || {
+            let mut raw = self.raw.lock();
+            match raw.alloc(layout.size(), layout.align()) {
+                Some(memory) => {
+                    NonNull::new(memory as *mut [MaybeUninit<u8>] as *mut [u8]).ok_or(raw)
+                }
+                None => Err(raw),
+            }
+        });
+        // the reason of the failure is determined under the lock of the last
+        // failed attempt, so that it is consistent with the failure
+        memory.map_err(|mut raw| {
+            raw.record_failure();
+            raw.alloc_error(layout.size(), layout.align())
+        })
+    }
+
+    /// Query the usage statistics of the heap.
+    ///
+    /// The statistics contain the current usage of the heap (e.g. the used and
+    /// free bytes and the largest free block) as well as values accumulated
+    /// over the lifetime of the allocator (e.g. the peak usage and the number
+    /// of failed allocations). See [`Stats`] for details. The current usage is
+    /// obtained by walking over all blocks, so this takes time proportional to
+    /// the number of blocks and should not be called in time-critical code.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<64>::new();
+    /// // SAFETY: the layout has a non-zero size
+    /// let ptr = unsafe { allocator.alloc(Layout::new::<[u32; 4]>()) };
+    ///
+    /// let stats = allocator.stats();
+    /// assert_eq!(stats.used, 16);
+    /// assert_eq!(stats.free, 36);
+    /// assert_eq!(stats.overhead, 12); // two headers and the bitmap
+    /// assert_eq!(stats.allocations, 1);
+    /// assert_eq!(stats.peak_used, 16);
+    /// ```
+    pub fn stats(&self) -> Stats {
+        self.raw.lock().stats()
+    }
+
+    /// Walk over all blocks of the heap.
+    ///
+    /// The given function is called with the [`BlockInfo`] of each block in
+    /// the order of their addresses. This allows to inspect the layout of the
+    /// heap, e.g. for diagnostics or in tests. The block holding the table of
+    /// handles is reported as [`BlockState::Internal`] and is not counted in
+    /// the used memory of the [`stats()`](Self::stats).
+    ///
+    /// The allocator is locked during the walk, so the function must not
+    /// allocate or free memory with this allocator. Doing so would deadlock,
+    /// which is easy to miss if this is the global allocator (e.g. when
+    /// formatting into a `String`).
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    /// use emballoc::BlockState;
+    ///
+    /// let allocator = emballoc::Allocator::<64>::new();
+    /// // SAFETY: the layout has a non-zero size
+    /// let ptr = unsafe { allocator.alloc(Layout::new::<[u32; 4]>()) };
+    ///
+    /// let mut blocks = [None; 4];
+    /// let mut count = 0;
+    /// allocator.walk(|block| {
+    ///     blocks[count] = Some((block.address, block.size, block.state));
+    ///     count += 1;
+    /// });
+    /// assert_eq!(count, 2);
+    /// assert_eq!(blocks[0], Some((ptr as usize, 16, BlockState::Used)));
+    /// assert_eq!(blocks[1], Some((ptr as usize + 20, 36, BlockState::Free)));
+    /// ```
+    pub fn walk(&self, f: impl FnMut(BlockInfo)) {
+        self.raw.lock().walk(f);
+    }
+
+    /// Check the consistency of the whole heap.
+    ///
+    /// The headers of all blocks are validated against each other: the blocks
+    /// have to stay inside of the heap, the last one has to end exactly at the
+    /// end of the heap and no two adjacent free blocks may remain unmerged.
+    /// This detects corrupted headers, e.g. if a buffer overflow of an
+    /// allocation overwrote the header of the following block. If red zones
+    /// are enabled (see [`enable_red_zones()`]), the guard bytes of all
+    /// allocations are verified as well. The first problem found is returned
+    /// as a [`CheckError`].
+    ///
+    /// The allocator is locked during the whole check, which takes time
+    /// proportional to the number of blocks. Use [`check_step()`] to split the
+    /// check into small steps instead.
+    ///
+    /// # Errors
+    /// The first inconsistency of the heap is returned, see [`CheckError`].
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    ///
+    /// let allocator = emballoc::Allocator::<64>::new();
+    /// // SAFETY: the layout has a non-zero size
+    /// let ptr = unsafe { allocator.alloc(Layout::new::<[u32; 4]>()) };
+    /// assert_eq!(allocator.check(), Ok(()));
+    /// ```
+    ///
+    /// [`check_step()`]: Self::check_step
+    /// [`enable_red_zones()`]: Self::enable_red_zones
+    pub fn check(&self) -> Result<(), CheckError> {
+        self.raw.lock().check()
+    }
+
+    /// Check the consistency of the next `count` blocks of the heap.
+    ///
+    /// This performs the same checks as [`check()`](Self::check), but only for
+    /// a bounded number of blocks, so that it can be called regularly (e.g.
+    /// from an idle loop) without blocking the allocator for long. Each call
+    /// continues with the block, where the previous call stopped, even if the
+    /// heap was modified in between. After the last block was checked, the
+    /// next call starts over at the first block. This is signaled by
+    /// returning `Ok(true)`, i.e. a whole pass over the heap was completed.
+    ///
+    /// # Errors
+    /// The first inconsistency of the checked blocks is returned, see
+    /// [`CheckError`]. The next call starts over at the first block.
+    ///
+    /// # Example
+    /// ```
+    /// let allocator = emballoc::Allocator::<4096>::new();
+    ///
+    /// // the idle loop of the program
+    /// loop {
+    ///     match allocator.check_step(4) {
+    ///         Ok(true) => break, // the whole heap is consistent
+    ///         Ok(false) => {}    // continue in the next iteration
+    ///         Err(error) => panic!("heap corrupted: {error:?}"),
+    ///     }
+    /// }
+    /// ```
+    pub fn check_step(&self, count: usize) -> Result<bool, CheckError> {
+        self.raw.lock().check_step(count)
+    }
+
+    /// Surround all future allocations with red zones to detect buffer
+    /// overflows.
+    ///
+    /// Each allocation is placed between guard bytes with a known pattern
+    /// (at least 16 bytes on each side), which are verified, when the
+    /// allocation is freed, by the heap checks ([`check()`] and
+    /// [`check_step()`]) and on demand via [`check_red_zone()`]. A violation
+    /// on free is handled by the [`FreePolicy`] as
+    /// [`FreeError::RedZoneViolation`] (the allocation is freed nevertheless),
+    /// the checks report a [`CheckError::RedZoneViolation`]. Both contain the
+    /// address and size of the allocation.
+    ///
+    /// The guard bytes and their metadata (two words holding the size and the
+    /// position of the allocation) need 40 additional bytes per allocation on
+    /// 32-bit targets and 48 bytes on 64-bit targets. The guard bytes in front
+    /// of the allocation grow, if its alignment requires it, and the size of
+    /// the whole block is rounded up to the granule as usual. Hence this is
+    /// meant for debugging and the heap has to be sized accordingly.
+    ///
+    /// The red zones can only be enabled before the first allocation, since
+    /// existing allocations cannot be guarded afterwards. Whether the red zones
+    /// are enabled is returned.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    /// use emballoc::CheckError;
+    ///
+    /// let allocator = emballoc::Allocator::<256>::new();
+    /// assert!(allocator.enable_red_zones());
+    ///
+    /// // SAFETY: the layout has a non-zero size
+    /// let ptr = unsafe { allocator.alloc(Layout::new::<[u8; 8]>()) };
+    /// // SAFETY: a buffer overflow, the byte is part of the red zone
+    /// unsafe { ptr.add(8).write(0) };
+    ///
+    /// let error = CheckError::RedZoneViolation {
+    ///     address: ptr as usize,
+    ///     size: 8,
+    /// };
+    /// assert_eq!(allocator.check_red_zone(ptr), Err(error));
+    /// assert_eq!(allocator.check(), Err(error));
+    /// ```
+    ///
+    /// [`check()`]: Self::check
+    /// [`check_step()`]: Self::check_step
+    /// [`check_red_zone()`]: Self::check_red_zone
+    pub fn enable_red_zones(&self) -> bool {
+        self.raw.lock().enable_red_zones()
+    }
+
+    /// Verify the red zones of the allocation containing `ptr`.
+    ///
+    /// If red zones are not enabled (see [`enable_red_zones()`]) or `ptr` does
+    /// not belong to an allocation, there is nothing to check and `Ok(())` is
+    /// returned.
+    ///
+    /// # Errors
+    /// If the guard bytes around the allocation were overwritten,
+    /// [`CheckError::RedZoneViolation`] is returned with the address and size
+    /// of the allocation.
+    ///
+    /// [`enable_red_zones()`]: Self::enable_red_zones
+    pub fn check_red_zone(&self, ptr: *const u8) -> Result<(), CheckError> {
+        self.raw.lock().check_red_zone(ptr)
+    }
+
+    /// Select the reaction to invalid frees.
+    ///
+    /// Freeing a pointer, that is not allocated (e.g. a double free), leaves
+    /// the heap unchanged. Since [`GlobalAlloc::dealloc()`] cannot report an
+    /// error, those are ignored by default. The [`FreePolicy`] allows to panic
+    /// (aborting the program), to record them into the [`free_error_log()`] or
+    /// to call a function instead.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    /// use emballoc::{FreeError, FreePolicy};
+    ///
+    /// let allocator = emballoc::Allocator::<64>::new();
+    /// allocator.set_free_policy(FreePolicy::Record);
+    ///
+    /// let layout = Layout::new::<u32>();
+    /// // SAFETY: the layout has a non-zero size
+    /// let ptr = unsafe { allocator.alloc(layout) };
+    /// // SAFETY: the first free is valid, the second one is detected
+    /// unsafe {
+    ///     allocator.dealloc(ptr, layout);
+    ///     allocator.dealloc(ptr, layout);
+    /// }
+    ///
+    /// let log = allocator.free_error_log();
+    /// assert_eq!(log.count(), 1);
+    /// assert_eq!(log.last().unwrap().address, ptr as usize);
+    /// assert_eq!(log.last().unwrap().error, FreeError::DoubleFreeDetected);
+    /// ```
+    ///
+    /// [`GlobalAlloc::dealloc()`]: core::alloc::GlobalAlloc::dealloc
+    /// [`free_error_log()`]: Self::free_error_log
+    pub fn set_free_policy(&self, policy: FreePolicy) {
+        self.free_errors.lock().set_policy(policy);
+    }
+
+    /// Query the log of the invalid frees recorded by the
+    /// [`FreePolicy::Record`] policy.
+    pub fn free_error_log(&self) -> FreeErrorLog {
+        self.free_errors.lock().log()
     }
-}
-// SAFETY: the safety contracts of global allocator is a bit lengthy, but in
-// short: the implementation does not panic (at least on purpose, if it would,
-// there is a bug) and it actually adheres to the layout requirements (ensured
-// by tests).
-unsafe impl<const N: usize> GlobalAlloc for Allocator<N> {
-    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
-        let align = layout.align();
-        // the raw allocator always returns 4-byte-aligned slices, therefore
-        // smaller alignments are always fulfilled. Larger alignments are a bit
-        // more tricky, since this requires over-allocation and adjusting the
-        // pointer accordingly. The over-allocation is rather conservative and
-        // uses a worst case estimation, therefore it allocates `align` bytes
-        // more, ensuring there is enough memory.
-        let size = if align > 4 {
-            layout.size() + align
-        } else {
-            layout.size()
-        };
 
-        // allocate a memory block and return the sufficiently aligned pointer
-        // into that memory block.
-        self.raw
-            .lock()
-            .alloc(size)
-            .map_or(ptr::null_mut(), |memory| {
-                // SAFETY: `align` is a power of two as by the contract of
-                // `Layout`. Furthermore the memory slice is enlarged (see
-                // above), so that the aligned pointer will still be in the same
-                // allocation.
-                unsafe { Self::align_to(ptr::addr_of_mut!(*memory).cast(), align) }
-            })
-    }
-
-    unsafe fn dealloc(&self, ptr: *mut u8, _layout: Layout) {
-        // alignment is irrelevant here, as `RawAllocator::free` can handle any
-        // pointer in an entry's memory, so simply forward the pointer. The
-        // `free()`-method might detect errors, but those cannot lead to panics
-        // (by contract of `GlobalAlloc`). Therefore there are two choices:
-        // 1. abort the process
-        // 2. ignore the error
-        // Since there is no process and there is no stable way to abort the
-        // program on `core` the only viable option is option #1: do nothing.
-        let _maybe_error = self.raw.lock().free(ptr.cast()).ok();
-        // errors are ignored
+    /// Register a hook to reclaim memory, if an allocation fails.
+    ///
+    /// If the heap cannot serve an allocation, the registered hooks are called
+    /// one after the other, so that they can free memory, which is not needed
+    /// strictly (e.g. caches). The allocation is retried after each hook, that
+    /// reports to have freed memory, so that only as many hooks as necessary
+    /// are called. The hooks with a higher `priority` are called first (hooks
+    /// with the same priority in the order of their registration), so the
+    /// memory, that is the cheapest to restore, should be reclaimed by the
+    /// hooks with the highest priority.
+    ///
+    /// The hooks are called without locking the allocator, so they can free
+    /// and even allocate memory. Allocations, that fail while the hooks are
+    /// called (e.g. from within a hook), fail immediately without calling the
+    /// hooks again. Up to [`MAX_RECLAIM_HOOKS`] hooks can be registered, if
+    /// all of them are in use, `false` is returned.
+    ///
+    /// # Example
+    /// ```
+    /// use core::alloc::{GlobalAlloc, Layout};
+    /// use core::sync::atomic::{AtomicPtr, Ordering};
+    ///
+    /// static ALLOCATOR: emballoc::Allocator<64> = emballoc::Allocator::new();
+    /// static CACHE: AtomicPtr<u8> = AtomicPtr::new(core::ptr::null_mut());
+    ///
+    /// let layout = Layout::new::<[u8; 40]>();
+    /// // SAFETY: the layout has a non-zero size
+    /// CACHE.store(unsafe { ALLOCATOR.alloc(layout) }, Ordering::Relaxed);
+    /// let registered = ALLOCATOR.register_reclaim(0, |_| {
+    ///     let cache = CACHE.swap(core::ptr::null_mut(), Ordering::Relaxed);
+    ///     // SAFETY: the cache was allocated with the same layout
+    ///     unsafe { ALLOCATOR.dealloc(cache, Layout::new::<[u8; 40]>()) };
+    ///     !cache.is_null()
+    /// });
+    /// assert!(registered);
+    ///
+    /// // the heap is too small for both, so the cache is dropped
+    /// // SAFETY: the layout has a non-zero size
+    /// let ptr = unsafe { ALLOCATOR.alloc(layout) };
+    /// assert!(!ptr.is_null());
+    /// assert!(CACHE.load(Ordering::Relaxed).is_null());
+    /// ```
+    #[must_use = "the hook is not registered, if too many hooks are registered already"]
+    pub fn register_reclaim(&self, priority: u8, hook: ReclaimHook) -> bool {
+        self.reclaimers.lock().register(priority, hook)
     }
 }
+impl_global_alloc!([const N: usize, S: Strategy, H: Header] Allocator<N, S, H>);
 
 // include the readme in doc-tests. Credits to https://blog.guillaume-gomez.fr/articles/2020-03-07+cfg%28doctest%29+is+stable+and+you+should+use+it
 #[cfg(doctest)]
@@ -368,37 +968,12 @@ mod extra_doctests {
 }
 
 #[cfg(test)]
+#[allow(clippy::undocumented_unsafe_blocks)] // the tests use the `unsafe` allocator API
 mod tests {
-    use crate::Allocator;
+    use crate::{Allocator, FreeError, FreePolicy, InvalidFree};
     use core::alloc::{GlobalAlloc, Layout};
     use core::ptr;
-
-    #[test]
-    fn alignment_of_align_to() {
-        // create buffer memory for proper indexing. One could use random
-        // integers and cast them to pointers, but this would violate the strict
-        // provenance rules and `miri` would detect that. Therefore this uses a
-        // valid and suitable aligned buffer and uses pointers into that buffer.
-        #[repr(align(16))]
-        struct Align([u8; 16]);
-        let mut just_a_buffer_to_get_a_valid_address = Align([0_u8; 16]);
-        let base: *mut u8 = ptr::addr_of_mut!(just_a_buffer_to_get_a_valid_address.0).cast();
-
-        // create some pointers to the buffer with some offsets
-        let ptr_0x10 = base;
-        let ptr_0x11 = base.wrapping_add(1);
-        let ptr_0x14 = base.wrapping_add(4);
-        let ptr_0x1c = base.wrapping_add(0xc);
-        let ptr_0x20 = base.wrapping_add(0x10);
-
-        // the actual test for the alignment of `align_to()`
-        assert_eq!(unsafe { Allocator::<8>::align_to(ptr_0x11, 4) }, ptr_0x14);
-        assert_eq!(unsafe { Allocator::<8>::align_to(ptr_0x10, 4) }, ptr_0x10);
-
-        assert_eq!(unsafe { Allocator::<8>::align_to(ptr_0x11, 1) }, ptr_0x11);
-
-        assert_eq!(unsafe { Allocator::<8>::align_to(ptr_0x1c, 16) }, ptr_0x20);
-    }
+    use core::sync::atomic::{AtomicUsize, Ordering};
 
     // the following tests ensure, that a pointer with the requested alignment
     // is returned
@@ -415,7 +990,7 @@ mod tests {
         let allocator = Allocator::<128>::new();
 
         let ptr = unsafe { allocator.alloc(Layout::from_size_align(8, 2).unwrap()) };
-        assert_alignment!(ptr, 1);
+        assert_alignment!(ptr, 2);
 
         let ptr = unsafe { allocator.alloc(Layout::from_size_align(4, 4).unwrap()) };
         assert_alignment!(ptr, 4);
@@ -442,6 +1017,11 @@ mod tests {
         let ptr = unsafe { ALLOCATOR.alloc(Layout::from_size_align(4, FOUR_MEG).unwrap()) };
 
         assert_alignment!(ptr, FOUR_MEG);
+
+        // only the needed padding is used, so there is room for another one
+        let ptr = unsafe { ALLOCATOR.alloc(Layout::from_size_align(4, FOUR_MEG).unwrap()) };
+        assert_ne!(ptr, ptr::null_mut());
+        assert_alignment!(ptr, FOUR_MEG);
     }
 
     #[test]
@@ -455,13 +1035,193 @@ mod tests {
 
     #[test]
     fn allocation_failure_due_to_alignment() {
-        let allocator = Allocator::<128>::new();
+        // place the allocator at a known alignment, so that there is no
+        // address with the requested alignment inside of the heap memory: the
+        // only such address inside of the allocator is its start, which is in
+        // front of the first block regardless of the field order.
+        #[repr(align(4096))]
+        struct Aligned(Allocator<128>);
+        let allocator = Aligned(Allocator::new());
 
-        // try an allocation, that exceeds the total memory size
-        let ptr = unsafe { allocator.alloc(Layout::from_size_align(8, 128).unwrap()) };
+        let ptr = unsafe { allocator.0.alloc(Layout::from_size_align(8, 4096).unwrap()) };
         assert_eq!(ptr, ptr::null_mut());
     }
 
+    #[test]
+    fn alignment_padding_is_reused() {
+        let allocator = Allocator::<256>::new();
+
+        unsafe {
+            let layout = Layout::from_size_align(64, 64).unwrap();
+            let ptr = allocator.alloc(layout);
+            assert_alignment!(ptr, 64);
+
+            // the padding in front of the aligned allocation and the memory
+            // after it can be used for other allocations, so that the heap can
+            // be filled up almost completely
+            let small = Layout::from_size_align(4, 4).unwrap();
+            let count = (0..)
+                .map(|_| allocator.alloc(small))
+                .take_while(|ptr| !ptr.is_null())
+                .count();
+            // 256 bytes minus the aligned block and its header, each small
+            // allocation needs 8 bytes including the header.
+            assert!(count >= (256 - 64 - 4) / 8 - 1);
+        }
+    }
+
+    #[test]
+    fn realloc_in_place() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::from_size_align(8, 4).unwrap();
+
+        unsafe {
+            let ptr = allocator.alloc(layout);
+            ptr.write_bytes(0xAB, 8);
+
+            // there is free memory after the allocation, so it can grow in place
+            let grown = allocator.realloc(ptr, layout, 64);
+            assert_eq!(grown, ptr);
+            assert_eq!(*grown.add(7), 0xAB);
+
+            // shrinking is always possible in place
+            let layout = Layout::from_size_align(64, 4).unwrap();
+            let shrunk = allocator.realloc(grown, layout, 4);
+            assert_eq!(shrunk, ptr);
+            assert_eq!(*shrunk.add(3), 0xAB);
+
+            // the split off memory is available again
+            let ptr = allocator.alloc(Layout::from_size_align(100, 4).unwrap());
+            assert_ne!(ptr, ptr::null_mut());
+        }
+    }
+
+    #[test]
+    fn realloc_moves_and_keeps_alignment() {
+        let allocator = Allocator::<256>::new();
+        let layout = Layout::from_size_align(16, 16).unwrap();
+
+        unsafe {
+            let ptr = allocator.alloc(layout);
+            assert_alignment!(ptr, 16);
+            for i in 0..16 {
+                ptr.add(i).write(i as u8);
+            }
+
+            // block the memory after the first allocation, so that it has to be
+            // moved on growing. The blocker is too large for the padding in
+            // front of the first allocation.
+            let blocker = allocator.alloc(Layout::from_size_align(32, 4).unwrap());
+            assert_ne!(blocker, ptr::null_mut());
+
+            let moved = allocator.realloc(ptr, layout, 64);
+            assert_ne!(moved, ptr::null_mut());
+            assert_ne!(moved, ptr);
+            assert_alignment!(moved, 16);
+            for i in 0..16 {
+                assert_eq!(*moved.add(i), i as u8);
+            }
+        }
+    }
+
+    #[test]
+    fn realloc_failure_keeps_allocation() {
+        let allocator = Allocator::<64>::new();
+        let layout = Layout::from_size_align(8, 4).unwrap();
+
+        unsafe {
+            let ptr = allocator.alloc(layout);
+            ptr.write_bytes(0xCD, 8);
+            assert_eq!(allocator.realloc(ptr, layout, 128), ptr::null_mut());
+            assert_eq!(*ptr.add(7), 0xCD);
+            allocator.dealloc(ptr, layout);
+        }
+    }
+
+    #[test]
+    fn alloc_zeroed_clears_reused_memory() {
+        let allocator = Allocator::<256>::new();
+        let layout = Layout::from_size_align(64, 16).unwrap();
+
+        unsafe {
+            let ptr = allocator.alloc(layout);
+            ptr.write_bytes(0xFF, 64);
+            allocator.dealloc(ptr, layout);
+
+            let ptr = allocator.alloc_zeroed(layout);
+            assert_ne!(ptr, ptr::null_mut());
+            assert_alignment!(ptr, 16);
+            assert!((0..64).all(|i| *ptr.add(i) == 0));
+
+            // the untouched memory at the end of the heap is zero as well
+            let layout = Layout::from_size_align(100, 4).unwrap();
+            let ptr = allocator.alloc_zeroed(layout);
+            assert_ne!(ptr, ptr::null_mut());
+            assert!((0..100).all(|i| *ptr.add(i) == 0));
+        }
+    }
+
+    #[test]
+    fn wide_headers() {
+        let allocator = Allocator::<256, crate::BestFit, crate::Header64>::new();
+        let layout = Layout::from_size_align(20, 16).unwrap();
+
+        unsafe {
+            let ptr = allocator.alloc(layout);
+            assert_ne!(ptr, ptr::null_mut());
+            assert_alignment!(ptr, 16);
+            ptr.write_bytes(0xAB, 20);
+
+            let ptr = allocator.realloc(ptr, layout, 100);
+            assert_ne!(ptr, ptr::null_mut());
+            assert!((0..20).all(|i| *ptr.add(i) == 0xAB));
+            allocator.dealloc(ptr, Layout::from_size_align(100, 16).unwrap());
+        }
+    }
+
+    #[test]
+    fn compact_headers() {
+        let allocator = Allocator::<256, crate::BestFit, crate::Header16>::new();
+
+        unsafe {
+            let mut ptrs = [ptr::null_mut(); 8];
+            for (size, ptr) in (1..).zip(&mut ptrs) {
+                let layout = Layout::from_size_align(size * 3, 4).unwrap();
+                *ptr = allocator.alloc(layout);
+                assert_ne!(*ptr, ptr::null_mut());
+                assert_alignment!(*ptr, 4);
+                ptr.write_bytes(0xAB, size * 3);
+            }
+            for (size, &ptr) in (1..).zip(&ptrs).step_by(2) {
+                allocator.dealloc(ptr, Layout::from_size_align(size * 3, 4).unwrap());
+            }
+
+            let layout = Layout::from_size_align(24, 4).unwrap();
+            let ptr = allocator.realloc(ptrs[1], layout, 40);
+            assert_ne!(ptr, ptr::null_mut());
+            assert!((0..6).all(|i| *ptr.add(i) == 0xAB));
+        }
+    }
+
+    #[test]
+    fn granule_avoids_padding() {
+        // each allocation occupies exactly 16 bytes including its header, so
+        // that 15 of them fit into the heap (the first 4 bytes are not usable
+        // with a granule of 8 and the last 12 bytes hold the bitmap of block
+        // starts)
+        let allocator = Allocator::<256, crate::BestFit, crate::Header32<8>>::new();
+        let layout = Layout::new::<u64>();
+
+        unsafe {
+            for _ in 0..15 {
+                let ptr = allocator.alloc(layout);
+                assert_ne!(ptr, ptr::null_mut());
+                assert_alignment!(ptr, 8);
+            }
+            assert_eq!(allocator.alloc(layout), ptr::null_mut());
+        }
+    }
+
     #[test]
     fn example_usage() {
         // do some example allocations. There is an intermediate deallocation,
@@ -498,4 +1258,127 @@ mod tests {
             ALLOCATOR.dealloc(ptr1, layout1);
         }
     }
+
+    #[test]
+    fn free_hook_can_use_the_allocator() {
+        static ALLOCATOR: Allocator<128> = Allocator::new();
+        static LEAKED: AtomicUsize = AtomicUsize::new(0);
+        ALLOCATOR.set_free_policy(FreePolicy::Hook(|ptr, layout, error| {
+            assert_eq!(error, FreeError::AllocationNotFound);
+            assert_eq!(layout, Layout::new::<u64>());
+            // the allocator is not locked while the hook is called
+            let leaked = unsafe { ALLOCATOR.alloc(Layout::new::<u32>()) };
+            assert_ne!(leaked, ptr::null_mut());
+            LEAKED.store(ptr as usize, Ordering::Relaxed);
+        }));
+
+        let mut invalid = 0_u64;
+        let invalid = ptr::addr_of_mut!(invalid).cast::<u8>();
+        unsafe { ALLOCATOR.dealloc(invalid, Layout::new::<u64>()) };
+        assert_eq!(LEAKED.load(Ordering::Relaxed), invalid as usize);
+    }
+
+    #[test]
+    fn reclaim_hooks_can_use_the_allocator() {
+        static ALLOCATOR: Allocator<64> = Allocator::new();
+        static CACHE: AtomicUsize = AtomicUsize::new(0);
+        let large = Layout::new::<[u8; 32]>();
+        let cache = unsafe { ALLOCATOR.alloc(large) };
+        CACHE.store(cache as usize, Ordering::Relaxed);
+
+        // the hook with the higher priority tries a (failing) allocation
+        // itself, which must neither deadlock nor call the hooks recursively.
+        let registered = ALLOCATOR.register_reclaim(1, |layout| {
+            assert!(unsafe { ALLOCATOR.alloc(layout) }.is_null());
+            false
+        });
+        assert!(registered);
+        let registered = ALLOCATOR.register_reclaim(0, |_| {
+            let cache = CACHE.swap(0, Ordering::Relaxed) as *mut u8;
+            unsafe { ALLOCATOR.dealloc(cache, Layout::new::<[u8; 32]>()) };
+            !cache.is_null()
+        });
+        assert!(registered);
+
+        let ptr = unsafe { ALLOCATOR.alloc_zeroed(large) };
+        assert_eq!(ptr, cache);
+        assert_eq!(CACHE.load(Ordering::Relaxed), 0);
+        // there is nothing left to reclaim
+        assert!(unsafe { ALLOCATOR.alloc(large) }.is_null());
+    }
+
+    #[test]
+    fn failed_requests_are_counted_once() {
+        static ALLOCATOR: Allocator<64> = Allocator::new();
+        static CACHE: AtomicUsize = AtomicUsize::new(0);
+        let large = Layout::new::<[u8; 32]>();
+        CACHE.store(
+            unsafe { ALLOCATOR.alloc(large) } as usize,
+            Ordering::Relaxed,
+        );
+        let registered = ALLOCATOR.register_reclaim(0, |_| {
+            let cache = CACHE.swap(0, Ordering::Relaxed) as *mut u8;
+            unsafe { ALLOCATOR.dealloc(cache, Layout::new::<[u8; 32]>()) };
+            !cache.is_null()
+        });
+        assert!(registered);
+
+        // the allocation succeeds after reclaiming memory, so it did not fail
+        let ptr = unsafe { ALLOCATOR.alloc(large) };
+        assert!(!ptr.is_null());
+        assert_eq!(ALLOCATOR.stats().failed_allocations, 0);
+
+        assert!(unsafe { ALLOCATOR.alloc(large) }.is_null());
+        assert!(unsafe { ALLOCATOR.realloc(ptr, large, 64) }.is_null());
+        assert!(ALLOCATOR.try_alloc(large).is_err());
+        assert_eq!(ALLOCATOR.alloc_handle(large), None);
+        assert_eq!(ALLOCATOR.stats().failed_allocations, 4);
+    }
+
+    #[test]
+    fn invalid_frees_are_ignored_by_default() {
+        let allocator = Allocator::<128>::new();
+        let layout = Layout::new::<u32>();
+        let ptr = unsafe { allocator.alloc(layout) };
+        unsafe {
+            allocator.dealloc(ptr, layout);
+            allocator.dealloc(ptr, layout);
+        }
+        assert_eq!(allocator.free_error_log().count(), 0);
+
+        allocator.set_free_policy(FreePolicy::Record);
+        unsafe { allocator.dealloc(ptr, layout) };
+        allocator.set_free_policy(FreePolicy::Ignore);
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert_eq!(allocator.free_error_log().count(), 1);
+    }
+
+    #[test]
+    fn red_zone_violations_are_reported_on_dealloc() {
+        let allocator = Allocator::<256>::new();
+        assert!(allocator.enable_red_zones());
+        allocator.set_free_policy(FreePolicy::Record);
+
+        // the reallocation keeps the red zones in place
+        let layout = Layout::new::<[u8; 8]>();
+        let ptr = unsafe { allocator.alloc(layout) };
+        let ptr = unsafe { allocator.realloc(ptr, layout, 24) };
+        let layout = Layout::new::<[u8; 24]>();
+        unsafe { ptr.write_bytes(1, 24) };
+        assert_eq!(allocator.check(), Ok(()));
+
+        unsafe { ptr.add(24).write(0) };
+        unsafe { allocator.dealloc(ptr, layout) };
+        let expected = InvalidFree {
+            address: ptr as usize,
+            size: 24,
+            align: 1,
+            error: FreeError::RedZoneViolation {
+                address: ptr as usize,
+                size: 24,
+            },
+        };
+        assert_eq!(allocator.free_error_log().last(), Some(expected));
+        assert_eq!(allocator.check(), Ok(()));
+    }
 }
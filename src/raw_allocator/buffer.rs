@@ -2,145 +2,391 @@
 //!
 //! This module tries to encapsulate all the low-level details on working with
 //! uninitialized heap memory, alignment into that buffer and reading/writing
-//! [`Entry`]s.
+//! [`Entry`]s in the selected [`Header`] format.
+use super::check::CheckError;
 use super::entry::{Entry, State};
+use super::header::{header_size, sealed::Sealed, Header, Header32};
+use super::memory::{Array, Memory, Region};
 
-use core::mem::{self, MaybeUninit};
+use core::marker::PhantomData;
+use core::mem::MaybeUninit;
 
-/// The size of a single block header.
-pub const HEADER_SIZE: usize = mem::size_of::<Entry>();
+/// Marker bit for free-list-links stored in place of a boundary tag.
+///
+/// Free blocks with a size of a single header only have a single word of
+/// memory, which is needed for both the boundary tag and the link to the next
+/// free block. Therefore those blocks store the link with the lowest bit set
+/// instead of the boundary tag. A real boundary tag never has that bit set,
+/// since it is a free entry, so the two can be told apart. The size of such a
+/// block is implicitly the header size.
+const SMALL_BLOCK_LINK: usize = 0b1;
 
 /// An offset into the [`Buffer`], that is validated and known to be safe.
 ///
 /// See [`EntryIter`] for details on the idea and necessity of this type.
-#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
 pub struct ValidatedOffset(usize);
 
+/// A compact reference to an optional entry, e.g. for linking free entries.
+///
+/// The link stores the offset of the entry plus [`Link::BIAS`] (which is the
+/// offset of the _memory_ of the entry with the default header format). This
+/// ensures, that `0` is never a valid link and can therefore be used to
+/// represent "no entry". This is important, since all the data structures need
+/// to be zero-initialized, so that a freshly created allocator is placed in the
+/// `.bss`-section. Like a [`ValidatedOffset`], a link can only be created from
+/// a valid offset (or be empty).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Link(usize);
+impl Link {
+    /// The link to no entry at all.
+    pub const NONE: Self = Self(0);
+
+    /// The value added to the offset of an entry.
+    ///
+    /// Since entry offsets are multiples of the header size (at least 2), the
+    /// lowest bit of a link is always clear, which is required for the links
+    /// stored in small free blocks (see [`SMALL_BLOCK_LINK`]).
+    const BIAS: usize = 4;
+
+    /// Create a new link to the given entry or an empty link.
+    pub fn new(offset: Option<ValidatedOffset>) -> Self {
+        Self(offset.map_or(0, |offset| offset.0 + Self::BIAS))
+    }
+
+    /// Query the entry, that is referenced by this link.
+    pub const fn get(self) -> Option<ValidatedOffset> {
+        if self.0 == 0 {
+            None
+        } else {
+            Some(ValidatedOffset(self.0 - Self::BIAS))
+        }
+    }
+}
+
 /// The buffer memory backing the heap.
-#[repr(align(4))]
-pub struct Buffer<const N: usize>([MaybeUninit<u8>; N]);
-impl<const N: usize> Buffer<N> {
-    /// Create a new buffer.
+///
+/// The entries, boundary tags and links are stored in the format given by the
+/// [`Header`] `H`, i.e. each of them occupies [`Self::HEADER_SIZE`](Self::HEADER_SIZE)
+/// bytes. Each block (the header and its memory) occupies a multiple of the
+/// [`Self::GRANULE`](Self::GRANULE), so that the memory of all blocks is
+/// aligned to the granule. If the header is smaller than the granule, the
+/// first header is placed at [`Self::START`](Self::START) instead of the start
+/// of the buffer.
+///
+/// The end of the memory holds a bitmap with a bit for each granule, which
+/// marks the offsets, where an entry starts (see [`entry_of()`]). The blocks
+/// end in front of the bitmap, so that it cannot be changed by writing into
+/// an allocation (except for a buffer overflow of the last block).
+///
+/// [`entry_of()`]: Self::entry_of
+#[repr(C, align(4))]
+pub struct Buffer<M, H: Header = Header32> {
+    /// The actual heap memory.
     ///
-    /// This buffer will be uninitialized except for the first few bytes: the
-    /// firs `HEADER_SIZE` bytes are initialized to zero. The caller must
-    /// subsequently call [`Buffer::ensure_initialization()`] to initialize the
-    /// first bytes as the header. This header is a free [`Entry`] with the size
-    /// of the remaining buffer.
+    /// This has to be the first field in order to be aligned, if the memory is
+    /// owned by the buffer.
+    memory: M,
+    /// The offset of the memory, that was never written to.
     ///
-    /// # Panics
-    /// This function panics if the buffer is less than 4 bytes in size, i.e. if
-    /// `N < 4`.
-    pub const fn new() -> Self {
-        assert!(N >= HEADER_SIZE, "buffer too small, use N >= 4");
-        assert!(N % HEADER_SIZE == 0, "memory size has to be divisible by 4");
+    /// All bytes starting at this offset are still zero (as initialized in
+    /// [`Buffer::new()`]), except for the last header-sized bytes of the
+    /// buffer: those contain the boundary tag of the last entry, if that is
+    /// free. Writing that boundary tag therefore does not advance this offset,
+    /// otherwise the whole buffer would be touched after the initialization.
+    untouched: usize,
+    /// The offset of the next entry to check in [`check_step()`].
+    ///
+    /// The offset `0` stands for the first entry, so that the cursor can be
+    /// zero-initialized (the first entry is at [`Self::START`](Self::START),
+    /// which might be `0` as well). Whenever a header is written, that spans
+    /// over the cursor, the cursor is moved back to that header, so that it
+    /// always refers to an entry (see [`write_entry()`]).
+    ///
+    /// [`check_step()`]: Self::check_step
+    /// [`write_entry()`]: Self::write_entry
+    check_cursor: usize,
+    /// The offset of the last entry.
+    ///
+    /// An offset below [`Self::START`](Self::START) stands for the first
+    /// entry, so that the offset can be zero-initialized. It is updated
+    /// whenever a header is written, whose block ends at the end of the
+    /// entries (see [`write_entry()`]).
+    ///
+    /// [`write_entry()`]: Self::write_entry
+    last: usize,
+    /// The format of the headers stored in the memory.
+    header: PhantomData<H>,
+    /// Zero-sized field raising the alignment of the buffer to the alignment
+    /// required by the header format.
+    alignment: [<H as Sealed>::Alignment; 0],
+}
+impl<M: Memory, H: Header> Buffer<M, H> {
+    /// The size of a single block header.
+    pub const HEADER_SIZE: usize = header_size::<H>(M::SIZE);
+
+    /// The size, of which all blocks including their header are a multiple.
+    ///
+    /// This is given by the header format and is at least the header size and
+    /// at least `4`.
+    pub const GRANULE: usize = H::GRANULE;
+
+    /// The offset of the first entry.
+    ///
+    /// The memory of the first entry (and thus of all entries) starts at a
+    /// multiple of the granule.
+    pub const START: usize = Self::GRANULE - Self::HEADER_SIZE;
+
+    /// The value added to the block sizes in the raw form of an [`Entry`].
+    ///
+    /// This is necessary for headers smaller than `4`, see [`Entry`] for
+    /// details.
+    const SIZE_BIAS: usize = Self::HEADER_SIZE % 4;
+
+    /// The alignment of the memory of all entries.
+    ///
+    /// The buffer itself is aligned to (at least) the granule and the memory
+    /// of all entries starts at multiples of the granule.
+    pub const ALIGNMENT: usize = Self::GRANULE;
+
+    /// The offset of the end of the last entry.
+    ///
+    /// The bytes after the last entry up to the bitmap of entry starts are
+    /// unused, so that the buffer is split into a whole number of granules.
+    pub fn end(&self) -> usize {
+        self.bitmap_start().saturating_sub(Self::START)
+    }
+
+    /// Compute the size of the bitmap of entry starts in a memory of `size`
+    /// bytes.
+    ///
+    /// The bitmap has a bit for each granule of the memory and its size is
+    /// rounded up to a multiple of the granule.
+    const fn bitmap_size(size: usize) -> usize {
+        let bytes = (size / Self::GRANULE + 7) / 8;
+        (bytes + Self::GRANULE - 1) / Self::GRANULE * Self::GRANULE
+    }
+
+    /// Query the offset of the bitmap of entry starts, which fills the end of
+    /// the memory.
+    fn bitmap_start(&self) -> usize {
+        let size = self.memory.bytes().len();
+        size - Self::bitmap_size(size)
+    }
+
+    /// Query the byte and the bit of the bitmap, that belong to the entry
+    /// offset `offset`.
+    ///
+    /// If `offset` cannot be the offset of an entry (it is not a multiple of
+    /// the granule after [`Self::START`](Self::START) or not inside of the
+    /// entries), `None` is returned.
+    fn bit_of(&self, offset: usize) -> Option<(usize, u8)> {
+        let index = offset.checked_sub(Self::START)?;
+        if index % Self::GRANULE != 0 || offset + Self::HEADER_SIZE >= self.end() {
+            return None;
+        }
+        let index = index / Self::GRANULE;
+        Some((self.bitmap_start() + index / 8, 1 << (index % 8)))
+    }
 
-        // initialize the header bytes to zero to make sure, that we can check
-        // against zero later without undefined behavior.
-        let mut buffer = [MaybeUninit::uninit(); N];
-        buffer[0] = MaybeUninit::new(0x00);
-        buffer[1] = MaybeUninit::new(0x00);
-        buffer[2] = MaybeUninit::new(0x00);
-        buffer[3] = MaybeUninit::new(0x00);
-        Self(buffer)
+    /// Check, whether an entry starts at the given offset.
+    ///
+    /// This only reads the bitmap of entry starts, so that a header-like word
+    /// in the memory of an entry is not mistaken for an entry.
+    fn is_entry(&self, offset: usize) -> bool {
+        self.bit_of(offset).map_or(false, |(byte, bit)| {
+            // SAFETY: the bitmap is zeroed on creation and is only written by
+            // the buffer, unless it was overwritten by a buffer overflow of the
+            // last entry, which is undefined behavior of the user anyway.
+            let byte = unsafe { self.memory.bytes()[byte].assume_init() };
+            byte & bit != 0
+        })
+    }
+
+    /// Mark the given offset as the start of an entry or clear that mark.
+    fn mark_entry(&mut self, offset: usize, is_entry: bool) {
+        if let Some((byte, bit)) = self.bit_of(offset) {
+            let byte = &mut self.memory.bytes_mut()[byte];
+            // SAFETY: see `is_entry()`
+            let value = unsafe { byte.assume_init() };
+            *byte = MaybeUninit::new(if is_entry { value | bit } else { value & !bit });
+        }
+    }
+
+    /// Check, whether the sizes and links of a buffer with the given size can
+    /// be stored in the header format.
+    const fn fits_header(size: usize) -> bool {
+        // both the raw sizes and the links are at most the end offset minus a
+        // header (plus the bias).
+        let size_bits = 8 * Self::HEADER_SIZE;
+        let largest_word = size - Self::START - Self::HEADER_SIZE + Self::SIZE_BIAS;
+        size_bits >= usize::BITS as usize || largest_word >> size_bits == 0
     }
 
     /// Ensure, that the buffer is initialized.
     ///
     /// This separate step is necessary to make sure, that the buffer is not
     /// initialized with non-zero data in the binary (i.e when calling `new()`).
-    pub fn ensure_initialization(&mut self) {
-        let buffer = &mut self.0;
-        let not_yet_initialized = buffer
-            .iter_mut()
-            .take(HEADER_SIZE)
-            // SAFETY: the bytes of the first entry are initialized in `new()`
-            .map(|byte| unsafe { byte.assume_init() })
-            .all(|byte| byte == 0x00);
+    ///
+    /// If the buffer was initialized by this call, the offset of the initial
+    /// free entry is returned, so that it can be registered as free memory.
+    pub fn ensure_initialization(&mut self) -> Option<ValidatedOffset> {
+        // the first header is touched by the initialization. Note, that the
+        // header itself cannot be checked against zero: the padding in front
+        // of an aligned allocation might be a free entry of size zero.
+        // An empty buffer (e.g. of an allocator, whose memory is provided
+        // later) has no entries at all.
+        let not_yet_initialized = self.untouched == 0 && self.end() != 0;
 
         if not_yet_initialized {
-            let remaining_size = N - HEADER_SIZE;
-            let initial_entry = Entry::free(remaining_size).as_raw();
+            let remaining_size = self.end() - Self::START - Self::HEADER_SIZE;
+            let initial_entry = Entry::free(remaining_size);
+            self.write_entry(Self::START, initial_entry);
+            self.untouched = Self::START + Self::HEADER_SIZE;
+            self.write_boundary_tag(self.end() - Self::HEADER_SIZE, initial_entry);
+        }
+        not_yet_initialized.then(|| ValidatedOffset(Self::START))
+    }
 
-            buffer[0] = MaybeUninit::new(initial_entry[0]);
-            buffer[1] = MaybeUninit::new(initial_entry[1]);
-            buffer[2] = MaybeUninit::new(initial_entry[2]);
-            buffer[3] = MaybeUninit::new(initial_entry[3]);
+    /// Read the word (an entry, a boundary tag or a link) at the given offset.
+    ///
+    /// The word occupies [`Self::HEADER_SIZE`](Self::HEADER_SIZE) bytes and is stored
+    /// in native endian order. It is read byte by byte, so there are no
+    /// alignment requirements on the memory.
+    ///
+    /// # Safety
+    /// The bytes of the word have to be initialized. The whole buffer is
+    /// initialized in [`Buffer::new()`], but users of an allocation might write
+    /// uninitialized bytes into its memory, so the word has to be written by
+    /// this buffer (e.g. a header or a boundary tag) or the memory must not be
+    /// handed out.
+    ///
+    /// # Panics
+    /// This function panics if the offset is not a multiple of the header size
+    /// or the offset plus the header size would read past the end of the
+    /// buffer.
+    unsafe fn read_word(&self, offset: usize) -> usize {
+        assert!(offset % Self::HEADER_SIZE == 0);
+        let memory = &self.memory.bytes()[offset..offset + Self::HEADER_SIZE];
+
+        let mut raw = [0; 8];
+        for (raw, byte) in raw.iter_mut().zip(memory) {
+            // SAFETY: the caller guarantees, that the bytes are initialized
+            *raw = unsafe { byte.assume_init() };
+        }
+        #[allow(clippy::cast_possible_truncation)] // words are written from an `usize`
+        match Self::HEADER_SIZE {
+            2 => u16::from_ne_bytes([raw[0], raw[1]]) as usize,
+            4 => u32::from_ne_bytes([raw[0], raw[1], raw[2], raw[3]]) as usize,
+            _ => u64::from_ne_bytes(raw) as usize,
         }
     }
 
-    /// Obtain a reference to an [`Entry`] inside of the buffer.
+    /// Write the word (an entry, a boundary tag or a link) at the given offset.
     ///
-    /// The returned memory will point inside the buffer itself and thus
-    /// modifying the reference will modify the buffer contents. This is a safe
-    /// operation, since the calling requirements (see below) are checked at
-    /// runtime. For safety-reasons this function does not return the [`Entry`]
-    /// directly, but instead uses a [`MaybeUninit<Entry>`] instead. Without
-    /// this, the function would be unsafe, since the caller would need to
-    /// guarantee, that the memory read is actually filled with a valid and
-    /// initialized `Entry`. By using the `MaybeUninit`-variant, the caller has
-    /// to use the `unsafe`-block when actually reading and assuming, that it is
-    /// initialized.
+    /// Please see [`read_word()`](Self::read_word) for details. The caller has
+    /// to ensure, that the word fits into the header size, which is the case
+    /// for all entries and links, since the buffer size is checked in
+    /// [`Buffer::new()`].
     ///
     /// # Panics
-    /// This function panics if the offset is not a multiple of 4 or the offset
-    /// plus the 4 bytes after it would read past the end of the buffer.
-    fn at(&self, offset: usize) -> &MaybeUninit<Entry> {
-        assert!(offset % mem::align_of::<Entry>() == 0);
-        assert!(offset + HEADER_SIZE <= self.0.len());
-
-        // SAFETY: this operation is unsafe for multiple reasons: the alignment
-        // has to be satisfied and the entry read must be in bound of the buffer
-        // memory.
-        // 1. the bounds of the memory is checked by the assert above: the
-        //    current offset plus the number of bytes read for an `Entry` is
-        //    inside the buffer. Therefore this safety requirement is always
-        //    fulfilled.
-        // 2. the proper alignment is ensured by first checking, whether the
-        //    offset is a multiple of the alignment of `Entry`. This makes sure,
-        //    that we are aligned within the buffer. Another important aspect is
-        //    that the buffer itself is aligned. This is achieved using a
-        //    `#[repr(align(4))]`-attribute on the buffer itself. Therefore the
-        //    alignment safety requirement is fulfilled as well.
-        //
-        // Note, that the memory, that is pointed to, might not contain a valid
-        // `Entry`. This is fine, since the function returns a `MaybeUninit`
-        // version of an `Entry`. Therefore the caller has to ensure, that the
-        // thing written or read is valid.
-        unsafe {
-            let memory = &self.0[offset..offset + 4];
-            let memory = memory.as_ptr();
-            #[allow(clippy::cast_ptr_alignment)] // alignment is asserted above
-            &*(memory
-                .cast::<[MaybeUninit<u8>; 4]>()
-                .cast::<MaybeUninit<Entry>>())
-        }
-    }
-
-    /// Obtain a mutable reference to an [`Entry`] inside of the buffer.
-    ///
-    /// Please see [`at()`](Self::at) for details.
+    /// This function panics if the offset is not a multiple of the header size
+    /// or the offset plus the header size would write past the end of the
+    /// buffer.
+    fn write_word(&mut self, offset: usize, word: usize) {
+        assert!(offset % Self::HEADER_SIZE == 0);
+        assert!(offset + Self::HEADER_SIZE <= self.memory.bytes().len());
+
+        // the boundary tag of the last entry is not tracked (see `untouched`)
+        if offset + Self::HEADER_SIZE < self.end() {
+            self.untouched = self.untouched.max(offset + Self::HEADER_SIZE);
+        }
+
+        let memory = &mut self.memory.bytes_mut()[offset..offset + Self::HEADER_SIZE];
+        #[allow(clippy::cast_possible_truncation)] // checked by the caller
+        match Self::HEADER_SIZE {
+            2 => write_bytes(memory, &(word as u16).to_ne_bytes()),
+            4 => write_bytes(memory, &(word as u32).to_ne_bytes()),
+            _ => write_bytes(memory, &(word as u64).to_ne_bytes()),
+        }
+    }
+
+    /// Read the [`Entry`] at the given offset.
+    ///
+    /// # Safety
+    /// The same requirements as for [`read_word()`](Self::read_word) apply.
     ///
     /// # Panics
-    /// This function panics if the offset is not a multiple of 4 or the offset
-    /// plus the 4 bytes after it would read past the end of the buffer.
-    fn at_mut(&mut self, offset: usize) -> &mut MaybeUninit<Entry> {
-        assert!(offset % mem::align_of::<Entry>() == 0);
-        assert!(offset + HEADER_SIZE <= self.0.len());
+    /// This function panics under the same conditions as
+    /// [`read_word()`](Self::read_word).
+    unsafe fn entry_at(&self, offset: usize) -> Entry {
+        // SAFETY: the caller upholds the safety contract
+        Entry::from_raw(unsafe { self.read_word(offset) }, Self::SIZE_BIAS)
+    }
 
-        // SAFETY: same as `at()`
-        unsafe {
-            let memory = &mut self.0[offset..offset + 4];
-            let memory = memory.as_mut_ptr();
-            #[allow(clippy::cast_ptr_alignment)] // alignment is asserted above
-            &mut *(memory
-                .cast::<[MaybeUninit<u8>; 4]>()
-                .cast::<MaybeUninit<Entry>>())
+    /// Write the [`Entry`] at the given offset.
+    ///
+    /// If the entry spans over the check cursor, the cursor is moved back to
+    /// the entry: the entry at the cursor was merged into this one (or moved),
+    /// so the check continues at the start of the merged entry.
+    ///
+    /// The offset is marked as the start of an entry. If an existing entry is
+    /// enlarged, the entries absorbed by it are no longer marked. Each entry
+    /// is absorbed only once, so this takes constant time on average (and
+    /// every operation of the allocator absorbs at most two entries anyway).
+    ///
+    /// # Panics
+    /// This function panics under the same conditions as
+    /// [`write_word()`](Self::write_word).
+    fn write_entry(&mut self, offset: usize, entry: Entry) {
+        let end = offset + Self::HEADER_SIZE + entry.size();
+        if offset < self.check_cursor && self.check_cursor < end {
+            self.check_cursor = offset;
+        }
+        if self.is_entry(offset) {
+            // SAFETY: the header of a marked entry was written by the buffer
+            let old_size = unsafe { self.entry_at(offset) }.size();
+            let mut next = offset.saturating_add(Self::HEADER_SIZE + old_size);
+            while next < end && self.is_entry(next) {
+                self.mark_entry(next, false);
+                // SAFETY: same as above
+                let size = unsafe { self.entry_at(next) }.size();
+                next = next.saturating_add(Self::HEADER_SIZE + size);
+            }
+        }
+        if end == self.end() {
+            self.last = offset;
         }
+        self.mark_entry(offset, true);
+        self.write_word(offset, entry.as_raw(Self::SIZE_BIAS));
+    }
+
+    /// Write the boundary tag of a free [`Entry`] at the given offset.
+    ///
+    /// Unlike a header, the boundary tag does not start an entry, therefore
+    /// it does not move the check cursor.
+    ///
+    /// # Panics
+    /// This function panics under the same conditions as
+    /// [`write_word()`](Self::write_word).
+    fn write_boundary_tag(&mut self, offset: usize, entry: Entry) {
+        self.write_word(offset, entry.as_raw(Self::SIZE_BIAS));
+    }
+
+    /// Query the entry at a [`ValidatedOffset`].
+    pub fn entry(&self, offset: ValidatedOffset) -> Entry {
+        // SAFETY: the `ValidatedOffset` marks the read valid (safety invariant
+        // of that type)
+        unsafe { self.entry_at(offset.0) }
+    }
+
+    /// Replace the entry at a [`ValidatedOffset`].
+    pub fn set_entry(&mut self, offset: ValidatedOffset, entry: Entry) {
+        self.write_entry(offset.0, entry);
     }
 
     /// Iterate over all entries and obtain the [`ValidatedOffset`]s.
-    pub const fn entries(&self) -> EntryIter<N> {
+    pub const fn entries(&self) -> EntryIter<'_, M, H> {
         EntryIter::new(self)
     }
 
@@ -149,10 +395,10 @@ impl<const N: usize> Buffer<N> {
     /// This operation is safe, since the offset is validated. It returns the
     /// slice of the memory of the given entry.
     pub fn memory_of(&self, offset: ValidatedOffset) -> &[MaybeUninit<u8>] {
-        let size = self[offset].size();
+        let size = self.entry(offset).size();
 
-        let offset = offset.0 + HEADER_SIZE;
-        &self.0[offset..offset + size]
+        let offset = offset.0 + Self::HEADER_SIZE;
+        &self.memory.bytes()[offset..offset + size]
     }
 
     /// Request the mutable memory of an entry at a [`ValidatedOffset`].
@@ -160,10 +406,100 @@ impl<const N: usize> Buffer<N> {
     /// This operation is safe, since the offset is validated. It returns the
     /// slice of the memory of the given entry.
     pub fn memory_of_mut(&mut self, offset: ValidatedOffset) -> &mut [MaybeUninit<u8>] {
-        let size = self[offset].size();
+        let size = self.entry(offset).size();
+
+        let offset = offset.0 + Self::HEADER_SIZE;
+        &mut self.memory.bytes_mut()[offset..offset + size]
+    }
 
-        let offset = offset.0 + HEADER_SIZE;
-        &mut self.0[offset..offset + size]
+    /// Query the offset of the memory, that was never written to.
+    ///
+    /// All the bytes starting at that offset are known to be zero, except for
+    /// the last header of the buffer, which might contain the boundary tag of
+    /// the last entry. The offset only grows over time.
+    pub const fn untouched(&self) -> usize {
+        self.untouched
+    }
+
+    /// Zero the memory of an entry, except for the bytes known to be zero.
+    ///
+    /// The `untouched` offset has to be obtained by [`untouched()`] _before_
+    /// the entry was marked as used, since this marks its whole memory as
+    /// touched.
+    ///
+    /// [`untouched()`]: Self::untouched
+    pub fn zero_memory_of(&mut self, offset: ValidatedOffset, untouched: usize) {
+        let start = offset.0 + Self::HEADER_SIZE;
+        let end = start + self.entry(offset).size();
+
+        let dirty_end = untouched.clamp(start, end);
+        let buffer_end = self.end();
+        let memory = self.memory.bytes_mut();
+        memory[start..dirty_end].fill(MaybeUninit::new(0x00));
+        if end == buffer_end {
+            // the boundary tag of the last entry is not tracked
+            let tag_start = (buffer_end - Self::HEADER_SIZE).max(dirty_end);
+            memory[tag_start..end].fill(MaybeUninit::new(0x00));
+        }
+    }
+
+    /// Query the entry directly following the given one, if there is any.
+    ///
+    /// This uses the same end-condition as the [`EntryIter`], therefore it
+    /// yields exactly the next offset, that the iteration would yield.
+    fn following_entry(&self, offset: ValidatedOffset) -> Option<ValidatedOffset> {
+        let next = offset.0 + Self::HEADER_SIZE + self.entry(offset).size();
+        (next + Self::HEADER_SIZE < self.end()).then(|| ValidatedOffset(next))
+    }
+
+    /// Query the size of the memory of the buffer in bytes.
+    pub fn size(&self) -> usize {
+        self.memory.bytes().len()
+    }
+
+    /// Check, whether the given pointer points into the memory of the buffer.
+    pub fn contains(&self, ptr: *const u8) -> bool {
+        let memory = self.memory.bytes();
+        let start = memory.as_ptr() as usize;
+        (start..start + memory.len()).contains(&(ptr as usize))
+    }
+
+    /// Find the entry, whose memory contains the given pointer.
+    ///
+    /// The bitmap of entry starts is searched backwards starting at the offset
+    /// of a header directly in front of the pointer. Only the header of the
+    /// entry found this way is read, so that data written into an allocation
+    /// (or the header of an entry, that was merged into its neighbor) cannot
+    /// be mistaken for an entry. For a pointer to the start of the memory of
+    /// an entry (as returned by the allocation functions), this reads a single
+    /// byte of the bitmap, so it takes constant time. Otherwise the time is
+    /// proportional to the distance to the start of the entry (a byte of the
+    /// bitmap per eight granules). If the pointer does not point into the
+    /// memory of an entry (e.g. it points to a header or outside of the
+    /// buffer), `None` is returned.
+    pub fn entry_of(&self, ptr: *const u8) -> Option<ValidatedOffset> {
+        let offset = (ptr as usize).checked_sub(self.memory.bytes().as_ptr() as usize)?;
+        if offset >= self.end() {
+            return None;
+        }
+        let index = (offset.checked_sub(Self::START + Self::HEADER_SIZE)?) / Self::GRANULE;
+
+        let bitmap = &self.memory.bytes()[self.bitmap_start()..];
+        let mut byte = index / 8;
+        let mut mask = u8::MAX >> (7 - index % 8);
+        loop {
+            // SAFETY: see `is_entry()`
+            let bits = unsafe { bitmap[byte].assume_init() } & mask;
+            if bits != 0 {
+                let index = 8 * byte + 7 - bits.leading_zeros() as usize;
+                let entry = ValidatedOffset(Self::START + index * Self::GRANULE);
+                let start = entry.0 + Self::HEADER_SIZE;
+                let end = start + self.entry(entry).size();
+                return (start..end).contains(&offset).then(|| entry);
+            }
+            byte = byte.checked_sub(1)?;
+            mask = u8::MAX;
+        }
     }
 
     /// Query the following free entry, if there is such an entry.
@@ -172,17 +508,153 @@ impl<const N: usize> Buffer<N> {
     /// obtain the entry after it. If there is no entry after it (because the
     /// given one is the last in the buffer) or if the entry following it is a
     /// used one, then `None` is returned.
-    #[allow(clippy::needless_pass_by_ref_mut)] // this is a "mutable" operation
-    pub fn following_free_entry(&mut self, offset: ValidatedOffset) -> Option<Entry> {
-        let iter_starting_at_offset = EntryIter {
-            buffer: self,
-            offset: offset.0,
+    pub fn following_free_entry(&self, offset: ValidatedOffset) -> Option<ValidatedOffset> {
+        self.following_entry(offset)
+            .filter(|offset| self.entry(*offset).state() == State::Free)
+    }
+
+    /// Query the preceding free entry, if there is such an entry.
+    ///
+    /// This function takes a [`ValidatedOffset`] of one entry and checks, if
+    /// the entry before it is free. If so, the offset of that entry is returned
+    /// (this is possible, since free entries store a copy of their header at
+    /// their end). If the given entry is the first one in the buffer or if the
+    /// entry preceding it is used, then `None` is returned.
+    pub fn preceding_free_entry(&self, offset: ValidatedOffset) -> Option<ValidatedOffset> {
+        self.entry(offset).is_preceded_by_free().then(|| {
+            let tag_offset = offset.0 - Self::HEADER_SIZE;
+            // SAFETY: the preceding entry is free, therefore the boundary tag
+            // (or the link of a small block) directly in front of the current
+            // entry is initialized.
+            let tag = unsafe { self.read_word(tag_offset) };
+            let size = if tag & SMALL_BLOCK_LINK == 0 {
+                // SAFETY: the boundary tag is a valid entry (see above)
+                unsafe { self.entry_at(tag_offset) }.size()
+            } else {
+                Self::HEADER_SIZE
+            };
+            ValidatedOffset(tag_offset - size)
+        })
+    }
+
+    /// Query the next free entry in the free list, that is stored inside the
+    /// given free entry.
+    ///
+    /// The link is only valid, if it was written with [`set_next_free()`]
+    /// before. Free entries of size `0` cannot store a link at all, therefore
+    /// calling this function on those is a bug.
+    ///
+    /// [`set_next_free()`]: Self::set_next_free
+    pub fn next_free(&self, offset: ValidatedOffset) -> Option<ValidatedOffset> {
+        debug_assert!(self.entry(offset).state() == State::Free);
+        debug_assert!(self.entry(offset).size() >= Self::HEADER_SIZE);
+
+        // SAFETY: the link was written by `set_next_free()`
+        let link = unsafe { self.read_word(offset.0 + Self::HEADER_SIZE) };
+        Link(link & !SMALL_BLOCK_LINK).get()
+    }
+
+    /// Store the next free entry of the free list inside the given free entry.
+    ///
+    /// The link is stored in the first word of the memory of that entry. Free
+    /// entries of size `0` cannot store a link at all, therefore calling this
+    /// function on those is a bug.
+    pub fn set_next_free(&mut self, offset: ValidatedOffset, next: Option<ValidatedOffset>) {
+        let size = self.entry(offset).size();
+        debug_assert!(self.entry(offset).state() == State::Free);
+        debug_assert!(size >= Self::HEADER_SIZE);
+
+        let Link(link) = Link::new(next);
+        let link = if size == Self::HEADER_SIZE {
+            link | SMALL_BLOCK_LINK
+        } else {
+            link
         };
+        self.write_word(offset.0 + Self::HEADER_SIZE, link);
+    }
+
+    /// Query the previous free entry in a doubly linked list, that is stored
+    /// inside the given free entry.
+    ///
+    /// The link is only valid, if it was written with [`set_previous_free()`]
+    /// before. Only free entries with a size of at least three headers can
+    /// store this link (the first word is occupied by the link to the next entry and the
+    /// last word by the boundary tag), therefore calling this function on
+    /// smaller entries is a bug.
+    ///
+    /// [`set_previous_free()`]: Self::set_previous_free
+    pub fn previous_free(&self, offset: ValidatedOffset) -> Option<ValidatedOffset> {
+        debug_assert!(self.entry(offset).state() == State::Free);
+        debug_assert!(self.entry(offset).size() >= 3 * Self::HEADER_SIZE);
+
+        // SAFETY: the link was written by `set_previous_free()`
+        let link = unsafe { self.read_word(offset.0 + 2 * Self::HEADER_SIZE) };
+        Link(link).get()
+    }
+
+    /// Store the previous free entry of a doubly linked list inside the given
+    /// free entry.
+    ///
+    /// The link is stored in the second word of the memory of that entry. Only
+    /// free entries with a size of at least three headers can store this link,
+    /// therefore calling this function on smaller entries is a bug.
+    pub fn set_previous_free(
+        &mut self,
+        offset: ValidatedOffset,
+        previous: Option<ValidatedOffset>,
+    ) {
+        debug_assert!(self.entry(offset).state() == State::Free);
+        debug_assert!(self.entry(offset).size() >= 3 * Self::HEADER_SIZE);
+
+        let Link(link) = Link::new(previous);
+        self.write_word(offset.0 + 2 * Self::HEADER_SIZE, link);
+    }
+
+    /// Read the `index`-th word of the memory of the given used entry.
+    ///
+    /// This allows the allocator to store its own data structures (e.g. the
+    /// table of handles) inside of a used entry. The word is only valid, if it
+    /// was written with [`set_word_in()`](Self::set_word_in) before, therefore
+    /// calling this function on the memory of a user allocation is a bug.
+    pub fn word_in(&self, offset: ValidatedOffset, index: usize) -> usize {
+        debug_assert!(self.entry(offset).state() == State::Used);
+        debug_assert!((index + 1) * Self::HEADER_SIZE <= self.entry(offset).size());
+
+        // SAFETY: the word was written by `set_word_in()`
+        unsafe { self.read_word(offset.0 + (index + 1) * Self::HEADER_SIZE) }
+    }
+
+    /// Write the `index`-th word of the memory of the given used entry.
+    ///
+    /// The word has to fit into the header size (like a link).
+    pub fn set_word_in(&mut self, offset: ValidatedOffset, index: usize, word: usize) {
+        debug_assert!(self.entry(offset).state() == State::Used);
+        debug_assert!((index + 1) * Self::HEADER_SIZE <= self.entry(offset).size());
+
+        self.write_word(offset.0 + (index + 1) * Self::HEADER_SIZE, word);
+    }
+
+    /// Read a link stored in the `index`-th word of the memory of the given
+    /// used entry.
+    ///
+    /// The link is only valid, if it was written with [`set_link_in()`] before
+    /// (or if the word is zero, which is the empty link).
+    ///
+    /// [`set_link_in()`]: Self::set_link_in
+    pub fn link_in(&self, offset: ValidatedOffset, index: usize) -> Option<ValidatedOffset> {
+        Link(self.word_in(offset, index)).get()
+    }
 
-        iter_starting_at_offset
-            .map(|offset| self[offset])
-            .nth(1)
-            .filter(|entry| entry.state() == State::Free)
+    /// Store a link in the `index`-th word of the memory of the given used
+    /// entry.
+    pub fn set_link_in(
+        &mut self,
+        offset: ValidatedOffset,
+        index: usize,
+        link: Option<ValidatedOffset>,
+    ) {
+        let Link(link) = Link::new(link);
+        self.set_word_in(offset, index, link);
     }
 
     /// Mark the given `Entry` as used and try to split it up.
@@ -193,73 +665,463 @@ impl<const N: usize> Buffer<N> {
     /// `Entry`, which holds the remaining memory (except for the necessary
     /// header space). If the entry is not large enough for splitting, than the
     /// entry is simply converted to an used entry.
-    pub fn mark_as_used(&mut self, offset: ValidatedOffset, size: usize) {
-        let old_size = self[offset].size();
+    ///
+    /// If the entry was split, the offset of the new free entry is returned.
+    pub fn mark_as_used(
+        &mut self,
+        offset: ValidatedOffset,
+        size: usize,
+    ) -> Option<ValidatedOffset> {
+        let old_entry = self.entry(offset);
+        let old_size = old_entry.size();
         debug_assert!(old_size >= size);
 
-        self[offset] = Entry::used(size);
-        if let Some(remaining_size) = (old_size - size).checked_sub(HEADER_SIZE) {
-            self.at_mut(offset.0 + size + HEADER_SIZE)
-                .write(Entry::free(remaining_size));
+        let preceding_free = old_entry.is_preceded_by_free();
+        self.set_entry(
+            offset,
+            Entry::used(size).with_preceding_free(preceding_free),
+        );
+        // the memory of the entry is handed out and might be written anywhere
+        self.untouched = self.untouched.max(offset.0 + Self::HEADER_SIZE + size);
+        if let Some(remaining_size) = (old_size - size).checked_sub(Self::HEADER_SIZE) {
+            // the following entry is still preceded by a free one: the split-
+            // off remainder.
+            let remainder = offset.0 + size + Self::HEADER_SIZE;
+            self.write_free_entry(remainder, remaining_size, false);
+            Some(ValidatedOffset(remainder))
+        } else {
+            if let Some(next) = self.following_entry(offset) {
+                self.set_entry(next, self.entry(next).with_preceding_free(false));
+            }
+            None
+        }
+    }
+
+    /// Compute the padding needed in front of an allocation of `size` bytes
+    /// with the alignment `align` inside the free entry at the given offset.
+    ///
+    /// The padding is either zero or large enough to hold a free entry with at
+    /// least `min_block_size` bytes of memory (plus its header), so that it can
+    /// be split off with [`split_free()`](Self::split_free). If the aligned
+    /// allocation does not fit into the entry, `None` is returned.
+    pub fn alignment_padding(
+        &self,
+        offset: ValidatedOffset,
+        size: usize,
+        align: usize,
+        min_block_size: usize,
+    ) -> Option<usize> {
+        let memory = self.memory_of(offset);
+        let start = memory.as_ptr() as usize;
+        let misalignment = start.wrapping_neg() & (align - 1);
+        let min_padding = Self::rounded_size(min_block_size)? + Self::HEADER_SIZE;
+        let padding = if misalignment == 0 || misalignment >= min_padding {
+            misalignment
+        } else {
+            // the padding is too small for a free entry, so the next aligned
+            // address after a minimal free entry is used instead.
+            min_padding + ((start + min_padding).wrapping_neg() & (align - 1))
+        };
+        (padding.checked_add(size)? <= memory.len()).then(|| padding)
+    }
+
+    /// Round up the given size to the next valid block size.
+    ///
+    /// All blocks including their header occupy a multiple of the granule. If
+    /// the rounded size overflows, `None` is returned.
+    pub const fn rounded_size(size: usize) -> Option<usize> {
+        match size.checked_add(Self::HEADER_SIZE + Self::GRANULE - 1) {
+            Some(end) => Some(end / Self::GRANULE * Self::GRANULE - Self::HEADER_SIZE),
+            None => None,
+        }
+    }
+
+    /// Split the free entry at the given offset into two free entries.
+    ///
+    /// The first entry keeps `size` bytes of memory, the second one gets the
+    /// remaining memory (except for its header). The offset of the second
+    /// entry is returned. Note, that this creates two adjacent free entries, so
+    /// the caller has to mark the second one as used afterwards.
+    pub fn split_free(&mut self, offset: ValidatedOffset, size: usize) -> ValidatedOffset {
+        let old_entry = self.entry(offset);
+        debug_assert!(old_entry.state() == State::Free);
+        debug_assert!(old_entry.size() >= size + Self::HEADER_SIZE);
+
+        self.write_free_entry(offset.0, size, old_entry.is_preceded_by_free());
+        let second = offset.0 + Self::HEADER_SIZE + size;
+        self.write_free_entry(second, old_entry.size() - size - Self::HEADER_SIZE, true);
+        ValidatedOffset(second)
+    }
+
+    /// Mark the given `Entry` as free with the given (possibly enlarged) size.
+    ///
+    /// This writes the free header as well as the boundary tag at the end of
+    /// the block and informs the following entry, that it is now preceded by a
+    /// free one. The `size` might be larger than the current size of the entry
+    /// in order to absorb adjacent free entries. The state of the entry before
+    /// the given one is preserved.
+    pub fn mark_as_free(&mut self, offset: ValidatedOffset, size: usize) {
+        let preceding_free = self.entry(offset).is_preceded_by_free();
+        self.write_free_entry(offset.0, size, preceding_free);
+        if let Some(next) = self.following_entry(offset) {
+            self.set_entry(next, self.entry(next).with_preceding_free(true));
         }
     }
+
+    /// Move the used entry at the given offset into the free entry directly
+    /// preceding it.
+    ///
+    /// The memory of the used entry is copied to the start of the free entry,
+    /// so that the free memory ends up behind the moved entry. The offset of
+    /// that free entry is returned. Its size is the size of the preceding free
+    /// entry, the caller has to merge it with a following free entry and has
+    /// to inform the following entry about its free predecessor (e.g. via
+    /// [`mark_as_free()`](Self::mark_as_free)).
+    pub fn move_to_preceding(
+        &mut self,
+        offset: ValidatedOffset,
+        preceding: ValidatedOffset,
+    ) -> ValidatedOffset {
+        let entry = self.entry(offset);
+        let free = self.entry(preceding);
+        debug_assert!(entry.state() == State::Used);
+        debug_assert!(free.state() == State::Free);
+        debug_assert!(preceding.0 + Self::HEADER_SIZE + free.size() == offset.0);
+
+        // the header of the moved entry ends up in the memory of the moved or
+        // the free entry (or it is overwritten by the header of the latter)
+        self.mark_entry(offset.0, false);
+        let size = entry.size();
+        let source = offset.0 + Self::HEADER_SIZE;
+        let destination = preceding.0 + Self::HEADER_SIZE;
+        self.memory
+            .bytes_mut()
+            .copy_within(source..source + size, destination);
+        self.set_entry(
+            preceding,
+            Entry::used(size).with_preceding_free(free.is_preceded_by_free()),
+        );
+
+        let remainder = destination + size;
+        self.write_free_entry(remainder, free.size(), false);
+        ValidatedOffset(remainder)
+    }
+
+    /// Write a free entry including its boundary tag at the given offset.
+    fn write_free_entry(&mut self, offset: usize, size: usize, preceding_free: bool) {
+        let entry = Entry::free(size).with_preceding_free(preceding_free);
+        self.write_entry(offset, entry);
+        self.write_boundary_tag(offset + size, entry);
+    }
+
+    /// Check the entry at the given offset for consistency.
+    ///
+    /// The entry has to fit into the buffer and has to end at a multiple of
+    /// the granule (the last entry exactly at the end of the buffer). If the
+    /// entry is free, its boundary tag has to lead back to it and the
+    /// following entry must not be free as well. The following entry has to
+    /// know the state of this entry. The offset of the following entry is
+    /// returned, which is the end offset after the last entry.
+    ///
+    /// The offset has to be the one of an entry (the first one or one returned
+    /// by this function), the entry itself is not trusted, so that this does
+    /// not panic on a corrupted buffer.
+    pub fn check_entry(&self, offset: usize) -> Result<usize, CheckError> {
+        let address = self.memory.bytes().as_ptr() as usize + offset + Self::HEADER_SIZE;
+        // SAFETY: the offset of an entry is aligned and its header is inside
+        // of the buffer (checked for the previous entry). The header was
+        // written by the buffer, unless the previous header was corrupted by
+        // a write out of the bounds of an allocation, which is undefined
+        // behavior of the user anyway.
+        let entry = unsafe { self.entry_at(offset) };
+        let size = entry.size();
+        let next = offset
+            .checked_add(Self::HEADER_SIZE + size)
+            .filter(|next| *next <= self.end())
+            .ok_or(CheckError::OutOfBounds { address, size })?;
+        if (next + Self::HEADER_SIZE) % Self::GRANULE != 0 {
+            return Err(CheckError::Misaligned { address, size });
+        }
+        if offset == Self::START && entry.is_preceded_by_free() {
+            return Err(CheckError::InconsistentPrecedingState { address });
+        }
+
+        let is_free = entry.state() == State::Free;
+        if is_free {
+            let tag_offset = next - Self::HEADER_SIZE;
+            // SAFETY: the boundary tag is the last word of the entry, which is
+            // aligned and inside of the buffer (checked above)
+            let tag = unsafe { self.read_word(tag_offset) };
+            let tag_size = if tag & SMALL_BLOCK_LINK == 0 {
+                // SAFETY: same as above
+                unsafe { self.entry_at(tag_offset) }.size()
+            } else {
+                Self::HEADER_SIZE
+            };
+            if tag_offset.checked_sub(tag_size) != Some(offset) {
+                return Err(CheckError::InvalidBoundaryTag { address });
+            }
+        }
+
+        if next + Self::HEADER_SIZE < self.end() {
+            // SAFETY: the following header is aligned and inside of the buffer
+            // (checked above)
+            let following = unsafe { self.entry_at(next) };
+            if following.is_preceded_by_free() != is_free {
+                return Err(CheckError::InconsistentPrecedingState {
+                    address: address + size + Self::HEADER_SIZE,
+                });
+            }
+            if is_free && following.state() == State::Free {
+                return Err(CheckError::UncoalescedFreeBlocks { address });
+            }
+        }
+        Ok(next)
+    }
+
+    /// Check all entries of the buffer for consistency.
+    ///
+    /// See [`check_entry()`](Self::check_entry) for the conditions checked
+    /// for each entry. Each consistent entry is checked by `f` in addition
+    /// (e.g. the contents of its memory). The first problem found is returned.
+    pub fn check(
+        &self,
+        f: impl Fn(&Self, ValidatedOffset) -> Result<(), CheckError>,
+    ) -> Result<(), CheckError> {
+        let mut offset = Self::START;
+        while offset + Self::HEADER_SIZE < self.end() {
+            let next = self.check_entry(offset)?;
+            f(self, ValidatedOffset(offset))?;
+            offset = next;
+        }
+        Ok(())
+    }
+
+    /// Check the next `count` entries of the buffer for consistency.
+    ///
+    /// The check continues at the entry, where the previous step stopped, and
+    /// restarts at the first entry after the last one was checked (or after a
+    /// problem was found). Each consistent entry is checked by `f` in addition
+    /// like in [`check()`](Self::check). Whether the last entry was checked in
+    /// this step is returned.
+    pub fn check_step(
+        &mut self,
+        count: usize,
+        f: impl Fn(&Self, ValidatedOffset) -> Result<(), CheckError>,
+    ) -> Result<bool, CheckError> {
+        let mut offset = self.check_cursor.max(Self::START);
+        for _ in 0..count {
+            if offset + Self::HEADER_SIZE >= self.end() {
+                break;
+            }
+            offset = self
+                .check_entry(offset)
+                .and_then(|next| f(self, ValidatedOffset(offset)).map(|()| next))
+                .map_err(|error| {
+                    self.check_cursor = 0;
+                    error
+                })?;
+        }
+
+        let completed = offset + Self::HEADER_SIZE >= self.end();
+        self.check_cursor = if completed { 0 } else { offset };
+        Ok(completed)
+    }
 }
-impl<const N: usize> core::ops::Index<ValidatedOffset> for Buffer<N> {
-    type Output = Entry;
+impl<const N: usize, H: Header> Buffer<Array<N>, H> {
+    /// Create a new buffer.
+    ///
+    /// This buffer will be initialized to zero. The caller must subsequently
+    /// call [`Buffer::ensure_initialization()`] to initialize the first bytes
+    /// as the header. This header is a free [`Entry`] with the size of the
+    /// remaining buffer.
+    ///
+    /// # Panics
+    /// This function panics if the buffer cannot hold a single entry, if its
+    /// size is not a multiple of the granule or if the buffer is too large for
+    /// the sizes to be stored in a header.
+    pub const fn new() -> Self {
+        assert!(
+            N >= Self::bitmap_size(N) + 2 * Self::START + Self::GRANULE,
+            "buffer too small, use N >= 8"
+        );
+        assert!(N % 4 == 0, "memory size has to be divisible by 4");
+        assert!(
+            N % Self::GRANULE == 0,
+            "memory size has to be divisible by the header size and the granule"
+        );
+        assert!(
+            Self::fits_header(N),
+            "memory size too large for the header format, use a larger header"
+        );
 
-    fn index(&self, index: ValidatedOffset) -> &Self::Output {
-        // SAFETY: the `ValidatedOffset` marks the read valid (safety invariant
-        // of that type)
-        unsafe { self.at(index.0).assume_init_ref() }
+        // initialize all the bytes to zero: this makes sure, that the header
+        // can be checked against zero later without undefined behavior and
+        // that the untouched memory is known to be zero. Since the buffer is
+        // zeroed completely, a static allocator is still placed in `.bss`.
+        let memory = [MaybeUninit::new(0x00); N];
+        Self {
+            memory,
+            untouched: 0,
+            check_cursor: 0,
+            last: 0,
+            header: PhantomData,
+            alignment: [],
+        }
     }
 }
-impl<const N: usize> core::ops::IndexMut<ValidatedOffset> for Buffer<N> {
-    fn index_mut(&mut self, index: ValidatedOffset) -> &mut Self::Output {
-        // SAFETY: the `ValidatedOffset` marks the read valid (safety invariant
-        // of that type)
-        unsafe { self.at_mut(index.0).assume_init_mut() }
+impl<H: Header> Buffer<Region, H> {
+    /// Create a buffer without any memory.
+    ///
+    /// There are no entries in such a buffer, so all allocations fail.
+    pub const fn empty() -> Self {
+        Self {
+            memory: Region::EMPTY,
+            untouched: 0,
+            check_cursor: 0,
+            last: 0,
+            header: PhantomData,
+            alignment: [],
+        }
+    }
+
+    /// Create a new buffer in the given memory region.
+    ///
+    /// The region is shrunk to the largest part, that starts at a multiple of
+    /// the granule and whose size is a multiple of the granule. That part is
+    /// zeroed, like the memory of a buffer created by [`Buffer::new()`].
+    ///
+    /// # Panics
+    /// This function panics if the region cannot hold a single entry (at least
+    /// two headers) or if it is too large for the sizes to be stored in a header.
+    pub fn with_region(region: Region) -> Self {
+        let padding = region.start().wrapping_neg() % Self::GRANULE;
+        let size = region.size().saturating_sub(padding) / Self::GRANULE * Self::GRANULE;
+        assert!(
+            size >= Self::bitmap_size(size) + 2 * Self::START + Self::GRANULE
+                && size >= 2 * Self::HEADER_SIZE,
+            "memory region too small"
+        );
+        assert!(
+            Self::fits_header(size),
+            "memory region too large for the header format, use a larger header"
+        );
+
+        let mut memory = region.sub_region(padding, size);
+        memory.bytes_mut().fill(MaybeUninit::new(0x00));
+        Self {
+            memory,
+            untouched: 0,
+            check_cursor: 0,
+            last: 0,
+            header: PhantomData,
+            alignment: [],
+        }
+    }
+
+    /// Append the given memory region to the end of the buffer.
+    ///
+    /// The region has to start directly at the end of the memory of the
+    /// buffer, otherwise (or if the buffer is empty) it is given back. Its size
+    /// is rounded down to a multiple of the granule and its memory is zeroed.
+    /// The bitmap of entry starts is moved to the new end of the memory. If the
+    /// buffer is initialized already, the memory gained for the entries is
+    /// turned into a used entry, whose offset is returned: the caller has to
+    /// free it, so that it is merged with the last entry of the buffer, if that
+    /// one is free.
+    ///
+    /// # Panics
+    /// This function panics if the enlarged buffer is too large for the sizes
+    /// to be stored in a header.
+    pub fn append(&mut self, region: Region) -> Result<Option<ValidatedOffset>, Region> {
+        if self.end() == 0 || region.start() != self.memory.start() + self.memory.size() {
+            return Err(region);
+        }
+        let additional = region.size() / Self::GRANULE * Self::GRANULE;
+        assert!(
+            Self::fits_header(self.memory.size() + additional),
+            "memory region too large for the header format, use a larger header"
+        );
+
+        let last = ValidatedOffset(self.last.max(Self::START));
+        let end = self.end();
+        let bitmap = self.bitmap_start()..self.memory.size();
+        let mut region = region.sub_region(0, additional);
+        region.bytes_mut().fill(MaybeUninit::new(0x00));
+        self.memory.append(region);
+
+        // the former bitmap becomes part of the memory of the entries, so it
+        // has to be zero afterwards like the appended memory. The bitmap only
+        // grows, so its new end is part of the (zeroed) appended memory.
+        let new_bitmap = self.bitmap_start();
+        let memory = self.memory.bytes_mut();
+        memory.copy_within(bitmap.clone(), new_bitmap);
+        memory[bitmap.start..new_bitmap.min(bitmap.end)].fill(MaybeUninit::new(0x00));
+        if self.end() == end || self.untouched == 0 {
+            return Ok(None);
+        }
+
+        // the boundary tag of the former last entry is not tracked as touched,
+        // but writing the new header behind it marks the tag as touched.
+        let preceding_free = self.entry(last).state() == State::Free;
+        let size = self.end() - end - Self::HEADER_SIZE;
+        let entry = Entry::used(size).with_preceding_free(preceding_free);
+        self.write_entry(end, entry);
+        Ok(Some(ValidatedOffset(end)))
+    }
+}
+
+/// Copy the given bytes into the (possibly uninitialized) memory.
+fn write_bytes(memory: &mut [MaybeUninit<u8>], bytes: &[u8]) {
+    for (memory, &byte) in memory.iter_mut().zip(bytes) {
+        *memory = MaybeUninit::new(byte);
     }
 }
 
 /// An iterator over the allocation entries in a [`Buffer`].
 ///
 /// This iterator does not yield [`Entry`]s directly but rather yields so-called
-/// [`ValidatedOffset`]s. Those can be used to access the entries in a mutable
-/// and immutable way via indexing (`buffer[offset]`). This design was chosen,
+/// [`ValidatedOffset`]s. Those can be used to read and replace the entries via
+/// [`Buffer::entry()`] and [`Buffer::set_entry()`]. This design was chosen,
 /// since the naive way of an `EntryIter` and `EntryIterMut`, which yield
-/// `&Entry` and `&mut Entry` result in many borrowing issues.
+/// `&Entry` and `&mut Entry` result in many borrowing issues (and the entries
+/// are not stored as `Entry`s in the buffer anyway, but in the header format).
 ///
 /// One could make this iterator yield the offsets as plain `usize`s, but the
 /// newtype is a better solution: it allows to know, that the offset comes from
 /// a known place (this iterator, which knows, that there is an entry at that
 /// offset. If there were none, the iteration wouldn't be possible) and thus
-/// the indexing can become safe. This builds on the assumption, that nobody
+/// the access can become safe. This builds on the assumption, that nobody
 /// constructs an invalid `ValidatedOffset`.
-pub struct EntryIter<'buffer, const N: usize> {
+pub struct EntryIter<'buffer, M, H: Header> {
     /// The memory to iterate over.
     ///
-    /// This must be in a valid state (starting with an entry at offset `0` and
-    /// headers after all entries until the end of the buffer) in order for the
-    /// iteration to succeed.
-    buffer: &'buffer Buffer<N>,
+    /// This must be in a valid state (starting with an entry at the start offset
+    /// and headers after all entries until the end of the buffer) in order for
+    /// the iteration to succeed.
+    buffer: &'buffer Buffer<M, H>,
     /// The current offset into the buffer.
     offset: usize,
 }
-impl<'buffer, const N: usize> EntryIter<'buffer, N> {
+impl<'buffer, M: Memory, H: Header> EntryIter<'buffer, M, H> {
     /// Create an entry iterator over the given [`Buffer`].
-    const fn new(buffer: &'buffer Buffer<N>) -> Self {
-        Self { buffer, offset: 0 }
+    const fn new(buffer: &'buffer Buffer<M, H>) -> Self {
+        Self {
+            buffer,
+            offset: Buffer::<M, H>::START,
+        }
     }
 }
-impl<'buffer, const N: usize> Iterator for EntryIter<'buffer, N> {
+impl<'buffer, M: Memory, H: Header> Iterator for EntryIter<'buffer, M, H> {
     type Item = ValidatedOffset;
 
     fn next(&mut self) -> Option<Self::Item> {
-        (self.offset + HEADER_SIZE < N).then(|| {
+        let header_size = Buffer::<M, H>::HEADER_SIZE;
+        (self.offset + header_size < self.buffer.end()).then(|| {
             let offset = self.offset;
             // SAFETY: the buffer invariant (valid entries) have to be upheld
-            let entry = unsafe { self.buffer.at(offset).assume_init_ref() };
-            self.offset += entry.size() + HEADER_SIZE;
+            let entry = unsafe { self.buffer.entry_at(offset) };
+            self.offset += entry.size() + header_size;
             ValidatedOffset(offset)
         })
     }
@@ -267,13 +1129,248 @@ impl<'buffer, const N: usize> Iterator for EntryIter<'buffer, N> {
 
 #[cfg(test)]
 mod tests {
-    use super::{Buffer, Entry, ValidatedOffset, HEADER_SIZE};
+    // the bitmap of entry starts is part of the memory, e.g. the entries of an
+    // `Array<36>` span 32 bytes and the bitmap fills the remaining granule.
+
+    use super::super::header::{AutoHeader, Header16, Header32, Header64};
+    use super::super::memory::{Array, Region};
+    use super::{write_bytes, Buffer, Entry, Link, ValidatedOffset};
+    use core::mem::MaybeUninit;
 
     #[test]
     fn validated_offset_debug() {
         assert_eq!(format!("{:?}", ValidatedOffset(12)), "ValidatedOffset(12)");
     }
 
+    #[test]
+    fn link() {
+        assert_eq!(Link::NONE.get(), None);
+        assert_eq!(Link::new(None), Link::NONE);
+        assert_eq!(
+            Link::new(Some(ValidatedOffset(0))).get(),
+            Some(ValidatedOffset(0))
+        );
+        assert_eq!(
+            Link::new(Some(ValidatedOffset(12))).get(),
+            Some(ValidatedOffset(12))
+        );
+        assert_ne!(Link::new(Some(ValidatedOffset(0))), Link::NONE);
+    }
+
+    #[test]
+    fn previous_free() {
+        let mut buffer = Buffer::<Array<32>>::new();
+        let initial = buffer.ensure_initialization().unwrap();
+        let free = buffer.mark_as_used(initial, 4).unwrap();
+
+        // the previous link must not interfere with the next link and the
+        // boundary tag
+        buffer.set_next_free(free, Some(initial));
+        buffer.set_previous_free(free, None);
+        assert_eq!(buffer.next_free(free), Some(initial));
+        assert_eq!(buffer.previous_free(free), None);
+        buffer.set_previous_free(free, Some(initial));
+        assert_eq!(buffer.next_free(free), Some(initial));
+        assert_eq!(buffer.previous_free(free), Some(initial));
+        assert_eq!(buffer.preceding_free_entry(free), None);
+    }
+
+    #[test]
+    fn untouched_memory() {
+        let mut buffer = Buffer::<Array<36>>::new();
+        assert_eq!(buffer.untouched(), 0);
+
+        // the boundary tag at the end of the buffer is not tracked
+        let initial = buffer.ensure_initialization().unwrap();
+        assert_eq!(buffer.untouched(), 4);
+
+        // splitting the entry touches its memory and the new header
+        let free = buffer.mark_as_used(initial, 8).unwrap();
+        assert_eq!(buffer.untouched(), 16);
+        buffer.set_entry(free, Entry::used(16));
+        assert_eq!(buffer.untouched(), 16);
+
+        // the memory of the entry is cleared up to the untouched offset and the
+        // last header of the buffer
+        buffer.memory[..32].fill(MaybeUninit::new(0xFF));
+        buffer.set_entry(free, Entry::used(16));
+        buffer.zero_memory_of(free, 20);
+        // SAFETY: the whole buffer was initialized above
+        let memory = buffer.memory.map(|byte| unsafe { byte.assume_init() });
+        assert_eq!(memory[16..20], [0; 4]);
+        assert_eq!(memory[20..28], [0xFF; 8]);
+        assert_eq!(memory[28..32], [0; 4]);
+    }
+
+    #[test]
+    fn alignment_padding() {
+        let mut buffer = Buffer::<Array<268>>::new();
+        let initial = buffer.ensure_initialization().unwrap();
+        let start = buffer.memory_of(initial).as_ptr() as usize;
+        let misalignment = start.wrapping_neg() & 63;
+
+        // small alignments never need padding
+        assert_eq!(buffer.alignment_padding(initial, 8, 4, 0), Some(0));
+        assert_eq!(buffer.alignment_padding(initial, 252, 1, 0), Some(0));
+        assert_eq!(buffer.alignment_padding(initial, 256, 4, 0), None);
+
+        // the padding is either zero or large enough for a free entry
+        let padding = buffer.alignment_padding(initial, 8, 64, 0).unwrap();
+        assert_eq!((start + padding) % 64, 0);
+        assert_eq!(padding, misalignment);
+        let padding = buffer.alignment_padding(initial, 8, 64, 12).unwrap();
+        assert_eq!((start + padding) % 64, 0);
+        assert!(padding == 0 || padding >= 16);
+        assert_eq!(
+            buffer.alignment_padding(initial, 252, 64, 0).is_some(),
+            misalignment == 0
+        );
+    }
+
+    #[test]
+    fn split_free() {
+        let mut buffer = Buffer::<Array<36>>::new();
+        let initial = buffer.ensure_initialization().unwrap();
+        let second = buffer.split_free(initial, 8);
+        assert_eq!(second, ValidatedOffset(12));
+        assert_eq!(buffer.entry(initial), Entry::free(8));
+        assert_eq!(
+            buffer.entry(second),
+            Entry::free(16).with_preceding_free(true)
+        );
+        assert_eq!(buffer.preceding_free_entry(second), Some(initial));
+
+        // the second entry is used afterwards
+        assert_eq!(buffer.mark_as_used(second, 16), None);
+        assert_eq!(buffer.preceding_free_entry(second), Some(initial));
+    }
+
+    #[test]
+    fn split_off_empty_first_entry() {
+        // the first entry is all zero afterwards, but the buffer must not be
+        // initialized again
+        let mut buffer = Buffer::<Array<36>>::new();
+        let initial = buffer.ensure_initialization().unwrap();
+        let second = buffer.split_free(initial, 0);
+        assert_eq!(buffer.entry(initial).as_raw(0), 0);
+        assert_eq!(buffer.ensure_initialization(), None);
+        assert_eq!(
+            buffer.entry(second),
+            Entry::free(24).with_preceding_free(true)
+        );
+    }
+
+    #[test]
+    fn entry_of() {
+        let mut buffer = Buffer::<Array<36>>::new();
+        let a = buffer.ensure_initialization().unwrap();
+        let b = buffer.mark_as_used(a, 4).unwrap();
+        let c = buffer.mark_as_used(b, 4).unwrap();
+        buffer.mark_as_used(c, 12);
+        buffer.mark_as_free(b, 4);
+        let start = |offset| buffer.memory_of(offset).as_ptr().cast::<u8>();
+
+        assert_eq!(buffer.entry_of(start(a)), Some(a));
+        assert_eq!(buffer.entry_of(start(b)), Some(b));
+        assert_eq!(buffer.entry_of(start(c)), Some(c));
+        // pointers into the memory of an entry
+        assert_eq!(buffer.entry_of(start(a).wrapping_add(2)), Some(a));
+        assert_eq!(buffer.entry_of(start(c).wrapping_add(11)), Some(c));
+
+        // pointers, that do not point into the memory of an entry
+        assert_eq!(buffer.entry_of(start(a).wrapping_sub(4)), None);
+        assert_eq!(buffer.entry_of(start(c).wrapping_sub(1)), None);
+        assert_eq!(buffer.entry_of(start(c).wrapping_add(12)), None);
+        assert_eq!(buffer.entry_of(core::ptr::null()), None);
+    }
+
+    #[test]
+    fn contains() {
+        let buffer = Buffer::<Array<32>>::new();
+        let start = buffer.memory.as_ptr().cast::<u8>();
+        assert!(buffer.contains(start));
+        assert!(buffer.contains(start.wrapping_add(31)));
+        assert!(!buffer.contains(start.wrapping_add(32)));
+        assert!(!buffer.contains(start.wrapping_sub(1)));
+        assert!(!Buffer::<Region>::empty().contains(start));
+    }
+
+    #[test]
+    fn entry_of_ignores_stale_headers() {
+        let mut buffer = Buffer::<Array<36>>::new();
+        let a = buffer.ensure_initialization().unwrap();
+        let b = buffer.mark_as_used(a, 4).unwrap();
+        let c = buffer.mark_as_used(b, 4).unwrap();
+        buffer.mark_as_used(c, 12);
+        let start_of_b = buffer.memory_of(b).as_ptr().cast::<u8>();
+
+        // the entry `b` is merged into the free entry `a`, but its header is
+        // left in the buffer. It must not be mistaken for an entry.
+        buffer.mark_as_free(a, 12);
+        assert_eq!(buffer.entry_of(start_of_b), Some(a));
+
+        // a header-like word written into the memory of an entry is ignored
+        // as well (like data written by the user of an allocation)
+        buffer.mark_as_used(a, 12);
+        buffer.write_word(4, Entry::used(4).as_raw(0));
+        assert_eq!(buffer.entry_of(start_of_b.wrapping_sub(4)), Some(a));
+    }
+
+    #[test]
+    fn entry_of_all_offsets() {
+        let mut buffer = Buffer::<Array<1060>>::new();
+        let first = buffer.ensure_initialization().unwrap();
+        let mut entries = vec![first];
+        let mut offset = first;
+        while let Some(next) = buffer.mark_as_used(offset, 12) {
+            entries.push(next);
+            offset = next;
+        }
+        // free every third entry (merging the freed ones with their free
+        // predecessors), so that the blocks have different sizes
+        for (i, entry) in entries.iter().enumerate().skip(1).step_by(3) {
+            let preceding = entries[i - 1];
+            buffer.mark_as_free(preceding, 12);
+            let size = buffer.entry(preceding).size() + 4 + buffer.entry(*entry).size();
+            buffer.mark_as_free(preceding, size);
+        }
+
+        let memory = buffer.memory.as_ptr().cast::<u8>();
+        for offset in 0..1060 {
+            let ptr = memory.wrapping_add(offset);
+            let expected = buffer.entries().find(|entry| {
+                let start = entry.0 + 4;
+                (start..start + buffer.entry(*entry).size()).contains(&offset)
+            });
+            assert_eq!(buffer.entry_of(ptr), expected);
+        }
+    }
+
+    #[test]
+    fn entry_of_reads_a_single_header() {
+        let mut buffer = Buffer::<Array<1060>>::new();
+        let first = buffer.ensure_initialization().unwrap();
+        let mut entries = vec![first];
+        let mut offset = first;
+        while let Some(next) = buffer.mark_as_used(offset, 4) {
+            entries.push(next);
+            offset = next;
+        }
+        assert_eq!(entries.len(), 128);
+
+        // the lookup of the start of an entry must not depend on any other
+        // header, so garbage in all of them does not change the result
+        let target = entries[100];
+        for entry in &entries {
+            if *entry != target {
+                buffer.write_word(entry.0, Entry::used(1000).as_raw(0));
+            }
+        }
+        let start = buffer.memory_of(target).as_ptr().cast::<u8>();
+        assert_eq!(buffer.entry_of(start), Some(target));
+        assert_eq!(buffer.entry_of(start.wrapping_add(3)), Some(target));
+    }
+
     #[test]
     fn validated_offset_equality() {
         assert_eq!(ValidatedOffset(12), ValidatedOffset(12));
@@ -288,18 +1385,84 @@ mod tests {
 
     #[test]
     fn empty_allocator() {
-        let mut buffer = Buffer::<32>::new();
+        let mut buffer = Buffer::<Array<36>>::new();
         buffer.ensure_initialization();
         let expected = Entry::free(32 - 4);
-        let actual = unsafe { buffer.at(0).assume_init() };
+        // SAFETY: the buffer is initialized, so there is an entry at offset 0
+        let actual = unsafe { buffer.entry_at(0) };
         assert_eq!(expected, actual);
     }
 
     #[test]
     fn header_size() {
-        // the codebase assumes, that the header size is `4`, so make sure that
-        // assumption holds.
-        assert_eq!(HEADER_SIZE, 4);
+        // the header size is part of the stability guarantees, so make sure
+        // that the default is `4`.
+        assert_eq!(Buffer::<Array<32>>::HEADER_SIZE, 4);
+        assert_eq!(Buffer::<Array<32>, Header64>::HEADER_SIZE, 8);
+        assert_eq!(Buffer::<Array<32>, Header16>::HEADER_SIZE, 2);
+        assert_eq!(Buffer::<Array<32>, AutoHeader>::HEADER_SIZE, 2);
+        assert_eq!(
+            Buffer::<Array<{ 64 * 1024 + 4 }>, AutoHeader>::HEADER_SIZE,
+            4
+        );
+    }
+
+    #[test]
+    fn granule() {
+        assert_eq!(Buffer::<Array<32>>::GRANULE, 4);
+        assert_eq!(Buffer::<Array<32>>::START, 0);
+        // the bitmap of entry starts fills (at least) the last granule
+        assert_eq!(Buffer::<Array<32>>::new().end(), 28);
+        assert_eq!(Buffer::<Array<32>, Header16>::GRANULE, 4);
+        assert_eq!(Buffer::<Array<32>, Header16>::START, 2);
+        assert_eq!(Buffer::<Array<32>, Header16>::new().end(), 26);
+        assert_eq!(Buffer::<Array<32>, Header64>::GRANULE, 8);
+        assert_eq!(Buffer::<Array<32>, Header64>::START, 0);
+        assert_eq!(Buffer::<Array<32>, Header32<8>>::START, 4);
+        assert_eq!(Buffer::<Array<32>, Header32<8>>::new().end(), 20);
+        assert_eq!(Buffer::<Array<64>, Header32<16>>::START, 12);
+        assert_eq!(Buffer::<Array<64>, Header32<16>>::new().end(), 36);
+        assert_eq!(Buffer::<Array<64>, Header16<16>>::START, 14);
+        assert_eq!(Buffer::<Array<64>, Header32<16>>::ALIGNMENT, 16);
+    }
+
+    #[test]
+    fn base_alignment() {
+        // the buffer is aligned to the granule
+        assert_eq!(core::mem::align_of::<Buffer<Array<32>, Header32<16>>>(), 16);
+        let buffer = Buffer::<Array<64>, Header16<16>>::new();
+        assert_eq!(buffer.memory.as_ptr() as usize % 16, 0);
+    }
+
+    #[test]
+    fn granule_sizes() {
+        let mut buffer = Buffer::<Array<80>, Header32<16>>::new();
+        let initial = buffer.ensure_initialization().unwrap();
+        assert_eq!(initial, ValidatedOffset(12));
+        assert_eq!(buffer.entry(initial), Entry::free(36));
+        assert_eq!(buffer.memory_of(initial).as_ptr() as usize % 16, 0);
+        let free = buffer.mark_as_used(initial, 12).unwrap();
+        assert_eq!(free, ValidatedOffset(28));
+        assert_eq!(buffer.entry(free), Entry::free(20));
+        assert_eq!(buffer.entries().collect::<Vec<_>>(), [initial, free]);
+        let start = buffer.memory_of(free).as_ptr().cast::<u8>();
+        assert_eq!(buffer.entry_of(start), Some(free));
+        // the padding in front of the header belongs to the previous entry
+        assert_eq!(buffer.entry_of(start.wrapping_sub(8)), Some(initial));
+        assert_eq!(buffer.entry_of(start.wrapping_sub(4)), None);
+    }
+
+    #[test]
+    fn rounded_size() {
+        assert_eq!(Buffer::<Array<32>>::rounded_size(0), Some(0));
+        assert_eq!(Buffer::<Array<32>>::rounded_size(5), Some(8));
+        assert_eq!(Buffer::<Array<32>, Header16>::rounded_size(0), Some(2));
+        assert_eq!(Buffer::<Array<32>, Header16>::rounded_size(3), Some(6));
+        assert_eq!(Buffer::<Array<32>, Header16>::rounded_size(6), Some(6));
+        assert_eq!(Buffer::<Array<32>, Header32<8>>::rounded_size(5), Some(12));
+        assert_eq!(Buffer::<Array<32>, Header32<16>>::rounded_size(0), Some(12));
+        assert_eq!(Buffer::<Array<32>, Header64>::rounded_size(9), Some(16));
+        assert_eq!(Buffer::<Array<32>>::rounded_size(usize::MAX - 2), None);
     }
 
     #[test]
@@ -307,7 +1470,7 @@ mod tests {
     fn too_small_buffer() {
         // this test ensures, that there is no out of bounds writing when
         // setting up the initial entry
-        Buffer::<3>::new();
+        Buffer::<Array<3>>::new();
     }
 
     #[test]
@@ -316,22 +1479,22 @@ mod tests {
         // the buffer size is not really an issue here, but the code is easier
         // to write/read if the buffer size is always a multiple of the header
         // size, i.e. the size of an entry, which is `4`.
-        Buffer::<13>::new();
+        Buffer::<Array<13>>::new();
     }
 
     #[test]
     fn entry_iter() {
-        let mut buffer = Buffer::<32>::new();
+        let mut buffer = Buffer::<Array<32>>::new();
         buffer.ensure_initialization();
         let mut iter = buffer.entries();
         assert_eq!(iter.next(), Some(ValidatedOffset(0)));
         assert_eq!(iter.next(), None);
 
-        let mut buffer = Buffer::<32>::new();
+        let mut buffer = Buffer::<Array<32>>::new();
         buffer.ensure_initialization();
-        buffer.at_mut(0).write(Entry::free(4));
-        buffer.at_mut(8).write(Entry::used(4));
-        buffer.at_mut(16).write(Entry::free(12));
+        buffer.write_entry(0, Entry::free(4));
+        buffer.write_entry(8, Entry::used(4));
+        buffer.write_entry(16, Entry::free(12));
         let mut iter = buffer.entries();
         assert_eq!(iter.next(), Some(ValidatedOffset(0)));
         assert_eq!(iter.next(), Some(ValidatedOffset(8)));
@@ -341,59 +1504,156 @@ mod tests {
 
     #[test]
     fn indexing() {
-        let mut buffer = Buffer::<32>::new();
+        let mut buffer = Buffer::<Array<32>>::new();
         buffer.ensure_initialization();
-        buffer.at_mut(8).write(Entry::used(4));
+        buffer.write_entry(8, Entry::used(4));
 
-        assert_eq!(buffer[ValidatedOffset(8)], Entry::used(4));
-        buffer[ValidatedOffset(8)] = Entry::free(12);
-        assert_eq!(buffer[ValidatedOffset(8)], Entry::free(12));
+        assert_eq!(buffer.entry(ValidatedOffset(8)), Entry::used(4));
+        buffer.set_entry(ValidatedOffset(8), Entry::free(12));
+        assert_eq!(buffer.entry(ValidatedOffset(8)), Entry::free(12));
     }
 
     #[test]
     #[should_panic]
-    fn at_out_of_bounds() {
-        let mut buffer = Buffer::<32>::new();
+    fn read_out_of_bounds() {
+        let mut buffer = Buffer::<Array<32>>::new();
         buffer.ensure_initialization();
-        buffer.at(64); // panic here
+        // SAFETY: nothing was handed out, so the buffer only contains the
+        // zeroes written in `new()` and the initial entry
+        unsafe { buffer.read_word(64) }; // panic here
     }
 
     #[test]
     #[should_panic]
-    fn at_mut_out_of_bounds() {
-        let mut buffer = Buffer::<32>::new();
+    fn write_out_of_bounds() {
+        let mut buffer = Buffer::<Array<32>>::new();
         buffer.ensure_initialization();
-        buffer.at_mut(64); // panic here
+        buffer.write_word(64, 0); // panic here
     }
 
     #[test]
     #[should_panic]
-    fn at_unaligned() {
-        let mut buffer = Buffer::<32>::new();
+    fn read_unaligned() {
+        let mut buffer = Buffer::<Array<32>>::new();
         buffer.ensure_initialization();
-        buffer.at(2); // panic here
+        // SAFETY: nothing was handed out, so the buffer only contains the
+        // zeroes written in `new()` and the initial entry
+        unsafe { buffer.read_word(2) }; // panic here
     }
 
     #[test]
     #[should_panic]
-    fn at_mut_unaligned() {
-        let mut buffer = Buffer::<32>::new();
+    fn write_unaligned() {
+        let mut buffer = Buffer::<Array<32>, Header64>::new();
         buffer.ensure_initialization();
-        buffer.at_mut(2); // panic here
+        buffer.write_word(4, 0); // panic here
+    }
+
+    #[test]
+    fn words() {
+        let mut buffer = Buffer::<Array<32>>::new();
+        buffer.write_word(8, 0x1234_5678);
+        // SAFETY: the word was written above
+        assert_eq!(unsafe { buffer.read_word(8) }, 0x1234_5678);
+        // SAFETY: the word is still zero, as written in `new()`
+        assert_eq!(unsafe { buffer.read_word(4) }, 0);
+
+        let mut buffer = Buffer::<Array<32>, Header64>::new();
+        buffer.write_word(8, usize::MAX);
+        // SAFETY: the word was written above
+        assert_eq!(unsafe { buffer.read_word(8) }, usize::MAX);
+        // SAFETY: the word is still zero, as written in `new()`
+        assert_eq!(unsafe { buffer.read_word(16) }, 0);
+    }
+
+    #[test]
+    fn wide_headers() {
+        let mut buffer = Buffer::<Array<72>, Header64>::new();
+        let initial = buffer.ensure_initialization().unwrap();
+        assert_eq!(buffer.entry(initial), Entry::free(56));
+        assert_eq!(buffer.untouched(), 8);
+
+        // all the offsets are multiples of the header size
+        let free = buffer.mark_as_used(initial, 8).unwrap();
+        assert_eq!(free, ValidatedOffset(16));
+        assert_eq!(buffer.entry(free), Entry::free(40));
+        let used = buffer.mark_as_used(free, 8).unwrap();
+        buffer.mark_as_free(free, 8);
+        assert_eq!(buffer.preceding_free_entry(used), Some(free));
+
+        // a free block of a single header stores its link in the tag
+        buffer.set_next_free(free, Some(initial));
+        assert_eq!(buffer.next_free(free), Some(initial));
+        assert_eq!(buffer.preceding_free_entry(used), Some(free));
+        let start = buffer.memory_of(free).as_ptr().cast::<u8>();
+        assert_eq!(buffer.entry_of(start), Some(free));
+    }
+
+    #[test]
+    fn compact_headers() {
+        let mut buffer = Buffer::<Array<36>, Header16>::new();
+        let initial = buffer.ensure_initialization().unwrap();
+        assert_eq!(initial, ValidatedOffset(2));
+        assert_eq!(buffer.entry(initial), Entry::free(26));
+        assert_eq!(buffer.untouched(), 4);
+        // the raw size includes the bias of 2, the bytes outside of the first
+        // and the last granule are not used at all
+        // SAFETY: nothing was handed out, so the buffer only contains the
+        // zeroes written in `new()` and the words written since
+        let memory = buffer.memory.map(|byte| unsafe { byte.assume_init() });
+        let raw = 28u16.to_ne_bytes();
+        assert_eq!(memory[..4], [0, 0, raw[0], raw[1]]);
+        assert_eq!(memory[28..32], [raw[0], raw[1], 0, 0]);
+
+        // the memory of all entries is aligned to 4
+        let free = buffer.mark_as_used(initial, 6).unwrap();
+        assert_eq!(free, ValidatedOffset(10));
+        assert_eq!(buffer.entry(free), Entry::free(18));
+        assert_eq!(buffer.memory_of(free).as_ptr() as usize % 4, 0);
+        // SAFETY: the header was written above
+        let raw = unsafe { buffer.read_word(initial.0) };
+        assert_eq!(raw, 8 | 0b0_1);
+
+        // a free block of a single header stores its link in the tag
+        let used = buffer.mark_as_used(free, 2).unwrap();
+        buffer.mark_as_free(free, 2);
+        buffer.set_next_free(free, Some(initial));
+        assert_eq!(buffer.next_free(free), Some(initial));
+        assert_eq!(buffer.preceding_free_entry(used), Some(free));
+        let start = buffer.memory_of(used).as_ptr().cast::<u8>();
+        assert_eq!(buffer.entry_of(start), Some(used));
+        assert_eq!(buffer.entries().collect::<Vec<_>>(), [initial, free, used]);
+    }
+
+    #[test]
+    #[should_panic(expected = "too large for the header format")]
+    fn compact_headers_size_limit() {
+        Buffer::<Array<{ 64 * 1024 + 4 }>, Header16>::new();
+    }
+
+    #[test]
+    fn compact_headers_largest_heap() {
+        let mut buffer = Box::new(Buffer::<Array<{ 64 * 1024 }>, Header16>::new());
+        let initial = buffer.ensure_initialization().unwrap();
+        // the bitmap of entry starts takes a 32nd of the memory
+        assert_eq!(buffer.entry(initial), Entry::free(64 * 1024 - 2048 - 6));
+        let free = buffer.mark_as_used(initial, 2).unwrap();
+        buffer.set_next_free(free, Some(initial));
+        assert_eq!(buffer.next_free(free), Some(initial));
     }
 
     #[test]
     fn following_free_entry() {
-        let mut buffer = Buffer::<24>::new();
+        let mut buffer = Buffer::<Array<28>>::new();
         buffer.ensure_initialization();
-        buffer.at_mut(0).write(Entry::used(4));
-        buffer.at_mut(8).write(Entry::used(4));
-        buffer.at_mut(16).write(Entry::free(4));
+        buffer.write_entry(0, Entry::used(4));
+        buffer.write_entry(8, Entry::used(4));
+        buffer.write_entry(16, Entry::free(4));
 
         // if the entry is followed by a free block, return that block
         assert_eq!(
             buffer.following_free_entry(ValidatedOffset(8)),
-            Some(Entry::free(4))
+            Some(ValidatedOffset(16))
         );
         // if the entry is followed by a used block, return None
         assert_eq!(buffer.following_free_entry(ValidatedOffset(0)), None);
@@ -401,47 +1661,127 @@ mod tests {
         assert_eq!(buffer.following_free_entry(ValidatedOffset(16)), None);
     }
 
+    #[test]
+    fn preceding_free_entry() {
+        let mut buffer = Buffer::<Array<36>>::new();
+        buffer.ensure_initialization();
+        buffer.mark_as_used(ValidatedOffset(0), 4);
+        buffer.mark_as_used(ValidatedOffset(8), 8);
+        buffer.mark_as_used(ValidatedOffset(20), 8);
+        buffer.mark_as_free(ValidatedOffset(8), 8);
+
+        // if the entry is preceded by a free block, return that block
+        assert_eq!(
+            buffer.preceding_free_entry(ValidatedOffset(20)),
+            Some(ValidatedOffset(8))
+        );
+        // if the entry is preceded by a used block, return None
+        assert_eq!(buffer.preceding_free_entry(ValidatedOffset(8)), None);
+        // if the entry is not preceded by any block, return None
+        assert_eq!(buffer.preceding_free_entry(ValidatedOffset(0)), None);
+    }
+
+    #[test]
+    fn mark_as_free() {
+        let mut buffer = Buffer::<Array<32>>::new();
+        buffer.ensure_initialization();
+        buffer.mark_as_used(ValidatedOffset(0), 4);
+        buffer.mark_as_used(ValidatedOffset(8), 4);
+
+        // the freed block has to be marked as free, its boundary tag has to be
+        // written and the following block needs to know about its free
+        // predecessor.
+        buffer.mark_as_free(ValidatedOffset(0), 4);
+        assert_eq!(buffer.entry(ValidatedOffset(0)), Entry::free(4));
+        // SAFETY: the boundary tag was written by `mark_as_free()`
+        let boundary_tag = unsafe { buffer.entry_at(4) };
+        assert_eq!(boundary_tag, Entry::free(4));
+        assert_eq!(
+            buffer.entry(ValidatedOffset(8)),
+            Entry::used(4).with_preceding_free(true)
+        );
+    }
+
     #[test]
     fn memory_of() {
         use core::ptr;
 
-        let mut buffer = Buffer::<20>::new();
+        let mut buffer = Buffer::<Array<20>>::new();
         buffer.ensure_initialization();
-        buffer.at_mut(0).write(Entry::used(4));
+        buffer.write_entry(0, Entry::used(4));
 
-        let expected = &buffer.0[4..8];
+        let expected = &buffer.memory[4..8];
         let actual = buffer.memory_of(ValidatedOffset(0));
         assert_eq!(ptr::addr_of!(expected[0]), ptr::addr_of!(actual[0]));
     }
 
     #[test]
     fn mark_used_without_split() {
-        let mut buffer = Buffer::<24>::new();
+        let mut buffer = Buffer::<Array<24>>::new();
         buffer.ensure_initialization();
-        buffer.at_mut(0).write(Entry::used(4));
-        buffer.at_mut(8).write(Entry::free(4));
-        buffer.at_mut(16).write(Entry::used(4));
+        buffer.write_entry(0, Entry::used(4));
+        buffer.write_entry(8, Entry::free(4));
+        buffer.write_entry(16, Entry::used(4));
 
         // the entry to be marked as used has exactly the requested size. There-
         // fore no splitting might happen
         buffer.mark_as_used(ValidatedOffset(8), 4);
-        assert_eq!(buffer[ValidatedOffset(0)], Entry::used(4));
-        assert_eq!(buffer[ValidatedOffset(8)], Entry::used(4)); // <--
-        assert_eq!(buffer[ValidatedOffset(16)], Entry::used(4));
+        assert_eq!(buffer.entry(ValidatedOffset(0)), Entry::used(4));
+        assert_eq!(buffer.entry(ValidatedOffset(8)), Entry::used(4)); // <--
+        assert_eq!(buffer.entry(ValidatedOffset(16)), Entry::used(4));
     }
 
     #[test]
     fn mark_used_with_split() {
-        let mut buffer = Buffer::<32>::new();
+        let mut buffer = Buffer::<Array<32>>::new();
         buffer.ensure_initialization();
-        buffer.at_mut(0).write(Entry::used(4));
-        buffer.at_mut(8).write(Entry::free(20));
+        buffer.write_entry(0, Entry::used(4));
+        buffer.write_entry(8, Entry::free(20));
 
         // the entry to be marked as used is large enough to be splitted. There-
         // fore there must be a used and a free block after the call.
         buffer.mark_as_used(ValidatedOffset(8), 4);
-        assert_eq!(buffer[ValidatedOffset(0)], Entry::used(4));
-        assert_eq!(buffer[ValidatedOffset(8)], Entry::used(4)); // <--
-        assert_eq!(buffer[ValidatedOffset(16)], Entry::free(12)); // <--
+        assert_eq!(buffer.entry(ValidatedOffset(0)), Entry::used(4));
+        assert_eq!(buffer.entry(ValidatedOffset(8)), Entry::used(4)); // <--
+        assert_eq!(buffer.entry(ValidatedOffset(16)), Entry::free(12)); // <--
+    }
+
+    #[test]
+    fn words_in_memory() {
+        let mut buffer = Buffer::<Array<32>>::new();
+        buffer.ensure_initialization();
+        buffer.write_entry(0, Entry::used(12));
+
+        buffer.set_word_in(ValidatedOffset(0), 0, 42);
+        buffer.set_link_in(ValidatedOffset(0), 1, Some(ValidatedOffset(16)));
+        buffer.set_link_in(ValidatedOffset(0), 2, None);
+        assert_eq!(buffer.word_in(ValidatedOffset(0), 0), 42);
+        assert_eq!(
+            buffer.link_in(ValidatedOffset(0), 1),
+            Some(ValidatedOffset(16))
+        );
+        assert_eq!(buffer.link_in(ValidatedOffset(0), 2), None);
+    }
+
+    #[test]
+    fn move_to_preceding() {
+        let mut buffer = Buffer::<Array<32>>::new();
+        buffer.ensure_initialization();
+        buffer.write_free_entry(0, 8, false);
+        buffer.write_entry(12, Entry::used(4).with_preceding_free(true));
+        buffer.write_entry(20, Entry::used(8));
+        write_bytes(buffer.memory_of_mut(ValidatedOffset(12)), &[1, 2, 3, 4]);
+
+        let remainder = buffer.move_to_preceding(ValidatedOffset(12), ValidatedOffset(0));
+        assert_eq!(remainder, ValidatedOffset(8));
+        assert_eq!(buffer.entry(ValidatedOffset(0)), Entry::used(4));
+        assert_eq!(buffer.entry(ValidatedOffset(8)), Entry::free(8));
+        let memory = buffer.memory_of(ValidatedOffset(0));
+        // SAFETY: the memory was initialized above
+        let memory: Vec<u8> = memory
+            .iter()
+            .map(|byte| unsafe { byte.assume_init() })
+            .collect();
+        assert_eq!(memory, [1, 2, 3, 4]);
     }
 }
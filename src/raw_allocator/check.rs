@@ -0,0 +1,67 @@
+//! Module providing the errors of the consistency check of a heap.
+//!
+//! The check walks over the blocks of the heap and validates their headers
+//! against each other (see [`Buffer::check_entry()`]). Since the headers are
+//! the only metadata of the heap, any corruption (e.g. a buffer overflow of
+//! an allocation into the following header) is detected there, if it is
+//! detectable at all. Overflows, that do not reach the following header, are
+//! only detected by the red zones, if they are enabled.
+//!
+//! [`Buffer::check_entry()`]: super::buffer::Buffer::check_entry
+
+/// A problem found by the consistency check of the heap.
+///
+/// The `address` of each variant is the address of the memory of the affected
+/// block (i.e. directly behind its header), like in a
+/// [`BlockInfo`](super::BlockInfo). The header of the block is therefore the
+/// one directly in front of that address.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CheckError {
+    /// The block with the given size does not fit into the heap.
+    OutOfBounds {
+        /// The address of the memory of the block.
+        address: usize,
+        /// The size of the block according to its header.
+        size: usize,
+    },
+    /// The block with the given size does not end at a multiple of the
+    /// granule, so that the following header is misplaced (or the last block
+    /// does not end exactly at the end of the heap).
+    Misaligned {
+        /// The address of the memory of the block.
+        address: usize,
+        /// The size of the block according to its header.
+        size: usize,
+    },
+    /// The block and the block following it are both free, i.e. they were
+    /// not merged.
+    UncoalescedFreeBlocks {
+        /// The address of the memory of the first of the two blocks.
+        address: usize,
+    },
+    /// The header of the block does not know the correct state of the block
+    /// preceding it.
+    InconsistentPrecedingState {
+        /// The address of the memory of the block.
+        address: usize,
+    },
+    /// The boundary tag at the end of the free block does not match its
+    /// header.
+    InvalidBoundaryTag {
+        /// The address of the memory of the block.
+        address: usize,
+    },
+    /// The guard bytes around an allocation were overwritten, i.e. there was
+    /// a buffer overflow (only detected, if red zones are enabled).
+    ///
+    /// Unlike the other variants, the address and size are the ones of the
+    /// allocation itself (i.e. without the red zones). If the metadata of the
+    /// red zones was overwritten as well, the ones of the whole block are
+    /// given instead.
+    RedZoneViolation {
+        /// The address of the allocation.
+        address: usize,
+        /// The size of the allocation.
+        size: usize,
+    },
+}
@@ -0,0 +1,499 @@
+//! Module providing the handle-based API of the [`RawAllocator`], which allows
+//! to move memory blocks in order to compact the heap.
+//!
+//! A block allocated via a handle is not referenced by a pointer, but by the
+//! index of a slot in a table of handles. Each slot consists of two words: the
+//! link to the block (or an empty link, if the slot is vacant) and the number
+//! of locks on the block. The table is stored in a used block inside of the
+//! heap, so that it is only present, if the handle-based API is used at all.
+//! If the table is full, a table with twice the number of slots is allocated
+//! and the old one is released.
+//!
+//! Blocks, that are not locked, can be moved by the allocator, since their
+//! address is not known outside of the allocator. Compacting the heap moves
+//! those blocks into the free block directly in front of them. This way the
+//! free memory is collected behind them, until it hits a block, that cannot
+//! be moved (a locked block or a block allocated via a pointer).
+use super::buffer::{Buffer, Link, ValidatedOffset};
+use super::memory::Memory;
+use super::{FreeError, Header, RawAllocator, Strategy};
+
+use core::mem::MaybeUninit;
+
+/// The number of words of a single slot of the table of handles.
+const SLOT_WORDS: usize = 2;
+
+/// The index of the word holding the link to the block inside of a slot.
+const BLOCK: usize = 0;
+
+/// The index of the word holding the number of locks inside of a slot.
+const LOCKS: usize = 1;
+
+/// The number of slots of the first table of handles.
+const INITIAL_SLOTS: usize = 4;
+
+/// The maximum number of nested locks of a single handle.
+///
+/// This has to fit into a word of the smallest header format.
+const MAX_LOCKS: usize = u16::MAX as usize;
+
+/// The tag assigned to the allocator, that allocated its first handle most
+/// recently.
+///
+/// The tags are handed out in ascending order, so that the handles of
+/// different allocators can be told apart (see
+/// [`RawAllocator::handle_tag()`]). This is zero-initialized like the
+/// allocators, `0` is never used as a tag.
+static LAST_HANDLE_TAG: spin::Mutex<usize> = spin::Mutex::new(0);
+
+impl<M: Memory, S: Strategy, H: Header> RawAllocator<M, S, H> {
+    /// Allocate a new movable memory block of size `n` with the alignment
+    /// `align` and return its handle.
+    ///
+    /// The block is allocated like with [`alloc()`](Self::alloc), but its
+    /// memory can only be accessed while the handle is locked (see
+    /// [`lock()`](Self::lock)). The alignment has to be a power of two and
+    /// must not exceed the granule, since a moved block is only aligned to the
+    /// granule.
+    ///
+    /// If the allocation fails, `None` will be returned.
+    pub fn alloc_handle(&mut self, n: usize, align: usize) -> Option<usize> {
+        self.ensure_initialization();
+        if align > Buffer::<M, H>::ALIGNMENT {
+            return None;
+        }
+
+        let handle = self.vacant_handle()?;
+        let table = self.handles.get()?;
+        let offset = self.allocate_entry(self.guarded_size(n, align), align)?;
+        self.guard(offset, n, align);
+        self.buffer
+            .set_link_in(table, SLOT_WORDS * handle + BLOCK, Some(offset));
+        self.buffer
+            .set_word_in(table, SLOT_WORDS * handle + LOCKS, 0);
+        Some(handle)
+    }
+
+    /// Lock the block of the given handle and return its memory.
+    ///
+    /// The block is not moved, until it is unlocked as often as it was locked.
+    /// If the handle is invalid or if the maximum number of nested locks (the
+    /// largest 16-bit number) is reached, `None` is returned.
+    pub fn lock(&mut self, handle: usize) -> Option<&mut [MaybeUninit<u8>]> {
+        self.ensure_initialization();
+
+        let offset = self.block_of(handle)?;
+        let table = self.handles.get()?;
+        let locks = self.buffer.word_in(table, SLOT_WORDS * handle + LOCKS);
+        if locks >= MAX_LOCKS {
+            return None;
+        }
+        self.buffer
+            .set_word_in(table, SLOT_WORDS * handle + LOCKS, locks + 1);
+        Some(self.allocation_of(offset))
+    }
+
+    /// Unlock the block of the given handle, that was locked before.
+    ///
+    /// This returns `false`, if the handle is invalid or not locked at all.
+    pub fn unlock(&mut self, handle: usize) -> bool {
+        self.ensure_initialization();
+
+        let table = match (self.block_of(handle), self.handles.get()) {
+            (Some(_), Some(table)) => table,
+            _ => return false,
+        };
+        let locks = self.buffer.word_in(table, SLOT_WORDS * handle + LOCKS);
+        if locks == 0 {
+            return false;
+        }
+        self.buffer
+            .set_word_in(table, SLOT_WORDS * handle + LOCKS, locks - 1);
+        true
+    }
+
+    /// Free the block of the given handle.
+    ///
+    /// The block is freed regardless of its locks. Afterwards the handle is
+    /// vacant and may be returned by a later call to
+    /// [`alloc_handle()`](Self::alloc_handle). If the handle is invalid (e.g.
+    /// it was freed already), [`FreeError::InvalidHandle`] is returned.
+    /// If the red zones of the block were overwritten,
+    /// [`FreeError::RedZoneViolation`] is returned after freeing it.
+    pub fn free_handle(&mut self, handle: usize) -> Result<(), FreeError> {
+        self.ensure_initialization();
+
+        let offset = self.block_of(handle).ok_or(FreeError::InvalidHandle)?;
+        let table = self.handles.get().ok_or(FreeError::InvalidHandle)?;
+        self.buffer
+            .set_link_in(table, SLOT_WORDS * handle + BLOCK, None);
+        self.buffer
+            .set_word_in(table, SLOT_WORDS * handle + LOCKS, 0);
+        let guards = self.verify_before_free(offset);
+        self.release(offset);
+        guards
+    }
+
+    /// Compact the heap by moving all blocks of unlocked handles towards the
+    /// start of the heap.
+    ///
+    /// # Algorithm
+    /// Each block of an unlocked handle, that is preceded by a free block, is
+    /// moved to the start of that free block. The free memory is placed behind
+    /// the moved block and merged with a following free block. This is repeated
+    /// until no block can be moved anymore. Blocks allocated by pointer (and
+    /// locked blocks) stay in place, so the free memory is collected in front
+    /// of them.
+    pub fn compact(&mut self) {
+        self.ensure_initialization();
+
+        let table = match self.handles.get() {
+            Some(table) => table,
+            None => return,
+        };
+        let mut moved = true;
+        while moved {
+            moved = false;
+            for handle in 0..self.slots(table) {
+                let offset = match self.buffer.link_in(table, SLOT_WORDS * handle + BLOCK) {
+                    Some(offset) => offset,
+                    None => continue,
+                };
+                if self.buffer.word_in(table, SLOT_WORDS * handle + LOCKS) != 0 {
+                    continue;
+                }
+                if let Some(offset) = self.move_to_preceding(offset) {
+                    self.buffer
+                        .set_link_in(table, SLOT_WORDS * handle + BLOCK, Some(offset));
+                    moved = true;
+                }
+            }
+        }
+    }
+
+    /// Query the tag of the handles of this allocator.
+    ///
+    /// The handles are just indices into the table of handles, so a handle of
+    /// another allocator might refer to a valid slot as well. Therefore each
+    /// allocator is assigned a distinct tag, when it is queried the first
+    /// time, which the caller stores next to the index of the handle. The tag
+    /// is never `0`. It stays the same when the allocator is moved.
+    pub fn handle_tag(&mut self) -> usize {
+        if self.handle_tag == 0 {
+            let mut last_tag = LAST_HANDLE_TAG.lock();
+            // the tags only repeat after `usize::MAX` allocators
+            *last_tag = last_tag.wrapping_add(1).max(1);
+            self.handle_tag = *last_tag;
+        }
+        self.handle_tag
+    }
+
+    /// Check, whether a handle with the given tag belongs to this allocator.
+    ///
+    /// This is the case, if the tag was obtained by
+    /// [`handle_tag()`](Self::handle_tag) of this allocator.
+    pub const fn has_handle_tag(&self, tag: usize) -> bool {
+        tag != 0 && tag == self.handle_tag
+    }
+
+    /// Query the number of slots of the given table of handles.
+    fn slots(&self, table: ValidatedOffset) -> usize {
+        self.buffer.entry(table).size() / (SLOT_WORDS * Self::HEADER_SIZE)
+    }
+
+    /// Query the block of the given handle, if the handle is valid.
+    fn block_of(&self, handle: usize) -> Option<ValidatedOffset> {
+        let table = self.handles.get()?;
+        if handle >= self.slots(table) {
+            return None;
+        }
+        self.buffer.link_in(table, SLOT_WORDS * handle + BLOCK)
+    }
+
+    /// Find a vacant slot in the table of handles, growing the table if it is
+    /// full.
+    fn vacant_handle(&mut self) -> Option<usize> {
+        let old_table = self.handles.get();
+        let old_slots = old_table.map_or(0, |table| self.slots(table));
+        if let Some(table) = old_table {
+            let vacant = (0..old_slots).find(|handle| {
+                self.buffer
+                    .link_in(table, SLOT_WORDS * handle + BLOCK)
+                    .is_none()
+            });
+            if vacant.is_some() {
+                return vacant;
+            }
+        }
+
+        // the table is full (or there is none), so a larger one is allocated.
+        // The handles of the old table are preserved, all other slots are
+        // vacant. The table is bookkeeping of the allocator, so it is not
+        // recorded in the statistics.
+        let size = (2 * old_slots).max(INITIAL_SLOTS) * SLOT_WORDS * Self::HEADER_SIZE;
+        let table = self.place_entry(size, 1)?;
+        for word in 0..self.slots(table) * SLOT_WORDS {
+            let value = match old_table {
+                Some(old_table) if word < old_slots * SLOT_WORDS => {
+                    self.buffer.word_in(old_table, word)
+                }
+                _ => 0,
+            };
+            self.buffer.set_word_in(table, word, value);
+        }
+        if let Some(old_table) = old_table {
+            self.free_entry(old_table);
+        }
+        self.handles = Link::new(Some(table));
+        Some(old_slots)
+    }
+
+    /// Move the used block at the given offset into the preceding free block,
+    /// if there is one.
+    ///
+    /// The new offset of the block is returned.
+    fn move_to_preceding(&mut self, offset: ValidatedOffset) -> Option<ValidatedOffset> {
+        let preceding = self.buffer.preceding_free_entry(offset)?;
+        self.strategy.remove(&mut self.buffer, preceding);
+
+        // the following free entry is merged with the free memory behind the
+        // moved block, so it is no longer a free list entry.
+        let following = self.buffer.following_free_entry(offset);
+        let additional_memory = following.map_or(0, |following| {
+            self.strategy.remove(&mut self.buffer, following);
+            self.buffer.entry(following).size() + Self::HEADER_SIZE
+        });
+
+        let remainder = self.buffer.move_to_preceding(offset, preceding);
+        let size = self.buffer.entry(remainder).size() + additional_memory;
+        self.buffer.mark_as_free(remainder, size);
+        self.strategy.insert(&mut self.buffer, remainder);
+        Some(preceding)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::entry::State;
+    use super::super::{Array, BestFit, Header32, RawAllocator, Strategy, Tlsf};
+    use super::Header;
+    use crate::raw_allocator::FreeError;
+
+    /// Collect the state and size of all entries of the allocator.
+    fn entries<const N: usize, S: Strategy, H: Header>(
+        allocator: &RawAllocator<Array<N>, S, H>,
+    ) -> Vec<(State, usize)> {
+        allocator
+            .buffer
+            .entries()
+            .map(|offset| allocator.buffer.entry(offset))
+            .map(|entry| (entry.state(), entry.size()))
+            .collect()
+    }
+
+    /// Fill the memory of the given handle with its index.
+    fn fill<const N: usize>(allocator: &mut RawAllocator<Array<N>>, handle: usize) {
+        let memory = allocator.lock(handle).unwrap();
+        #[allow(clippy::cast_possible_truncation)] // the handles are small
+        memory.fill(core::mem::MaybeUninit::new(handle as u8));
+        assert!(allocator.unlock(handle));
+    }
+
+    /// Check, that the memory of the given handle is filled with its index.
+    fn check<const N: usize>(allocator: &mut RawAllocator<Array<N>>, handle: usize) {
+        let memory = allocator.lock(handle).unwrap();
+        for byte in memory {
+            // SAFETY: the memory was initialized by `fill()`
+            assert_eq!(usize::from(unsafe { byte.assume_init() }), handle);
+        }
+        assert!(allocator.unlock(handle));
+    }
+
+    #[test]
+    fn handle_table_is_allocated_lazily() {
+        let mut allocator = RawAllocator::<Array<136>>::new();
+        allocator.alloc(8, 4).unwrap();
+        assert_eq!(entries(&allocator), [(State::Used, 8), (State::Free, 112)]);
+
+        // the table has 4 slots of two words each
+        assert_eq!(allocator.alloc_handle(8, 4), Some(0));
+        assert_eq!(
+            entries(&allocator),
+            [
+                (State::Used, 8),
+                (State::Used, 32),
+                (State::Used, 8),
+                (State::Free, 64)
+            ]
+        );
+    }
+
+    #[test]
+    fn handles_are_reused() {
+        let mut allocator = RawAllocator::<Array<128>>::new();
+        assert_eq!(allocator.alloc_handle(4, 4), Some(0));
+        assert_eq!(allocator.alloc_handle(4, 4), Some(1));
+        assert_eq!(allocator.free_handle(0), Ok(()));
+        assert_eq!(allocator.free_handle(0), Err(FreeError::InvalidHandle));
+        assert_eq!(allocator.alloc_handle(4, 4), Some(0));
+        assert_eq!(allocator.alloc_handle(4, 4), Some(2));
+    }
+
+    #[test]
+    fn handle_table_grows() {
+        let mut allocator = RawAllocator::<Array<268>>::new();
+        for handle in 0..5 {
+            assert_eq!(allocator.alloc_handle(4, 4), Some(handle));
+            fill(&mut allocator, handle);
+        }
+        // the old table of 4 slots was released, the new one has 8 slots. The
+        // last handle reuses the memory of the old table.
+        assert_eq!(
+            entries(&allocator),
+            [
+                (State::Used, 4),
+                (State::Free, 24),
+                (State::Used, 4),
+                (State::Used, 4),
+                (State::Used, 4),
+                (State::Used, 4),
+                (State::Used, 64),
+                (State::Free, 116),
+            ]
+        );
+        for handle in 0..5 {
+            check(&mut allocator, handle);
+        }
+    }
+
+    #[test]
+    fn invalid_handles() {
+        let mut allocator = RawAllocator::<Array<128>>::new();
+        assert!(allocator.lock(0).is_none());
+        assert!(!allocator.unlock(0));
+        assert_eq!(allocator.free_handle(0), Err(FreeError::InvalidHandle));
+
+        allocator.alloc_handle(4, 4).unwrap();
+        assert!(allocator.lock(1).is_none());
+        assert!(allocator.lock(4).is_none());
+        assert!(!allocator.unlock(0)); // not locked
+    }
+
+    #[test]
+    fn alignment_is_limited_to_the_granule() {
+        let mut allocator = RawAllocator::<Array<128>>::new();
+        assert_eq!(allocator.alloc_handle(4, 8), None);
+
+        let mut allocator = RawAllocator::<Array<128>, BestFit, Header32<8>>::new();
+        assert_eq!(allocator.alloc_handle(4, 8), Some(0));
+    }
+
+    #[test]
+    fn compaction_moves_unlocked_blocks() {
+        let mut allocator = RawAllocator::<Array<136>>::new();
+        for handle in 0..4 {
+            allocator.alloc_handle(8, 4).unwrap();
+            fill(&mut allocator, handle);
+        }
+        allocator.free_handle(0).unwrap();
+        allocator.free_handle(2).unwrap();
+        assert_eq!(
+            entries(&allocator),
+            [
+                (State::Used, 32),
+                (State::Free, 8),
+                (State::Used, 8),
+                (State::Free, 8),
+                (State::Used, 8),
+                (State::Free, 40),
+            ]
+        );
+
+        allocator.compact();
+        assert_eq!(
+            entries(&allocator),
+            [
+                (State::Used, 32),
+                (State::Used, 8),
+                (State::Used, 8),
+                (State::Free, 64)
+            ]
+        );
+        check(&mut allocator, 1);
+        check(&mut allocator, 3);
+    }
+
+    #[test]
+    fn compaction_keeps_locked_and_pointer_blocks() {
+        let mut allocator = RawAllocator::<Array<136>>::new();
+        assert_eq!(allocator.alloc_handle(8, 4), Some(0));
+        assert_eq!(allocator.alloc_handle(8, 4), Some(1));
+        allocator.alloc(4, 4).unwrap();
+        assert_eq!(allocator.alloc_handle(4, 4), Some(2));
+        assert_eq!(allocator.alloc_handle(4, 4), Some(3));
+        for handle in 0..4 {
+            fill(&mut allocator, handle);
+        }
+        allocator.free_handle(0).unwrap();
+        allocator.free_handle(2).unwrap();
+        allocator.lock(1).unwrap();
+
+        // the locked block 1 cannot be moved, the block allocated by pointer
+        // is never moved, but block 3 is moved towards it.
+        allocator.compact();
+        assert_eq!(
+            entries(&allocator),
+            [
+                (State::Used, 32),
+                (State::Free, 8),
+                (State::Used, 8),
+                (State::Used, 4),
+                (State::Used, 4),
+                (State::Free, 48),
+            ]
+        );
+
+        // once unlocked, the block can be moved as well
+        assert!(allocator.unlock(1));
+        allocator.compact();
+        assert_eq!(
+            entries(&allocator),
+            [
+                (State::Used, 32),
+                (State::Used, 8),
+                (State::Free, 8),
+                (State::Used, 4),
+                (State::Used, 4),
+                (State::Free, 48),
+            ]
+        );
+        check(&mut allocator, 1);
+        check(&mut allocator, 3);
+    }
+
+    #[test]
+    fn tlsf_compaction() {
+        let mut allocator = RawAllocator::<Array<268>, Tlsf>::new();
+        let handles: Vec<_> = (0..4)
+            .map(|_| allocator.alloc_handle(12, 4).unwrap())
+            .collect();
+        for handle in handles.iter().step_by(2) {
+            allocator.free_handle(*handle).unwrap();
+        }
+
+        allocator.compact();
+        assert_eq!(
+            entries(&allocator),
+            [
+                (State::Used, 32),
+                (State::Used, 12),
+                (State::Used, 12),
+                (State::Free, 184),
+            ]
+        );
+        for handle in handles.into_iter().skip(1).step_by(2) {
+            allocator.free_handle(handle).unwrap();
+        }
+        assert_eq!(entries(&allocator), [(State::Used, 32), (State::Free, 216)]);
+    }
+}
@@ -27,30 +27,54 @@ pub enum State {
 /// ```
 /// # use core::mem;
 /// enum NaiveEntry {
-///     Used(u32),
-///     Free(u32),
+///     Used(usize),
+///     Free(usize),
 /// }
 ///
 /// // mem::size_of::<Entry>() is not possible in doc-tests, since the struct is not exposed
-/// assert!(mem::size_of::<NaiveEntry>() > mem::size_of::<u32>());
+/// assert!(mem::size_of::<NaiveEntry>() > mem::size_of::<usize>());
 /// ```
 /// This struct is used heavily in the allocator and thus has to be optimized.
 /// It is used as a header for blocks of allocated or unallocated memory inside
-/// the heap buffer. Entries are written directly into the buffer, therefore
-/// their layout is important.
-#[repr(transparent)]
+/// the heap buffer. Entries are written into the buffer in their raw form,
+/// which is truncated to the size of the [`Header`](super::Header) format (the
+/// buffer ensures, that the block sizes fit into the header).
+///
+/// In the raw form the size is a multiple of `4`, so the lowest two bits of the
+/// size are always zero. Those are used to store the state of the block (bit
+/// `0`) and whether the block _preceding_ this one is free (bit `1`). The
+/// latter is necessary to coalesce a freed block with its left neighbor: a
+/// free block stores a copy of its header in its last bytes (a so-called
+/// boundary tag), so the header of the preceding block can be found, if it is
+/// known to be free. The compact [`Header16`](super::Header16) format uses
+/// block sizes, that are `2` less than a multiple of `4`. Those are stored with
+/// a _size bias_ of `2` added to the size, so that the same packing can be
+/// used.
+///
+/// The entry itself keeps the size above the two flag bits, so that it can
+/// represent the sizes of all header formats.
 #[derive(Clone, Copy, PartialEq, Eq)]
-pub struct Entry(u32);
+pub struct Entry(usize);
 impl Entry {
+    /// The bit marking a block as used.
+    const USED: usize = 0b01;
+    /// The bit marking, that the preceding block is free.
+    const PRECEDING_FREE: usize = 0b10;
+    /// The bits, that are not part of the block size.
+    const FLAGS: usize = Self::USED | Self::PRECEDING_FREE;
+    /// The number of bits, that are not part of the block size.
+    const FLAG_BITS: u32 = 2;
+
     /// Create a new free [`Entry`] with the given size.
     ///
     /// Note, that the `size` is the number of bytes of the payload, i.e. the
     /// size after the `Entry` itself. This is the same value as returned by
     /// [`size()`](Entry::size()).
+    ///
+    /// # Panics
+    /// This function panics, if the size is not a multiple of `2`.
     pub const fn free(size: usize) -> Self {
-        assert!(size <= 0x7FFF_FFFF);
-        #[allow(clippy::cast_possible_truncation)] // asserted above
-        Self((size << 1) as _)
+        Self(Self::checked_size(size))
     }
 
     /// Create a new occupied/used [`Entry`] with the given size.
@@ -58,15 +82,32 @@ impl Entry {
     /// Note, that the `size` is the number of bytes of the payload, i.e. the
     /// size after the `Entry` itself. This is the same value as returned by
     /// [`size()`](Entry::size()).
+    ///
+    /// # Panics
+    /// This function panics, if the size is not a multiple of `2`.
     pub const fn used(size: usize) -> Self {
-        assert!(size <= 0x7FFF_FFFF);
-        #[allow(clippy::cast_possible_truncation)] // asserted above
-        Self((size << 1 | 1) as _)
+        Self(Self::checked_size(size) | Self::USED)
+    }
+
+    /// Validate a block size and convert it to the internal representation.
+    const fn checked_size(size: usize) -> usize {
+        assert!(size % 2 == 0, "block size has to be a multiple of 2");
+        size << Self::FLAG_BITS
+    }
+
+    /// Create an entry from its raw representation, e.g. read from a buffer.
+    ///
+    /// The `size_bias` is subtracted from the raw size (see the type-level
+    /// documentation). A raw size smaller than the bias, which is only possible
+    /// for data, that is not an entry, results in a bogus size.
+    pub const fn from_raw(raw: usize, size_bias: usize) -> Self {
+        let size = (raw & !Self::FLAGS).wrapping_sub(size_bias);
+        Self(size << Self::FLAG_BITS | raw & Self::FLAGS)
     }
 
     /// Query the allocation state of this block.
     pub const fn state(self) -> State {
-        if self.0 & 1 == 0 {
+        if self.0 & Self::USED == 0 {
             State::Free
         } else {
             State::Used
@@ -78,13 +119,35 @@ impl Entry {
     /// This is the size of the usable memory, i.e. the header size is not
     /// included.
     pub const fn size(self) -> usize {
-        let size = self.0 >> 1;
-        size as _
+        self.0 >> Self::FLAG_BITS
     }
 
-    /// Query the raw bytes of this entry in native endian order.
-    pub const fn as_raw(self) -> [u8; 4] {
-        self.0.to_ne_bytes()
+    /// Query, whether the block directly preceding this one is free.
+    ///
+    /// If this is the case, the preceding block stores a copy of its header in
+    /// its last bytes, which is directly in front of this entry.
+    pub const fn is_preceded_by_free(self) -> bool {
+        self.0 & Self::PRECEDING_FREE != 0
+    }
+
+    /// Return a copy of this entry with the given preceding-block-state.
+    #[must_use]
+    pub const fn with_preceding_free(self, preceding_free: bool) -> Self {
+        if preceding_free {
+            Self(self.0 | Self::PRECEDING_FREE)
+        } else {
+            Self(self.0 & !Self::PRECEDING_FREE)
+        }
+    }
+
+    /// Query the raw representation of this entry.
+    ///
+    /// The `size_bias` is added to the size, which has to result in a multiple
+    /// of `4` (see the type-level documentation).
+    pub const fn as_raw(self, size_bias: usize) -> usize {
+        let size = self.size() + size_bias;
+        debug_assert!(size % 4 == 0);
+        size | self.0 & Self::FLAGS
     }
 }
 #[cfg(test)]
@@ -93,6 +156,7 @@ impl Debug for Entry {
         f.debug_struct("Entry")
             .field("state", &self.state())
             .field("size", &self.size())
+            .field("preceding_free", &self.is_preceded_by_free())
             .finish()
     }
 }
@@ -104,95 +168,136 @@ mod tests {
     #[test]
     fn equality() {
         assert_eq!(Entry::used(4), Entry::used(4));
-        assert_ne!(Entry::used(4), Entry::used(5));
+        assert_ne!(Entry::used(4), Entry::used(8));
 
         assert_eq!(Entry::free(4), Entry::free(4));
-        assert_ne!(Entry::free(4), Entry::free(5));
+        assert_ne!(Entry::free(4), Entry::free(8));
 
         assert_ne!(Entry::used(4), Entry::free(4));
-        assert_ne!(Entry::used(4), Entry::free(5));
+        assert_ne!(Entry::used(4), Entry::free(8));
 
         // now same with cloning
         assert_eq!(Entry::used(4).clone(), Entry::used(4));
-        assert_ne!(Entry::used(4).clone(), Entry::used(5));
+        assert_ne!(Entry::used(4).clone(), Entry::used(8));
 
         assert_eq!(Entry::free(4).clone(), Entry::free(4));
-        assert_ne!(Entry::free(4).clone(), Entry::free(5));
+        assert_ne!(Entry::free(4).clone(), Entry::free(8));
 
         assert_ne!(Entry::used(4).clone(), Entry::free(4));
-        assert_ne!(Entry::used(4).clone(), Entry::free(5));
+        assert_ne!(Entry::used(4).clone(), Entry::free(8));
     }
 
     #[test]
     fn entry_bitpacking_state() {
-        assert_eq!(Entry::free(5).state(), State::Free);
-        assert_eq!(Entry::used(5).state(), State::Used);
+        assert_eq!(Entry::free(4).state(), State::Free);
+        assert_eq!(Entry::used(4).state(), State::Used);
 
-        assert_eq!(Entry(0b00_0).state(), State::Free);
-        assert_eq!(Entry(0b00_1).state(), State::Used);
+        assert_eq!(Entry::from_raw(0b0_0_0, 0).state(), State::Free);
+        assert_eq!(Entry::from_raw(0b0_0_1, 0).state(), State::Used);
 
-        assert_eq!(Entry(0b10_0).state(), State::Free);
-        assert_eq!(Entry(0b10_1).state(), State::Used);
-        assert_eq!(Entry(0b11_1).state(), State::Used);
-        assert_eq!(Entry(0b11_0).state(), State::Free);
+        assert_eq!(Entry::from_raw(0b1_0_0, 0).state(), State::Free);
+        assert_eq!(Entry::from_raw(0b1_0_1, 0).state(), State::Used);
+        assert_eq!(Entry::from_raw(0b1_1_1, 0).state(), State::Used);
+        assert_eq!(Entry::from_raw(0b1_1_0, 0).state(), State::Free);
 
         // now the same with cloning
-        assert_eq!(Entry::free(5).state().clone(), State::Free);
-        assert_eq!(Entry::used(5).state().clone(), State::Used);
+        assert_eq!(Entry::free(4).state().clone(), State::Free);
+        assert_eq!(Entry::used(4).state().clone(), State::Used);
 
-        assert_eq!(Entry(0b00_0).state().clone(), State::Free);
-        assert_eq!(Entry(0b00_1).state().clone(), State::Used);
+        assert_eq!(Entry::from_raw(0b0_0_0, 0).state().clone(), State::Free);
+        assert_eq!(Entry::from_raw(0b0_0_1, 0).state().clone(), State::Used);
 
-        assert_eq!(Entry(0b10_0).state().clone(), State::Free);
-        assert_eq!(Entry(0b10_1).state().clone(), State::Used);
-        assert_eq!(Entry(0b11_1).state().clone(), State::Used);
-        assert_eq!(Entry(0b11_0).state().clone(), State::Free);
+        assert_eq!(Entry::from_raw(0b1_0_0, 0).state().clone(), State::Free);
+        assert_eq!(Entry::from_raw(0b1_0_1, 0).state().clone(), State::Used);
+        assert_eq!(Entry::from_raw(0b1_1_1, 0).state().clone(), State::Used);
+        assert_eq!(Entry::from_raw(0b1_1_0, 0).state().clone(), State::Free);
     }
 
     #[test]
     fn entry_bitpacking_size() {
-        assert_eq!(Entry(0b1_1).size(), 1);
-        assert_eq!(Entry(0b1_0).size(), 1);
-        assert_eq!(Entry(123 << 1).size(), 123);
-        assert_eq!(Entry(123 << 1 | 1).size(), 123);
+        assert_eq!(Entry::from_raw(0b1_1_1, 0).size(), 4);
+        assert_eq!(Entry::from_raw(0b1_0_0, 0).size(), 4);
+        assert_eq!(Entry::from_raw(124 | 0b1_1, 0).size(), 124);
+        assert_eq!(Entry::from_raw(124, 0).size(), 124);
+    }
+
+    #[test]
+    fn entry_bitpacking_preceding_free() {
+        assert!(!Entry::from_raw(0b1_0_0, 0).is_preceded_by_free());
+        assert!(!Entry::from_raw(0b1_0_1, 0).is_preceded_by_free());
+        assert!(Entry::from_raw(0b1_1_0, 0).is_preceded_by_free());
+        assert!(Entry::from_raw(0b1_1_1, 0).is_preceded_by_free());
+
+        // the flag must neither influence the state nor the size
+        let entry = Entry::used(12).with_preceding_free(true);
+        assert!(entry.is_preceded_by_free());
+        assert_eq!(entry.state(), State::Used);
+        assert_eq!(entry.size(), 12);
+
+        let entry = entry.with_preceding_free(false);
+        assert_eq!(entry, Entry::used(12));
+    }
+
+    #[test]
+    fn raw_representation() {
+        let entry = Entry::used(12).with_preceding_free(true);
+        assert_eq!(entry.as_raw(0), 12 | 0b1_1);
+        assert_eq!(Entry::from_raw(entry.as_raw(0), 0), entry);
     }
 
     #[test]
-    fn alignment() {
-        use core::mem;
+    fn biased_raw_representation() {
+        // the compact format stores sizes, that are 2 less than a multiple of 4
+        let entry = Entry::used(10).with_preceding_free(true);
+        assert_eq!(entry.as_raw(2), 12 | 0b1_1);
+        assert_eq!(Entry::from_raw(12 | 0b1_1, 2), entry);
 
-        // other parts of this crate might assume, that this type has the same
-        // alignment as a `u32`, i.e. `4`
-        assert_eq!(mem::align_of::<Entry>(), mem::align_of::<u32>());
+        let entry = Entry::free(2);
+        assert_eq!(entry.as_raw(2), 0b1_0_0);
+        assert_eq!(Entry::from_raw(0b1_0_0, 2), entry);
+        assert_eq!(Entry::from_raw(0b1_0_0, 2).state(), State::Free);
+        assert!(!Entry::from_raw(0b1_0_0, 2).is_preceded_by_free());
+    }
+
+    #[test]
+    fn compact_size_limits() {
+        // the largest size, that fits into 16 bits including the bias
+        let entry = Entry::used(0xFFFA).with_preceding_free(true);
+        assert_eq!(entry.as_raw(2), 0xFFFF);
+        assert_eq!(Entry::from_raw(0xFFFF, 2), entry);
+        assert_eq!(Entry::from_raw(0xFFFF, 2).size(), 0xFFFA);
     }
 
     #[test]
     fn large_entries() {
-        Entry::free((1 << 31) - 4);
-        Entry::used((1 << 31) - 4);
+        Entry::free(0xFFFF_FFFC);
+        Entry::used(0xFFFF_FFFC);
     }
 
     #[test]
-    #[should_panic]
-    fn huge_free_block() {
-        Entry::free(1 << 31); // panic here
+    #[cfg(target_pointer_width = "64")]
+    fn huge_entries() {
+        // the size is only limited by the header format, not by the entry
+        assert_eq!(Entry::free(1 << 32).size(), 1 << 32);
+        assert_eq!(Entry::used(1 << 40).size(), 1 << 40);
+        assert_eq!(Entry::used(1 << 40).state(), State::Used);
     }
 
     #[test]
-    #[should_panic]
-    fn huge_used_block() {
-        Entry::used(1 << 31); // panic here
+    #[should_panic(expected = "multiple of 2")]
+    fn unaligned_block_size() {
+        Entry::free(5); // panic here
     }
 
     #[test]
     fn debug_representation() {
         assert_eq!(
-            format!("{:?}", Entry::used(123)),
-            "Entry { state: Used, size: 123 }"
+            format!("{:?}", Entry::used(124)),
+            "Entry { state: Used, size: 124, preceding_free: false }"
         );
         assert_eq!(
-            format!("{:?}", Entry::free(456)),
-            "Entry { state: Free, size: 456 }"
+            format!("{:?}", Entry::free(456).with_preceding_free(true)),
+            "Entry { state: Free, size: 456, preceding_free: true }"
         );
     }
 }
@@ -0,0 +1,222 @@
+//! Module providing the [`FreeList`], which keeps track of all free entries.
+//!
+//! The list itself is stored inside the free entries of the [`Buffer`]: every
+//! free entry contains the offset of the next free entry. Only the offset of
+//! the first free entry is stored outside of the heap memory. This allows the
+//! allocator to only visit free entries instead of all entries when searching
+//! for a suitable block.
+use super::buffer::{Buffer, ValidatedOffset};
+use super::header::Header;
+use super::memory::Memory;
+
+/// A singly linked list of free entries.
+///
+/// The list is unordered, if new entries are simply put in front of the others
+/// using [`push()`](Self::push). Using [`insert_sorted()`](Self::insert_sorted)
+/// instead keeps the entries sorted by their address.
+/// Free entries of size `0` are never part of the list, since they cannot hold
+/// a link to the next entry (and they are useless for allocations anyway).
+pub struct FreeList {
+    /// The first free entry or `None`, if there are no free entries.
+    ///
+    /// The empty list is represented by zeroes only, so that a freshly created
+    /// allocator can be placed in the `.bss`-section.
+    head: Option<ValidatedOffset>,
+}
+impl FreeList {
+    /// Create a new empty list.
+    pub const fn new() -> Self {
+        Self { head: None }
+    }
+
+    /// Add the free entry at the given offset to the list.
+    ///
+    /// Entries of size `0` are ignored, as they cannot be part of the list.
+    pub fn push<M: Memory, H: Header>(
+        &mut self,
+        buffer: &mut Buffer<M, H>,
+        offset: ValidatedOffset,
+    ) {
+        if buffer.entry(offset).size() >= Buffer::<M, H>::HEADER_SIZE {
+            buffer.set_next_free(offset, self.head);
+            self.head = Some(offset);
+        }
+    }
+
+    /// Add the free entry at the given offset to a list sorted by address.
+    ///
+    /// This searches the entry preceding the new one in the list, therefore the
+    /// run time is linear in the number of list elements. Entries of size `0`
+    /// are ignored, as they cannot be part of the list.
+    pub fn insert_sorted<M: Memory, H: Header>(
+        &mut self,
+        buffer: &mut Buffer<M, H>,
+        offset: ValidatedOffset,
+    ) {
+        if buffer.entry(offset).size() < Buffer::<M, H>::HEADER_SIZE {
+            return;
+        }
+
+        let previous = self
+            .iter(buffer)
+            .take_while(|previous| *previous < offset)
+            .last();
+        if let Some(previous) = previous {
+            let next = buffer.next_free(previous);
+            buffer.set_next_free(offset, next);
+            buffer.set_next_free(previous, Some(offset));
+        } else {
+            buffer.set_next_free(offset, self.head);
+            self.head = Some(offset);
+        }
+    }
+
+    /// Remove the free entry at the given offset from the list.
+    ///
+    /// This is done by searching the entry preceding the given one in the list
+    /// and connecting it with the entry following the given one. Entries of
+    /// size `0` are ignored, as they cannot be part of the list.
+    pub fn remove<M: Memory, H: Header>(
+        &mut self,
+        buffer: &mut Buffer<M, H>,
+        offset: ValidatedOffset,
+    ) {
+        if buffer.entry(offset).size() < Buffer::<M, H>::HEADER_SIZE {
+            return;
+        }
+
+        let next = buffer.next_free(offset);
+        if self.head == Some(offset) {
+            self.head = next;
+            return;
+        }
+
+        let previous = self
+            .iter(buffer)
+            .find(|previous| buffer.next_free(*previous) == Some(offset));
+        debug_assert!(previous.is_some(), "entry is not part of the free list");
+        if let Some(previous) = previous {
+            buffer.set_next_free(previous, next);
+        }
+    }
+
+    /// Iterate over all entries in the list.
+    pub const fn iter<'buffer, M: Memory, H: Header>(
+        &self,
+        buffer: &'buffer Buffer<M, H>,
+    ) -> FreeListIter<'buffer, M, H> {
+        FreeListIter {
+            buffer,
+            current: self.head,
+        }
+    }
+}
+
+/// An iterator over the entries of a [`FreeList`].
+pub struct FreeListIter<'buffer, M: Memory, H: Header> {
+    /// The memory containing the links between the list elements.
+    buffer: &'buffer Buffer<M, H>,
+    /// The entry, that is yielded next.
+    current: Option<ValidatedOffset>,
+}
+impl<'buffer, M: Memory, H: Header> Iterator for FreeListIter<'buffer, M, H> {
+    type Item = ValidatedOffset;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let current = self.current?;
+        self.current = self.buffer.next_free(current);
+        Some(current)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::buffer::{Buffer, ValidatedOffset};
+    use super::super::memory::Array;
+    use super::FreeList;
+
+    /// Create a buffer with the following layout and return the offsets:
+    /// `free(4) used(4) free(8) used(4) free(0) used(4) free(8)`
+    fn fragmented_buffer() -> (Buffer<Array<64>>, [ValidatedOffset; 4]) {
+        let mut buffer = Buffer::<Array<64>>::new();
+        let initial = buffer.ensure_initialization().unwrap();
+        let a = buffer.mark_as_used(initial, 4).unwrap();
+        let b = buffer.mark_as_used(a, 4).unwrap();
+        let c = buffer.mark_as_used(b, 8).unwrap();
+        let d = buffer.mark_as_used(c, 4).unwrap();
+        let e = buffer.mark_as_used(d, 0).unwrap();
+        let f = buffer.mark_as_used(e, 4).unwrap();
+        buffer.mark_as_free(initial, 4);
+        buffer.mark_as_free(b, 8);
+        buffer.mark_as_free(d, 0);
+        (buffer, [initial, b, d, f])
+    }
+
+    #[test]
+    fn empty_list() {
+        let buffer = Buffer::<Array<32>>::new();
+        let list = FreeList::new();
+        assert_eq!(list.iter(&buffer).next(), None);
+    }
+
+    #[test]
+    fn push_and_iterate() {
+        let (mut buffer, [a, b, c, d]) = fragmented_buffer();
+        let mut list = FreeList::new();
+        list.push(&mut buffer, a);
+        list.push(&mut buffer, b);
+        list.push(&mut buffer, c); // too small, therefore ignored
+        list.push(&mut buffer, d);
+
+        let entries = list.iter(&buffer).collect::<Vec<_>>();
+        assert_eq!(entries, [d, b, a]);
+    }
+
+    #[test]
+    fn remove() {
+        let (mut buffer, [a, b, c, d]) = fragmented_buffer();
+        let mut list = FreeList::new();
+        list.push(&mut buffer, a);
+        list.push(&mut buffer, b);
+        list.push(&mut buffer, c);
+        list.push(&mut buffer, d);
+
+        // remove from the middle, an ignored entry, the head and the last one
+        list.remove(&mut buffer, b);
+        assert_eq!(list.iter(&buffer).collect::<Vec<_>>(), [d, a]);
+        list.remove(&mut buffer, c);
+        assert_eq!(list.iter(&buffer).collect::<Vec<_>>(), [d, a]);
+        list.remove(&mut buffer, d);
+        assert_eq!(list.iter(&buffer).collect::<Vec<_>>(), [a]);
+        list.remove(&mut buffer, a);
+        assert_eq!(list.iter(&buffer).next(), None);
+    }
+
+    #[test]
+    fn insert_sorted() {
+        let (mut buffer, [a, b, c, d]) = fragmented_buffer();
+        let mut list = FreeList::new();
+        list.insert_sorted(&mut buffer, b);
+        list.insert_sorted(&mut buffer, d);
+        list.insert_sorted(&mut buffer, c); // too small, therefore ignored
+        list.insert_sorted(&mut buffer, a);
+        assert_eq!(list.iter(&buffer).collect::<Vec<_>>(), [a, b, d]);
+
+        // removal keeps the order of the remaining entries
+        list.remove(&mut buffer, b);
+        list.insert_sorted(&mut buffer, b);
+        assert_eq!(list.iter(&buffer).collect::<Vec<_>>(), [a, b, d]);
+    }
+
+    #[test]
+    fn small_blocks_can_still_be_coalesced() {
+        // the link of a free block of size 4 replaces its boundary tag, but the
+        // preceding block has to be found nevertheless.
+        let (mut buffer, [a, ..]) = fragmented_buffer();
+        let mut list = FreeList::new();
+        list.push(&mut buffer, a);
+
+        let used = buffer.entries().nth(1).unwrap();
+        assert_eq!(buffer.preceding_free_entry(used), Some(a));
+    }
+}
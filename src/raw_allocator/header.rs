@@ -0,0 +1,140 @@
+//! Module providing the [`Header`] trait, which selects the size of the block
+//! headers, and its implementations [`Header16`], [`Header32`] (the default),
+//! [`Header64`] and [`AutoHeader`].
+//!
+//! The header size limits the size of a single block: the size has to fit into
+//! the header together with the two flag bits of an [`Entry`](super::Entry).
+//! The same size is used for the boundary tags and the links between free
+//! blocks, which are stored inside of the heap memory as well.
+//!
+//! Each format takes the _granule_ as a const generic parameter: all blocks
+//! including their header occupy a multiple of the granule, so that the memory
+//! of all blocks is aligned to the granule. Allocations with an alignment up to
+//! the granule therefore never need any padding. Supported granules are `4`,
+//! `8` and `16` (but at least the header size).
+
+/// The largest heap size supported by the [`Header16`] format.
+const COMPACT_LIMIT: usize = 64 * 1024;
+
+/// The format of the block headers inside of the heap.
+///
+/// This trait is implemented by the header formats provided by this crate and
+/// cannot be implemented outside of this crate. The format is selected via the
+/// third type parameter of [`Allocator`](crate::Allocator).
+pub trait Header: sealed::Sealed {}
+
+/// A compact header format using 2 bytes per header.
+///
+/// This limits the heap size to 64KiB, which is a common size for small
+/// microcontrollers. Since the memory of all blocks has to be aligned to the
+/// granule (at least `4`), the block sizes are `2` less than a multiple of the
+/// granule (i.e. the block including its header occupies a multiple of the
+/// granule). Compared to the default [`Header32`] format this saves two bytes
+/// per block.
+pub struct Header16<const GRANULE: usize = 4>;
+
+/// The default header format using 4 bytes per header.
+///
+/// This limits the heap size to 4GiB, which is not an issue on 32-bit targets.
+/// Since many types on 64-bit targets have an alignment of `8`, a granule of
+/// `8` (i.e. `Header32<8>`) avoids the padding in front of those allocations.
+pub struct Header32<const GRANULE: usize = 4>;
+
+/// A header format using 8 bytes per header.
+///
+/// This lifts the limit of the heap size of [`Header32`], which is useful for
+/// heaps larger than 4GiB on 64-bit systems, e.g. when running a simulation of
+/// an embedded application on a PC. In exchange, each allocation needs twice as
+/// much memory for its header and the block sizes are rounded up to multiples
+/// of (at least) 8.
+pub struct Header64<const GRANULE: usize = 8>;
+
+/// A header format chosen from the heap size.
+///
+/// Heaps of up to 64KiB use the [`Header16`] format, larger heaps use the
+/// [`Header32`] format.
+pub struct AutoHeader<const GRANULE: usize = 4>;
+
+/// Compute the size of a single header of the format `H` for a heap of the
+/// given size.
+pub const fn header_size<H: Header>(heap_size: usize) -> usize {
+    if H::AUTO && heap_size <= COMPACT_LIMIT {
+        2
+    } else {
+        H::SIZE
+    }
+}
+
+/// A zero-sized type with an alignment of 8.
+#[repr(align(8))]
+pub struct Align8;
+
+/// A zero-sized type with an alignment of 16.
+#[repr(align(16))]
+pub struct Align16;
+
+/// Implement the [`Header`] trait for the given format and granules.
+macro_rules! impl_header {
+    ($format:ident, size = $size:expr, auto = $auto:expr, granules = [$($granule:literal => $alignment:ty),*]) => {$(
+        impl Header for $format<$granule> {}
+        impl sealed::Sealed for $format<$granule> {
+            const SIZE: usize = $size;
+            const AUTO: bool = $auto;
+            const GRANULE: usize = $granule;
+            type Alignment = $alignment;
+        }
+    )*};
+}
+// the buffer is always aligned to 4, so no additional alignment is necessary
+impl_header!(Header16, size = 2, auto = false, granules = [4 => u8, 8 => Align8, 16 => Align16]);
+impl_header!(Header32, size = 4, auto = false, granules = [4 => u8, 8 => Align8, 16 => Align16]);
+impl_header!(Header64, size = 8, auto = false, granules = [8 => Align8, 16 => Align16]);
+impl_header!(AutoHeader, size = 4, auto = true, granules = [4 => u8, 8 => Align8, 16 => Align16]);
+
+/// Helper module to prevent implementations of [`Header`] outside of this crate.
+pub(super) mod sealed {
+    /// The super-trait of [`Header`](super::Header), which is not nameable
+    /// outside of this crate.
+    pub trait Sealed {
+        /// The size of a single block header in bytes.
+        ///
+        /// If the format is chosen automatically, this is the size used for
+        /// large heaps.
+        const SIZE: usize;
+
+        /// Whether the compact format is used for small heaps.
+        const AUTO: bool;
+
+        /// The size, of which all blocks including their header are a multiple.
+        const GRANULE: usize;
+
+        /// A type with the alignment of the granule.
+        ///
+        /// The memory of the blocks is aligned to the granule, so the buffer
+        /// itself has to be aligned to (at least) the granule.
+        type Alignment;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{header_size, AutoHeader, Header16, Header32, Header64};
+
+    #[test]
+    fn fixed_sizes() {
+        assert_eq!(header_size::<Header16>(1024), 2);
+        assert_eq!(header_size::<Header32>(1024), 4);
+        assert_eq!(header_size::<Header64>(1024), 8);
+        assert_eq!(header_size::<Header32>(1 << 20), 4);
+        assert_eq!(header_size::<Header32<16>>(1024), 4);
+    }
+
+    #[test]
+    fn automatic_size() {
+        assert_eq!(header_size::<AutoHeader>(8), 2);
+        assert_eq!(header_size::<AutoHeader>(64 * 1024), 2);
+        assert_eq!(header_size::<AutoHeader>(64 * 1024 + 4), 4);
+        assert_eq!(header_size::<AutoHeader>(1 << 20), 4);
+        assert_eq!(header_size::<AutoHeader<8>>(1024), 2);
+    }
+}
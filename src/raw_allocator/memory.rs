@@ -0,0 +1,129 @@
+//! Module providing the [`Memory`] trait, which abstracts over the storage of
+//! the heap memory of a [`Buffer`](super::buffer::Buffer).
+//!
+//! The memory is either an array owned by the buffer (the size is known at
+//! compile time, so that the allocator can be a `static` placed in `.bss`) or
+//! a [`Region`] of memory provided at runtime (e.g. the RAM between the end of
+//! the static data and the stack, which is only known from linker symbols).
+use core::mem::MaybeUninit;
+use core::ptr::NonNull;
+
+/// The heap memory owned by a buffer with a size known at compile time.
+pub type Array<const N: usize> = [MaybeUninit<u8>; N];
+
+/// The storage of the heap memory.
+///
+/// The memory has to be initialized completely (e.g. zeroed) and its start has
+/// to be aligned to the granule of the header format.
+pub trait Memory {
+    /// The size of the memory, if it is known at compile time.
+    ///
+    /// This is used to choose the header size of the
+    /// [`AutoHeader`](super::AutoHeader) format. Memory with a size only known
+    /// at runtime uses `usize::MAX`, so that the larger header format is used.
+    const SIZE: usize;
+
+    /// Access the bytes of the memory.
+    fn bytes(&self) -> &[MaybeUninit<u8>];
+
+    /// Access the bytes of the memory mutably.
+    fn bytes_mut(&mut self) -> &mut [MaybeUninit<u8>];
+}
+impl<const N: usize> Memory for Array<N> {
+    const SIZE: usize = N;
+
+    fn bytes(&self) -> &[MaybeUninit<u8>] {
+        self
+    }
+
+    fn bytes_mut(&mut self) -> &mut [MaybeUninit<u8>] {
+        self
+    }
+}
+
+/// A region of memory, that is provided at runtime.
+///
+/// The region is borrowed for the whole runtime of the program, i.e. it is
+/// never given back. An empty region is used for allocators, that are not
+/// initialized yet.
+pub struct Region {
+    /// The start of the region.
+    start: NonNull<MaybeUninit<u8>>,
+    /// The size of the region in bytes.
+    size: usize,
+}
+// SAFETY: the region is borrowed exclusively (see `Region::new()`), so it can
+// be used from any thread.
+unsafe impl Send for Region {}
+impl Region {
+    /// The region without any memory.
+    pub const EMPTY: Self = Self {
+        start: NonNull::dangling(),
+        size: 0,
+    };
+
+    /// Create a region from its start and its size in bytes.
+    ///
+    /// # Safety
+    /// The memory has to be valid for reads and writes of `size` bytes and
+    /// must not be accessed by anything else than the returned region for the
+    /// rest of the program (like a `&'static mut [MaybeUninit<u8>]`).
+    pub const unsafe fn new(start: NonNull<MaybeUninit<u8>>, size: usize) -> Self {
+        Self { start, size }
+    }
+
+    /// Query the start address of the region.
+    pub fn start(&self) -> usize {
+        self.start.as_ptr() as usize
+    }
+
+    /// Query the size of the region in bytes.
+    pub const fn size(&self) -> usize {
+        self.size
+    }
+
+    /// Shrink the region to the `size` bytes starting at `offset`.
+    ///
+    /// # Panics
+    /// This function panics if the sub-region is not inside of this region.
+    pub fn sub_region(self, offset: usize, size: usize) -> Self {
+        assert!(offset <= self.size && size <= self.size - offset);
+        // SAFETY: the sub-region is part of this region, which is consumed
+        let start = unsafe { NonNull::new_unchecked(self.start.as_ptr().add(offset)) };
+        Self { start, size }
+    }
+
+    /// Append the given region, which directly follows this region.
+    ///
+    /// # Panics
+    /// This function panics if the given region does not start at the end of
+    /// this region.
+    #[allow(clippy::needless_pass_by_value)] // the region is merged into this one
+    pub fn append(&mut self, region: Self) {
+        assert_eq!(self.start() + self.size, region.start());
+        self.size += region.size;
+    }
+}
+impl From<&'static mut [MaybeUninit<u8>]> for Region {
+    fn from(memory: &'static mut [MaybeUninit<u8>]) -> Self {
+        let size = memory.len();
+        // SAFETY: the slice is valid for `size` bytes and is borrowed for the
+        // rest of the program.
+        unsafe { Self::new(NonNull::from(memory).cast(), size) }
+    }
+}
+impl Memory for Region {
+    const SIZE: usize = usize::MAX;
+
+    fn bytes(&self) -> &[MaybeUninit<u8>] {
+        // SAFETY: the memory is valid for `size` bytes and is borrowed by the
+        // region exclusively (safety contract of `Region::new()`)
+        unsafe { core::slice::from_raw_parts(self.start.as_ptr(), self.size) }
+    }
+
+    fn bytes_mut(&mut self) -> &mut [MaybeUninit<u8>] {
+        // SAFETY: the memory is valid for `size` bytes and is borrowed by the
+        // region exclusively (safety contract of `Region::new()`)
+        unsafe { core::slice::from_raw_parts_mut(self.start.as_ptr(), self.size) }
+    }
+}
@@ -1,11 +1,27 @@
 //! This module provides the raw allocator and its support types.
 //!
 //! A "raw allocator" is one, that simply gets request for a specific memory
-//! size but does not need to worry about alignment.
+//! size (and optionally an alignment) but does not need to worry about types
+//! or layouts.
 mod buffer;
+mod check;
+mod compaction;
 mod entry;
+mod free_list;
+mod header;
+mod memory;
+mod red_zone;
+mod stats;
+mod strategy;
+mod tlsf;
+
+pub use check::CheckError;
+pub use header::{AutoHeader, Header, Header16, Header32, Header64};
+pub use memory::{Array, Memory, Region};
+pub use stats::{BlockInfo, BlockState, Stats};
+pub use strategy::{BestFit, FirstFit, NextFit, Strategy, WorstFit};
+pub use tlsf::Tlsf;
 
-use buffer::HEADER_SIZE;
 use entry::{Entry, State};
 
 use core::mem::MaybeUninit;
@@ -19,6 +35,77 @@ pub enum FreeError {
     /// An invalid pointer was freed up (either a pointer outside of the heap
     /// memory or a pointer to a header).
     AllocationNotFound,
+    /// A handle was freed, that does not refer to a block of the allocator
+    /// (e.g. a handle of another allocator).
+    InvalidHandle,
+    /// The guard bytes around the freed allocation were overwritten, i.e. there
+    /// was a buffer overflow (only detected, if red zones are enabled). The
+    /// allocation is freed nevertheless.
+    RedZoneViolation {
+        /// The address of the allocation.
+        address: usize,
+        /// The size of the allocation.
+        size: usize,
+    },
+}
+
+/// The reason, why an allocation failed.
+///
+/// Each variant contains the total number of free bytes and the size of the
+/// largest free block of the heap at the time of the failure, so that the
+/// caller can retry with a smaller request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AllocError {
+    /// The heap does not have enough free memory for the allocation.
+    OutOfMemory {
+        /// The total number of free bytes.
+        free: usize,
+        /// The size of the largest free block.
+        largest_free: usize,
+    },
+    /// The heap has enough free memory in total, but it is split into blocks,
+    /// that are too small for the allocation. This is also reported, if a
+    /// large enough block is not considered by the placement strategy (e.g.
+    /// the [`Tlsf`] strategy only considers blocks of the next larger size
+    /// class).
+    Fragmented {
+        /// The total number of free bytes.
+        free: usize,
+        /// The size of the largest free block.
+        largest_free: usize,
+    },
+    /// There are free blocks large enough for the size of the allocation, but
+    /// none of them can hold it including the padding needed for its
+    /// alignment. This is also reported, if the placement strategy reserves
+    /// the worst-case padding and no block is large enough for that (e.g. the
+    /// [`Tlsf`] strategy for alignments above the granularity of the heap).
+    Alignment {
+        /// The total number of free bytes.
+        free: usize,
+        /// The size of the largest free block.
+        largest_free: usize,
+    },
+}
+impl AllocError {
+    /// Query the total number of free bytes at the time of the failure.
+    #[must_use]
+    pub const fn free(self) -> usize {
+        match self {
+            Self::OutOfMemory { free, .. }
+            | Self::Fragmented { free, .. }
+            | Self::Alignment { free, .. } => free,
+        }
+    }
+
+    /// Query the size of the largest free block at the time of the failure.
+    #[must_use]
+    pub const fn largest_free(self) -> usize {
+        match self {
+            Self::OutOfMemory { largest_free, .. }
+            | Self::Fragmented { largest_free, .. }
+            | Self::Alignment { largest_free, .. } => largest_free,
+        }
+    }
 }
 
 /// A raw memory allocator for contiguous slices of bytes without any alignment.
@@ -27,49 +114,267 @@ pub enum FreeError {
 /// alignment of a [`Layout`](core::alloc::Layout). This abstracts the parts
 /// "allocating of memory" and "getting a pointer with proper alignment".
 ///
-/// Note, that the allocated memory is always aligned to `4`.
-pub struct RawAllocator<const N: usize> {
+/// Note, that the allocated memory is always aligned to the granule of the
+/// header format `H` (`4` by default, see [`Header`]).
+///
+/// The choice of the free memory block for an allocation is delegated to the
+/// [`Strategy`] `S`, which keeps track of all free blocks. The format of the
+/// block headers is given by the [`Header`] `H`. The heap memory `M` is either
+/// an [`Array`] of a fixed size or a [`Region`] provided at runtime.
+pub struct RawAllocator<M, S = BestFit, H: Header = Header32> {
     /// The internal buffer abstracting over the raw bytes of the heap.
-    buffer: buffer::Buffer<N>,
+    buffer: buffer::Buffer<M, H>,
+    /// The placement strategy managing all free entries in the buffer.
+    strategy: S,
+    /// The used entry holding the table of handles, if there is one.
+    ///
+    /// See [`alloc_handle()`](Self::alloc_handle) for details.
+    handles: buffer::Link,
+    /// The tag of the handles of this allocator or `0`, if it has not
+    /// allocated a handle yet.
+    ///
+    /// See [`handle_tag()`](Self::handle_tag) for details.
+    handle_tag: usize,
+    /// The counters tracking the history of the heap for the statistics.
+    counters: stats::Counters,
+    /// Whether the allocations are surrounded by red zones.
+    ///
+    /// See [`enable_red_zones()`](Self::enable_red_zones) for details.
+    red_zones: bool,
 }
-impl<const N: usize> RawAllocator<N> {
+impl<const N: usize, S: Strategy, H: Header> RawAllocator<Array<N>, S, H> {
     /// Create a new [`RawAllocator`] with a given heap size.
     ///
     /// # Panics
-    /// This function panics if the buffer size is less than `8` (the minimum
-    /// useful allocation heap) or if it is not divisible by 4.
+    /// This function panics if the buffer size is less than two headers (the
+    /// minimum useful allocation heap, at least `8` bytes), if it is not
+    /// divisible by 4 or by the header size, or if it is too large for the
+    /// header format.
     pub const fn new() -> Self {
-        assert!(N >= 8, "too small heap memory: minimum size is 8");
+        assert!(
+            N >= 8 && N >= 2 * Self::HEADER_SIZE,
+            "too small heap memory: minimum size is two headers (8 bytes by default)"
+        );
         assert!(N % 4 == 0, "memory size has to be divisible by 4");
 
-        let buffer = buffer::Buffer::new();
-        Self { buffer }
+        Self::with_buffer(buffer::Buffer::new())
+    }
+}
+impl<S: Strategy, H: Header> RawAllocator<Region, S, H> {
+    /// A [`RawAllocator`] without any heap memory.
+    ///
+    /// All allocations fail, until the allocator is replaced by one created
+    /// with [`with_region()`](Self::with_region).
+    pub const EMPTY: Self = Self::with_buffer(buffer::Buffer::empty());
+
+    /// Create a new [`RawAllocator`] managing the given memory region.
+    ///
+    /// The start of the region is rounded up and its size is rounded down to
+    /// multiples of the granule of the header format.
+    ///
+    /// # Panics
+    /// This function panics if the region is too small to hold a single block
+    /// (two headers after the rounding) or if it is too large for the header
+    /// format.
+    pub fn with_region(region: Region) -> Self {
+        Self::with_buffer(buffer::Buffer::with_region(region))
     }
 
-    /// Allocate a new memory block of size `n`.
+    /// Extend the heap by the given memory region.
+    ///
+    /// The region has to start directly at the end of the heap memory,
+    /// otherwise (or if the allocator is [`EMPTY`](Self::EMPTY)) it is given
+    /// back. The appended memory becomes a free block. If the last block of the
+    /// heap is free, it is enlarged in place instead. Note, that the last block
+    /// is searched linearly, so this takes time proportional to the number of
+    /// blocks.
+    ///
+    /// # Panics
+    /// This function panics if the enlarged heap is too large for the header
+    /// format.
+    pub fn extend(&mut self, region: Region) -> Result<(), Region> {
+        self.ensure_initialization();
+
+        // the appended memory is not an allocation, so it is merged without
+        // being recorded as released.
+        if let Some(offset) = self.buffer.append(region)? {
+            self.free_entry(offset);
+        }
+        Ok(())
+    }
+
+    /// Query, whether the allocator has no heap memory, i.e. whether it is
+    /// the [`EMPTY`](Self::EMPTY) one.
+    pub fn is_empty(&self) -> bool {
+        self.buffer.end() == 0
+    }
+}
+impl<M: Memory, S: Strategy, H: Header> RawAllocator<M, S, H> {
+    /// The size of a single block header.
+    const HEADER_SIZE: usize = buffer::Buffer::<M, H>::HEADER_SIZE;
+
+    /// The minimum size of the memory of a block in bytes.
+    const MIN_BLOCK_SIZE: usize =
+        match buffer::Buffer::<M, H>::rounded_size(S::MIN_BLOCK_WORDS * Self::HEADER_SIZE) {
+            Some(size) => size,
+            None => 0, // unreachable: the minimum size is small
+        };
+
+    /// Create a new [`RawAllocator`] on top of the given buffer.
+    const fn with_buffer(buffer: buffer::Buffer<M, H>) -> Self {
+        let strategy = S::EMPTY;
+        let handles = buffer::Link::NONE;
+        let counters = stats::Counters::NEW;
+        Self {
+            buffer,
+            strategy,
+            handles,
+            handle_tag: 0,
+            counters,
+            red_zones: false,
+        }
+    }
+
+    /// Ensure, that the buffer is initialized.
+    ///
+    /// If the buffer is initialized by this call, the initial entry spanning
+    /// the whole buffer is registered in the strategy.
+    fn ensure_initialization(&mut self) {
+        if let Some(initial_entry) = self.buffer.ensure_initialization() {
+            self.strategy.insert(&mut self.buffer, initial_entry);
+        }
+    }
+
+    /// Allocate a new memory block of size `n` with the alignment `align`.
     ///
     /// This method is used for general allocation of multiple contiguous bytes.
-    /// It searches for the smallest possible free entry and mark it as "used".
-    /// As usual with [`RawAllocator`], this does not take alignment in account.
+    /// The [`Strategy`] searches for a free entry containing `n` bytes at a
+    /// suitably aligned address (e.g. the smallest possible free entry), which
+    /// is marked as "used". If that address is not at the start of the
+    /// entry, the memory in front of it is split off as a new free entry, so
+    /// that only the needed padding is lost (and it can be reused for other
+    /// allocations). The alignment has to be a power of two. If red zones are
+    /// enabled, the block is large enough for the guard bytes as well and only
+    /// the memory between them is returned.
     ///
     /// If the allocation fails, `None` will be returned.
-    pub fn alloc(&mut self, n: usize) -> Option<&mut [MaybeUninit<u8>]> {
-        self.buffer.ensure_initialization();
+    pub fn alloc(&mut self, n: usize, align: usize) -> Option<&mut [MaybeUninit<u8>]> {
+        self.ensure_initialization();
 
-        // round up `n` to next multiple of `size_of::<Entry>()`
-        let n = (n + HEADER_SIZE - 1) / HEADER_SIZE * HEADER_SIZE;
+        let offset = self.allocate_entry(self.guarded_size(n, align), align)?;
+        Some(self.guard(offset, n, align))
+    }
+
+    /// Allocate a new memory block of size `n` with the alignment `align`, that
+    /// is filled with zeroes.
+    ///
+    /// This works like [`alloc()`](Self::alloc), but the memory
+    /// is cleared. The heap memory is zero initially, therefore only the bytes,
+    /// that might have been written to before, are cleared. This makes
+    /// allocating large zeroed blocks cheap, as long as the heap memory was not
+    /// reused.
+    pub fn alloc_zeroed(&mut self, n: usize, align: usize) -> Option<&mut [MaybeUninit<u8>]> {
+        self.ensure_initialization();
 
-        let (offset, _) = self
+        // the untouched offset has to be queried before the allocation, as
+        // the allocation itself touches the memory of the entry.
+        let untouched = self.buffer.untouched();
+        let offset = self.allocate_entry(self.guarded_size(n, align), align)?;
+        self.buffer.zero_memory_of(offset, untouched);
+        Some(self.guard(offset, n, align))
+    }
+
+    /// Determine, why an allocation of `n` bytes with the alignment `align`
+    /// failed.
+    ///
+    /// This walks over all blocks of the heap, so it should only be called
+    /// after an allocation failed indeed.
+    pub fn alloc_error(&mut self, n: usize, align: usize) -> AllocError {
+        let Stats {
+            free, largest_free, ..
+        } = self.stats();
+        let size = buffer::Buffer::<M, H>::rounded_size(self.guarded_size(n, align))
+            .map(|n| n.max(Self::MIN_BLOCK_SIZE));
+        match size {
+            // a large enough block, that is not used, has to be unsuitable due
+            // to its alignment: either the block does not contain a suitably
+            // aligned address or the strategy did not consider it, as it
+            // reserves the worst-case padding for the alignment (e.g. `Tlsf`).
+            // Without extra alignment, only the strategy might overlook it.
+            Some(size) if largest_free >= size && align > buffer::Buffer::<M, H>::ALIGNMENT => {
+                AllocError::Alignment { free, largest_free }
+            }
+            Some(size) if largest_free >= size => AllocError::Fragmented { free, largest_free },
+            Some(size) if free >= size => AllocError::Fragmented { free, largest_free },
+            _ => AllocError::OutOfMemory { free, largest_free },
+        }
+    }
+
+    /// Record a failed allocation in the statistics.
+    ///
+    /// A single request might try several allocations (e.g. after reclaiming
+    /// memory), so the failures are not recorded by the allocation methods
+    /// themselves. Instead, the caller records the failure once the request
+    /// fails as a whole.
+    pub fn record_failure(&mut self) {
+        self.counters.failed();
+    }
+
+    /// Query the number of bytes in used blocks.
+    ///
+    /// Unlike the [`stats()`](Self::stats), this is kept up to date by the
+    /// allocation functions, so it takes constant time.
+    pub const fn used(&self) -> usize {
+        self.counters.used()
+    }
+
+    /// Search a free entry for `n` bytes with the alignment `align`, mark it as
+    /// used and return it.
+    ///
+    /// A successful allocation is recorded in the counters of the statistics,
+    /// a failed one has to be recorded by the caller via
+    /// [`record_failure()`](Self::record_failure).
+    fn allocate_entry(&mut self, n: usize, align: usize) -> Option<buffer::ValidatedOffset> {
+        let offset = self.place_entry(n, align)?;
+        self.counters.allocated(self.buffer.entry(offset).size());
+        Some(offset)
+    }
+
+    /// Search a free entry for `n` bytes with the alignment `align`, mark it as
+    /// used and return it.
+    fn place_entry(&mut self, n: usize, align: usize) -> Option<buffer::ValidatedOffset> {
+        // round up `n` to next valid block size, but at least to the minimum
+        // size supported by the strategy
+        let n = buffer::Buffer::<M, H>::rounded_size(n)?.max(Self::MIN_BLOCK_SIZE);
+
+        let offset = self.strategy.find(&self.buffer, n, align)?;
+        let padding = self
             .buffer
-            .entries()
-            .map(|offset| (offset, self.buffer[offset]))
-            .filter(|(_offset, entry)| entry.state() == State::Free)
-            .filter(|(_offset, entry)| entry.size() >= n)
-            .min_by_key(|(_offset, entry)| entry.size())?;
+            .alignment_padding(offset, n, align, Self::MIN_BLOCK_SIZE)?;
+        self.strategy.remove(&mut self.buffer, offset);
+
+        // split off the padding in front of the aligned memory as a free entry
+        let offset = if padding == 0 {
+            offset
+        } else {
+            let aligned = self.buffer.split_free(offset, padding - Self::HEADER_SIZE);
+            self.strategy.insert(&mut self.buffer, offset);
+            aligned
+        };
 
-        // if the found block is large enough, split it into a used and a free
-        self.buffer.mark_as_used(offset, n);
-        Some(self.buffer.memory_of_mut(offset))
+        // if the found block is large enough, split it into a used and a free.
+        // If the free remainder would be too small for the strategy, the whole
+        // block is used instead.
+        let size = self.buffer.entry(offset).size();
+        let n = if size - n < Self::HEADER_SIZE + Self::MIN_BLOCK_SIZE {
+            size
+        } else {
+            n
+        };
+        if let Some(remainder) = self.buffer.mark_as_used(offset, n) {
+            self.strategy.insert(&mut self.buffer, remainder);
+        }
+        Some(offset)
     }
 
     /// Free a pointer inside a used memory block.
@@ -80,148 +385,366 @@ impl<const N: usize> RawAllocator<N> {
     /// reported (as allocators are not allowed to unwind).
     ///
     /// # Algorithm
-    /// Freeing a pointer is done in the following way: all the entries are
-    /// scanned linearly. The pointer is compared against each block. If the
-    /// pointer points to the memory of an entry, than that entry is selected.
-    /// If no such entry is found, than the user tried to free an allocation,
-    /// that was not allocated with this allocator (or the allocator messed up
-    /// internally). [`FreeError::AllocationNotFound`] is reported.
+    /// Freeing a pointer is done in the following way: the buffer keeps a
+    /// bitmap of the offsets, where an entry starts. The bitmap is searched
+    /// backwards from the offset of a header directly in front of the pointer
+    /// (which is the header of the entry for a pointer returned by the
+    /// allocation functions, so that this takes constant time). If the memory
+    /// of the entry found this way contains the pointer, than that entry is
+    /// selected. Otherwise the user tried to free an allocation, that was not
+    /// allocated with this allocator (or the allocator messed up internally).
+    /// [`FreeError::AllocationNotFound`] is reported.
     ///
     /// The selected block is tested for its state. If it is marked as "used",
     /// than everything is fine. If it is already marked as "free", than
-    /// [`FreeError::DoubleFreeDetected`] is returned. If the block following
-    /// the just freed up one is also free, the two blocks are concatenated to a
-    /// single one (to prevent fragmentation).
+    /// [`FreeError::DoubleFreeDetected`] is returned. If the blocks preceding or
+    /// following the just freed up one are also free, the blocks are
+    /// concatenated to a single one (to prevent fragmentation). The preceding
+    /// block can be found without scanning, since each free block stores a copy
+    /// of its header at its end (a "boundary tag") and each entry knows,
+    /// whether the block in front of it is free.
+    ///
+    /// If red zones are enabled, the guard bytes of the block are verified
+    /// before it is freed. If they were overwritten, the block is freed
+    /// nevertheless and [`FreeError::RedZoneViolation`] is returned.
     pub fn free(&mut self, ptr: *mut u8) -> Result<(), FreeError> {
-        self.buffer.ensure_initialization();
+        self.ensure_initialization();
 
         // find the offset of the entry, which the `ptr` points into
-        let offset = self
-            .buffer
-            .entries()
-            .find(|offset| {
-                let size = self.buffer[*offset].size();
-                let memory = self.buffer.memory_of(*offset);
-                let ptr = ptr as *const _;
-                let start = memory.as_ptr();
-                let end = start.wrapping_add(size);
-
-                start <= ptr && ptr < end
-            })
-            .ok_or(FreeError::AllocationNotFound)?;
+        let offset = self.find_entry(ptr).ok_or(FreeError::AllocationNotFound)?;
 
         // check, if the entry is occupied. If it is free, a double free (or a
         // really wrong pointer) was detected, so report an error in that case
-        let entry = self.buffer[offset];
+        let entry = self.buffer.entry(offset);
         if entry.state() == State::Free {
             return Err(FreeError::DoubleFreeDetected);
         }
+        let guards = self.verify_before_free(offset);
+        self.release(offset);
+        guards
+    }
 
-        // query the following free memory or `0` if the following entry is used
-        let additional_memory = self
-            .buffer
-            .following_free_entry(offset)
-            .map_or(0, |entry| entry.size() + HEADER_SIZE);
+    /// Mark the used entry at the given offset as free and merge it with the
+    /// adjacent free entries.
+    ///
+    /// The release is recorded in the counters of the statistics.
+    fn release(&mut self, offset: buffer::ValidatedOffset) {
+        self.counters.released(self.buffer.entry(offset).size());
+        self.free_entry(offset);
+    }
 
-        // write the header (entry) to the buffer. If the additional memory is
-        // non-zero, then the following entry is simply "ignored" by enlarging
-        // the current one
-        self.buffer[offset] = Entry::free(entry.size() + additional_memory);
-        Ok(())
+    /// Mark the used entry at the given offset as free and merge it with the
+    /// adjacent free entries without recording it in the statistics.
+    fn free_entry(&mut self, offset: buffer::ValidatedOffset) {
+        let entry = self.buffer.entry(offset);
+
+        // query the following free memory or `0` if the following entry is used.
+        // The following entry is absorbed, so it is no longer a free list entry
+        let following = self.buffer.following_free_entry(offset);
+        let additional_memory = following.map_or(0, |following| {
+            self.strategy.remove(&mut self.buffer, following);
+            self.buffer.entry(following).size() + Self::HEADER_SIZE
+        });
+        let size = entry.size() + additional_memory;
+
+        // if the preceding entry is free as well, the freed entry (and maybe
+        // the following one) is absorbed by enlarging the preceding entry.
+        // Otherwise the header (entry) is written to the buffer. If the
+        // additional memory is non-zero, then the following entry is simply
+        // "ignored" by enlarging the current one.
+        let (offset, size) = match self.buffer.preceding_free_entry(offset) {
+            Some(preceding) => {
+                self.strategy.remove(&mut self.buffer, preceding);
+                let size = self.buffer.entry(preceding).size() + Self::HEADER_SIZE + size;
+                (preceding, size)
+            }
+            None => (offset, size),
+        };
+        self.buffer.mark_as_free(offset, size);
+        self.strategy.insert(&mut self.buffer, offset);
+    }
+
+    /// Resize the used memory block containing `ptr` in place, so that at
+    /// least `n` bytes starting at `ptr` are part of the block.
+    ///
+    /// This returns `true`, if the block could be resized. In that case the
+    /// contents of the memory are untouched (up to the smaller of the old and
+    /// the new size). If it returns `false`, nothing is changed and the caller
+    /// has to move the allocation to a new memory block.
+    ///
+    /// # Algorithm
+    /// The block is searched like in [`free()`](Self::free). If the following
+    /// entry is free, it is absorbed first, since a shrinking block has to be
+    /// merged with it and a growing block needs its memory. If the resulting
+    /// block is too small, the resize fails. Otherwise the unneeded tail of
+    /// the block is split off as a new free entry, if it is large enough.
+    /// Shrinking a block therefore always succeeds.
+    pub fn resize(&mut self, ptr: *mut u8, n: usize) -> bool {
+        self.ensure_initialization();
+
+        let offset = match self.find_entry(ptr) {
+            Some(offset) if self.buffer.entry(offset).state() == State::Used => offset,
+            _ => return false,
+        };
+        if !self.is_resizable_at(offset, ptr) {
+            return false;
+        }
+
+        // the block has to contain the bytes in front of `ptr` (e.g. due to
+        // alignment or red zones) as well as the guard bytes behind it.
+        let requested = n;
+        let start = self.buffer.memory_of(offset).as_ptr() as usize;
+        let n = match (ptr as usize - start)
+            .checked_add(self.guarded_tail_size(n))
+            .and_then(buffer::Buffer::<M, H>::rounded_size)
+        {
+            Some(n) => n.max(Self::MIN_BLOCK_SIZE),
+            None => return false,
+        };
+
+        let entry = self.buffer.entry(offset);
+        let following = self.buffer.following_free_entry(offset);
+        let available = entry.size()
+            + following.map_or(0, |following| {
+                self.buffer.entry(following).size() + Self::HEADER_SIZE
+            });
+        if n > available {
+            return false;
+        }
+
+        // absorb the following free entry and split off the unneeded memory. If
+        // the free remainder would be too small for the strategy, the whole
+        // block is kept instead.
+        if let Some(following) = following {
+            self.strategy.remove(&mut self.buffer, following);
+        }
+        self.buffer.set_entry(
+            offset,
+            Entry::used(available).with_preceding_free(entry.is_preceded_by_free()),
+        );
+        let n = if available - n < Self::HEADER_SIZE + Self::MIN_BLOCK_SIZE {
+            available
+        } else {
+            n
+        };
+        if let Some(remainder) = self.buffer.mark_as_used(offset, n) {
+            // the block after the remainder might be a used one, that has to
+            // be informed about its free predecessor.
+            let size = self.buffer.entry(remainder).size();
+            self.buffer.mark_as_free(remainder, size);
+            self.strategy.insert(&mut self.buffer, remainder);
+        }
+        self.reguard(offset, requested);
+        self.counters
+            .resized(entry.size(), self.buffer.entry(offset).size());
+        true
+    }
+
+    /// Query the usage statistics of the heap.
+    ///
+    /// The snapshot of the current usage is obtained by walking over all
+    /// blocks, so this takes time proportional to the number of blocks. The
+    /// values depending on the history of the heap are kept up to date by the
+    /// allocation functions.
+    pub fn stats(&mut self) -> Stats {
+        self.ensure_initialization();
+
+        let mut stats = Stats {
+            used: 0,
+            free: 0,
+            overhead: self.buffer.size(),
+            largest_free: 0,
+            allocations: 0,
+            free_blocks: 0,
+            peak_used: 0,
+            total_allocations: 0,
+            failed_allocations: 0,
+        };
+        self.walk(|block| match block.state {
+            BlockState::Used => {
+                stats.overhead -= block.size;
+                stats.used += block.size;
+                stats.allocations += 1;
+            }
+            BlockState::Free => {
+                stats.overhead -= block.size;
+                stats.free += block.size;
+                stats.free_blocks += 1;
+                stats.largest_free = stats.largest_free.max(block.size);
+            }
+            BlockState::Internal => {}
+        });
+        self.counters.apply(stats)
+    }
+
+    /// Call the given function with the information about each block of the
+    /// heap.
+    ///
+    /// The blocks are visited in the order of their addresses. The table of
+    /// handles is reported as [`BlockState::Internal`].
+    pub fn walk(&mut self, mut f: impl FnMut(BlockInfo)) {
+        self.ensure_initialization();
+
+        let table = self.handles.get();
+        for offset in self.buffer.entries() {
+            let entry = self.buffer.entry(offset);
+            let state = if Some(offset) == table {
+                BlockState::Internal
+            } else {
+                entry.state().into()
+            };
+            f(BlockInfo {
+                address: self.buffer.memory_of(offset).as_ptr() as usize,
+                size: entry.size(),
+                state,
+            });
+        }
+    }
+
+    /// Check the consistency of the whole heap.
+    ///
+    /// All blocks are validated against each other: they have to fit into the
+    /// heap, the last one has to end exactly at its end, no two free blocks
+    /// may be adjacent and the headers have to know the state of the block in
+    /// front of them. If red zones are enabled, the guard bytes of all
+    /// allocations are verified as well. The first problem found is returned.
+    pub fn check(&mut self) -> Result<(), CheckError> {
+        self.ensure_initialization();
+        self.buffer.check(self.guard_check())
+    }
+
+    /// Check the consistency of the next `count` blocks of the heap.
+    ///
+    /// This performs the same checks as [`check()`](Self::check), but only
+    /// for a bounded number of blocks per call: each call continues with the
+    /// block, where the previous one stopped, and the check restarts at the
+    /// first block after the last one was checked. Modifications of the heap
+    /// between the calls are taken into account. Whether the last block was
+    /// checked by this call is returned.
+    pub fn check_step(&mut self, count: usize) -> Result<bool, CheckError> {
+        self.ensure_initialization();
+        let guard_check = self.guard_check();
+        self.buffer.check_step(count, guard_check)
+    }
+
+    /// Check, whether the given pointer points into the heap memory of this
+    /// allocator.
+    pub fn owns(&self, ptr: *const u8) -> bool {
+        self.buffer.contains(ptr)
+    }
+
+    /// Find the entry, whose memory contains the given pointer.
+    ///
+    /// This takes constant time for pointers returned by the allocation
+    /// functions, see [`Buffer::entry_of()`](buffer::Buffer::entry_of).
+    fn find_entry(&self, ptr: *mut u8) -> Option<buffer::ValidatedOffset> {
+        self.buffer.entry_of(ptr)
     }
 }
 
 #[cfg(test)]
 mod tests {
+    // the memory of the allocators ends with the bitmap of entry starts, e.g.
+    // the entries of an `Array<36>` span 32 bytes (see `Buffer`).
+
     use crate::raw_allocator;
 
-    use super::{Entry, FreeError, RawAllocator};
+    use super::{
+        AllocError, Array, AutoHeader, BestFit, BlockInfo, BlockState, CheckError, Entry, FirstFit,
+        FreeError, Header, Header16, Header32, Header64, NextFit, RawAllocator, Region, State,
+        Strategy, Tlsf, WorstFit,
+    };
+    use core::mem::MaybeUninit;
 
     /// Test, that the given allocator has exactly the given entries.
+    ///
+    /// Only the state and the size of the entries are compared, the flag about
+    /// the preceding entry is tested separately.
     macro_rules! assert_allocations {
         ($allocator:expr, $($entry:expr),*$(,)?) => {{
             let mut iter = $allocator
                 .buffer
                 .entries()
-                .map(|offset| $allocator.buffer[offset]);
-            $(assert_eq!(iter.next(), Some($entry));)*
+                .map(|offset| $allocator.buffer.entry(offset))
+                .map(|entry| (entry.state(), entry.size()));
+            $(assert_eq!(iter.next(), Some(($entry.state(), $entry.size())));)*
             assert_eq!(iter.next(), None);
         }};
     }
 
     #[test]
     fn successful_single_allocation() {
-        let mut allocator = RawAllocator::<32>::new();
-        allocator.alloc(4).unwrap();
+        let mut allocator = RawAllocator::<Array<36>>::new();
+        allocator.alloc(4, 4).unwrap();
         assert_allocations!(allocator, Entry::used(4), Entry::free(20));
     }
 
     #[test]
     fn unsuccessful_single_allocation() {
         // the allocation is larger than the buffer itself
-        let mut allocator = RawAllocator::<32>::new();
-        assert!(allocator.alloc(36).is_none());
+        let mut allocator = RawAllocator::<Array<36>>::new();
+        assert!(allocator.alloc(36, 4).is_none());
         assert_allocations!(allocator, Entry::free(28));
     }
 
     #[test]
     fn successful_multiple_allocation() {
-        let mut allocator = RawAllocator::<32>::new();
-        allocator.alloc(12).unwrap();
-        allocator.alloc(12).unwrap();
+        let mut allocator = RawAllocator::<Array<36>>::new();
+        allocator.alloc(12, 4).unwrap();
+        allocator.alloc(12, 4).unwrap();
         // allocator is now full
         assert_allocations!(allocator, Entry::used(12), Entry::used(12));
     }
 
     #[test]
     fn unsuccessful_multiple_allocation() {
-        let mut allocator = RawAllocator::<32>::new();
-        allocator.alloc(12).unwrap();
+        let mut allocator = RawAllocator::<Array<36>>::new();
+        allocator.alloc(12, 4).unwrap();
         // the second allocation is larger than the remaining space
-        assert!(allocator.alloc(13).is_none());
+        assert!(allocator.alloc(13, 4).is_none());
         assert_allocations!(allocator, Entry::used(12), Entry::free(12));
     }
 
     #[test]
     fn no_more_space_available() {
-        let mut allocator = RawAllocator::<32>::new();
-        allocator.alloc(4).unwrap();
-        allocator.alloc(4).unwrap();
-        allocator.alloc(4).unwrap();
-        allocator.alloc(4).unwrap();
+        let mut allocator = RawAllocator::<Array<36>>::new();
+        allocator.alloc(4, 4).unwrap();
+        allocator.alloc(4, 4).unwrap();
+        allocator.alloc(4, 4).unwrap();
+        allocator.alloc(4, 4).unwrap();
 
         // now the allocator is entirely filled
         let free_entries = allocator
             .buffer
             .entries()
-            .map(|offset| allocator.buffer[offset])
+            .map(|offset| allocator.buffer.entry(offset))
             .filter(|entry| entry.state() == raw_allocator::State::Free)
             .count();
         assert_eq!(free_entries, 0);
 
         // the next allocation needs to fail
-        assert!(allocator.alloc(4).is_none());
+        assert!(allocator.alloc(4, 4).is_none());
     }
 
     #[test]
     fn not_enough_space_available() {
-        let mut allocator = RawAllocator::<32>::new();
-        allocator.alloc(4).unwrap();
-        allocator.alloc(4).unwrap();
-        allocator.alloc(4).unwrap();
+        let mut allocator = RawAllocator::<Array<36>>::new();
+        allocator.alloc(4, 4).unwrap();
+        allocator.alloc(4, 4).unwrap();
+        allocator.alloc(4, 4).unwrap();
 
         // now the allocator is entirely filled
         let total_free_bytes = allocator
             .buffer
             .entries()
-            .map(|offset| allocator.buffer[offset])
+            .map(|offset| allocator.buffer.entry(offset))
             .filter(|entry| entry.state() == raw_allocator::State::Free)
             .map(|entry| entry.size())
-            .fold(0, |sum, size| sum + size);
+            .sum::<usize>();
         assert_eq!(total_free_bytes, 4);
 
         // the next allocation needs to fail
-        assert!(allocator.alloc(5).is_none());
+        assert!(allocator.alloc(5, 4).is_none());
     }
 
     macro_rules! address {
@@ -235,14 +758,14 @@ mod tests {
         // this test case shows, that the allocator is susceptible to memory
         // fragmentation, which makes larger allocations impossible, if the
         // heap is in a bad state.
-        let mut allocator = RawAllocator::<60>::new();
+        let mut allocator = RawAllocator::<Array<64>>::new();
 
         // build a fragmented heap
-        let ptr1 = address!(allocator.alloc(8).unwrap());
-        let _ptr2 = address!(allocator.alloc(8).unwrap());
-        let ptr3 = address!(allocator.alloc(8).unwrap());
-        let _ptr4 = address!(allocator.alloc(8).unwrap());
-        let ptr5 = address!(allocator.alloc(8).unwrap());
+        let ptr1 = address!(allocator.alloc(8, 4).unwrap());
+        let _ptr2 = address!(allocator.alloc(8, 4).unwrap());
+        let ptr3 = address!(allocator.alloc(8, 4).unwrap());
+        let _ptr4 = address!(allocator.alloc(8, 4).unwrap());
+        let ptr5 = address!(allocator.alloc(8, 4).unwrap());
         allocator.free(ptr1).unwrap();
         allocator.free(ptr3).unwrap();
         allocator.free(ptr5).unwrap();
@@ -258,14 +781,14 @@ mod tests {
         // now, there are 24 free bytes (3x 8 bytes) and the headers, but the
         // allocation of 10 bytes must fail, since there is no contiguous memory
         // of that size
-        assert!(allocator.alloc(10).is_none());
+        assert!(allocator.alloc(10, 4).is_none());
     }
 
     #[test]
     fn simple_free() {
-        let mut allocator = RawAllocator::<16>::new();
-        let ptr = address!(allocator.alloc(4).unwrap());
-        allocator.alloc(4).unwrap();
+        let mut allocator = RawAllocator::<Array<20>>::new();
+        let ptr = address!(allocator.alloc(4, 4).unwrap());
+        allocator.alloc(4, 4).unwrap();
         assert_allocations!(allocator, Entry::used(4), Entry::used(4));
 
         // now, that the heap is properly built up, there are two used entries.
@@ -277,9 +800,9 @@ mod tests {
 
     #[test]
     fn double_free() {
-        let mut allocator = RawAllocator::<16>::new();
-        let ptr = address!(allocator.alloc(4).unwrap());
-        allocator.alloc(4).unwrap();
+        let mut allocator = RawAllocator::<Array<20>>::new();
+        let ptr = address!(allocator.alloc(4, 4).unwrap());
+        allocator.alloc(4, 4).unwrap();
 
         // try to free up the pointer twice. The first time has to succeed, but
         // the second time has to result in a double-free-error.
@@ -292,8 +815,8 @@ mod tests {
     fn non_allocated_pointer_freed() {
         use core::ptr;
 
-        let mut allocator = RawAllocator::<32>::new();
-        allocator.alloc(4).unwrap();
+        let mut allocator = RawAllocator::<Array<32>>::new();
+        allocator.alloc(4, 4).unwrap();
 
         // try to free up a pointer, that was not allocated by this allocator.
         // This invalid usage has to be detected.
@@ -304,8 +827,8 @@ mod tests {
 
     #[test]
     fn invalid_pointer_freed() {
-        let mut allocator = RawAllocator::<16>::new();
-        let ptr = address!(allocator.alloc(4).unwrap());
+        let mut allocator = RawAllocator::<Array<16>>::new();
+        let ptr = address!(allocator.alloc(4, 4).unwrap());
 
         // try to free a pointer, which was returned by the allocator, but since
         // then was slightly modified.
@@ -315,9 +838,9 @@ mod tests {
 
     #[test]
     fn free_of_modified_pointer() {
-        let mut allocator = RawAllocator::<16>::new();
-        let ptr = address!(allocator.alloc(4).unwrap());
-        allocator.alloc(4).unwrap();
+        let mut allocator = RawAllocator::<Array<20>>::new();
+        let ptr = address!(allocator.alloc(4, 4).unwrap());
+        allocator.alloc(4, 4).unwrap();
         assert_allocations!(allocator, Entry::used(4), Entry::used(4));
 
         let ptr = ptr.wrapping_add(3);
@@ -328,10 +851,44 @@ mod tests {
         assert_allocations!(allocator, Entry::free(4), Entry::used(4));
     }
 
+    #[test]
+    fn free_with_forged_header() {
+        let mut allocator = RawAllocator::<Array<268>>::new();
+        let memory = allocator.alloc(32, 4).unwrap();
+        let ptr = address!(memory);
+
+        // the user writes data looking like a used block with a used
+        // successor into its allocation and frees a pointer behind it. The
+        // allocation containing the pointer has to be freed instead of the
+        // forged block.
+        let word = |entry: Entry| (entry.as_raw(0) as u32).to_ne_bytes().map(MaybeUninit::new);
+        memory[4..8].copy_from_slice(&word(Entry::used(8)));
+        memory[16..20].copy_from_slice(&word(Entry::used(0)));
+        allocator.free(ptr.wrapping_add(8)).unwrap();
+        assert_allocations!(allocator, Entry::free(252));
+        allocator.check().unwrap();
+        assert_eq!(address!(allocator.alloc(8, 4).unwrap()), ptr);
+    }
+
+    #[test]
+    fn double_free_after_concatenation() {
+        let mut allocator = RawAllocator::<Array<36>>::new();
+        let ptr1 = address!(allocator.alloc(4, 4).unwrap());
+        let ptr2 = address!(allocator.alloc(4, 4).unwrap());
+        allocator.alloc(4, 4).unwrap();
+
+        // the second block is merged into the first one, but its header is
+        // still in the memory. It must not be used for the second free.
+        allocator.free(ptr1).unwrap();
+        allocator.free(ptr2).unwrap();
+        assert_eq!(allocator.free(ptr2), Err(FreeError::DoubleFreeDetected));
+        assert_allocations!(allocator, Entry::free(12), Entry::used(4), Entry::free(4));
+    }
+
     #[test]
     fn free_with_concatenation() {
-        let mut allocator = RawAllocator::<32>::new();
-        let ptr = address!(allocator.alloc(4).unwrap());
+        let mut allocator = RawAllocator::<Array<36>>::new();
+        let ptr = address!(allocator.alloc(4, 4).unwrap());
         assert_allocations!(allocator, Entry::used(4), Entry::free(20));
 
         // now there is a used block followed by a free block. When the used
@@ -342,9 +899,9 @@ mod tests {
 
     #[test]
     fn free_at_end() {
-        let mut allocator = RawAllocator::<16>::new();
-        allocator.alloc(4).unwrap();
-        let ptr = address!(allocator.alloc(4).unwrap());
+        let mut allocator = RawAllocator::<Array<20>>::new();
+        allocator.alloc(4, 4).unwrap();
+        let ptr = address!(allocator.alloc(4, 4).unwrap());
         assert_allocations!(allocator, Entry::used(4), Entry::used(4));
 
         // now, that the heap is properly built up, there are two used entries.
@@ -355,35 +912,99 @@ mod tests {
     }
 
     #[test]
-    fn free_impossible_defrag() {
-        let mut allocator = RawAllocator::<16>::new();
-        let ptr1 = address!(allocator.alloc(4).unwrap());
-        let ptr2 = address!(allocator.alloc(4).unwrap());
+    fn free_with_preceding_concatenation() {
+        let mut allocator = RawAllocator::<Array<20>>::new();
+        let ptr1 = address!(allocator.alloc(4, 4).unwrap());
+        let ptr2 = address!(allocator.alloc(4, 4).unwrap());
         allocator.free(ptr1).unwrap();
+        assert_allocations!(allocator, Entry::free(4), Entry::used(4));
 
         // now we have a free block, followed by a used block which in turn gets
-        // freed up. Therefore there are two contiguous free blocks, but those
-        // aren't concatenated, since the old free block is to the left (instead
-        // of to the right).
+        // freed up. The old free block is to the left, but it has to be
+        // concatenated with the freed block nevertheless.
         allocator.free(ptr2).unwrap();
+        assert_allocations!(allocator, Entry::free(12));
+    }
+
+    #[test]
+    fn free_with_concatenation_on_both_sides() {
+        let mut allocator = RawAllocator::<Array<52>>::new();
+        let ptr1 = address!(allocator.alloc(4, 4).unwrap());
+        let ptr2 = address!(allocator.alloc(8, 4).unwrap());
+        let ptr3 = address!(allocator.alloc(4, 4).unwrap());
+        let _ptr4 = address!(allocator.alloc(4, 4).unwrap());
+        allocator.free(ptr1).unwrap();
+        allocator.free(ptr3).unwrap();
+        assert_allocations!(
+            allocator,
+            Entry::free(4),
+            Entry::used(8),
+            Entry::free(4),
+            Entry::used(4),
+            Entry::free(8)
+        );
 
-        // therefore there must be two free blocks
-        assert_allocations!(allocator, Entry::free(4), Entry::free(4));
+        // the middle block is surrounded by two free blocks, so all three have
+        // to be joined into a single one.
+        allocator.free(ptr2).unwrap();
+        assert_allocations!(allocator, Entry::free(24), Entry::used(4), Entry::free(8));
+    }
+
+    #[test]
+    fn heap_is_single_block_after_releasing_everything() {
+        // free the allocations in different orders, the heap has to end up as
+        // a single free block every time.
+        let orders: [[usize; 4]; 4] = [[0, 1, 2, 3], [3, 2, 1, 0], [1, 3, 0, 2], [2, 0, 3, 1]];
+        for order in &orders {
+            let mut allocator = RawAllocator::<Array<68>>::new();
+            let ptrs = [
+                address!(allocator.alloc(4, 4).unwrap()),
+                address!(allocator.alloc(12, 4).unwrap()),
+                address!(allocator.alloc(8, 4).unwrap()),
+                address!(allocator.alloc(4, 4).unwrap()),
+            ];
+            for &index in order {
+                allocator.free(ptrs[index]).unwrap();
+            }
+            assert_allocations!(allocator, Entry::free(60));
+        }
+    }
+
+    #[test]
+    fn preceding_free_flag_is_maintained() {
+        let mut allocator = RawAllocator::<Array<32>>::new();
+        let ptr1 = address!(allocator.alloc(4, 4).unwrap());
+        let _ptr2 = address!(allocator.alloc(4, 4).unwrap());
+        let flags = |allocator: &RawAllocator<Array<32>>| {
+            allocator
+                .buffer
+                .entries()
+                .map(|offset| allocator.buffer.entry(offset).is_preceded_by_free())
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(flags(&allocator), [false, false, false]);
+
+        allocator.free(ptr1).unwrap();
+        assert_eq!(flags(&allocator), [false, true, false]);
+
+        // re-using the free block has to clear the flag of its successor again
+        let _ptr3 = address!(allocator.alloc(4, 4).unwrap());
+        assert_eq!(flags(&allocator), [false, false, false]);
     }
 
     #[test]
     fn alloc_impossible_splitting() {
-        let mut allocator = RawAllocator::<32>::new();
-        let _ptr1 = address!(allocator.alloc(4).unwrap());
-        let ptr2 = address!(allocator.alloc(12).unwrap());
-        let _ptr3 = address!(allocator.alloc(4).unwrap());
+        let mut allocator = RawAllocator::<Array<36>>::new();
+        let _ptr1 = address!(allocator.alloc(4, 4).unwrap());
+        let ptr2 = address!(allocator.alloc(12, 4).unwrap());
+        let _ptr3 = address!(allocator.alloc(4, 4).unwrap());
         allocator.free(ptr2).unwrap();
         assert_allocations!(allocator, Entry::used(4), Entry::free(12), Entry::used(4));
 
         // new we've set up the heap such there is a free block of 12 in the
         // middle (and no free data at the end). If one acquires a block of size
         // 4 everything should work fine and the free block should be split up.;
-        let ptr4 = address!(allocator.alloc(4).unwrap());
+        let ptr4 = address!(allocator.alloc(4, 4).unwrap());
         assert_allocations!(
             allocator,
             Entry::used(4),
@@ -397,10 +1018,799 @@ mod tests {
         // now the previous state is restored. If there is an allocation for a
         // size of 12, no splitting must be happening, since the block is only
         // 12 bytes of size, so splitting would tamper the following block.
-        let _ptr5 = address!(allocator.alloc(12).unwrap());
+        let _ptr5 = address!(allocator.alloc(12, 4).unwrap());
         assert_allocations!(allocator, Entry::used(4), Entry::used(12), Entry::used(4));
     }
 
+    #[test]
+    fn best_fit_prefers_lowest_address() {
+        let mut allocator = RawAllocator::<Array<64>>::new();
+        let ptr1 = address!(allocator.alloc(8, 4).unwrap());
+        let _ptr2 = address!(allocator.alloc(4, 4).unwrap());
+        let ptr3 = address!(allocator.alloc(8, 4).unwrap());
+        let _ptr4 = address!(allocator.alloc(4, 4).unwrap());
+        let ptr5 = address!(allocator.alloc(8, 4).unwrap());
+        let _ptr6 = address!(allocator.alloc(4, 4).unwrap());
+
+        // free the blocks in an order, that puts the one with the highest
+        // address at the front of the free list. There are three equally sized
+        // best fits, the first one in memory has to be chosen nevertheless.
+        allocator.free(ptr3).unwrap();
+        allocator.free(ptr1).unwrap();
+        allocator.free(ptr5).unwrap();
+        let ptr = address!(allocator.alloc(8, 4).unwrap());
+        assert_eq!(ptr, ptr1);
+    }
+
+    #[test]
+    fn tlsf_minimum_block_size() {
+        // the strategy needs space for two links and the boundary tag in each
+        // block, therefore small allocations are enlarged.
+        let mut allocator = RawAllocator::<Array<68>, Tlsf>::new();
+        let memory = allocator.alloc(1, 4).unwrap();
+        assert_eq!(memory.len(), 12);
+        assert_allocations!(allocator, Entry::used(12), Entry::free(44));
+    }
+
+    #[test]
+    fn tlsf_no_splitting_of_tiny_remainders() {
+        let mut allocator = RawAllocator::<Array<68>, Tlsf>::new();
+        allocator.alloc(12, 4).unwrap();
+
+        // the remaining 44 bytes would leave a remainder of 12 bytes including
+        // the header, which cannot be managed. Therefore the whole block is used
+        let memory = allocator.alloc(32, 4).unwrap();
+        assert_eq!(memory.len(), 44);
+        assert_allocations!(allocator, Entry::used(12), Entry::used(44));
+        assert!(allocator.alloc(1, 4).is_none());
+    }
+
+    #[test]
+    fn tlsf_heap_is_single_block_after_releasing_everything() {
+        let mut allocator = RawAllocator::<Array<1060>, Tlsf>::new();
+        let ptrs = [4, 100, 12, 64, 256, 20, 8, 128]
+            .iter()
+            .map(|&size| address!(allocator.alloc(size, 4).unwrap()))
+            .collect::<Vec<_>>();
+        for &index in &[3, 0, 7, 5, 1, 6, 2, 4] {
+            allocator.free(ptrs[index]).unwrap();
+        }
+        assert_allocations!(allocator, Entry::free(1020));
+
+        // the coalesced block has to be usable for a large allocation. Due to the
+        // good fit, the request may only be slightly smaller than the block.
+        allocator.alloc(960, 4).unwrap();
+    }
+
+    #[test]
+    fn tlsf_reuses_freed_blocks() {
+        let mut allocator = RawAllocator::<Array<256>, Tlsf>::new();
+        let ptr1 = address!(allocator.alloc(64, 4).unwrap());
+        let _ptr2 = address!(allocator.alloc(16, 4).unwrap());
+        let ptr3 = address!(allocator.alloc(32, 4).unwrap());
+        let _ptr4 = address!(allocator.alloc(16, 4).unwrap());
+        allocator.free(ptr1).unwrap();
+        allocator.free(ptr3).unwrap();
+
+        // the request fits into both free blocks, the one in the matching size
+        // class is used.
+        let ptr = address!(allocator.alloc(24, 4).unwrap());
+        assert_eq!(ptr, ptr3);
+        let ptr = address!(allocator.alloc(48, 4).unwrap());
+        assert_eq!(ptr, ptr1);
+    }
+
+    #[test]
+    fn resize_shrink() {
+        let mut allocator = RawAllocator::<Array<68>>::new();
+        let ptr1 = address!(allocator.alloc(24, 4).unwrap());
+        let _ptr2 = address!(allocator.alloc(4, 4).unwrap());
+
+        // the split off tail is a free entry in front of a used one
+        assert!(allocator.resize(ptr1, 8));
+        assert_allocations!(
+            allocator,
+            Entry::used(8),
+            Entry::free(12),
+            Entry::used(4),
+            Entry::free(24)
+        );
+        let used = allocator.buffer.entries().nth(2).unwrap();
+        assert!(allocator.buffer.entry(used).is_preceded_by_free());
+
+        // shrinking next to a free entry merges the tail with it
+        assert!(allocator.resize(ptr1, 4));
+        assert_allocations!(
+            allocator,
+            Entry::used(4),
+            Entry::free(16),
+            Entry::used(4),
+            Entry::free(24)
+        );
+    }
+
+    #[test]
+    fn resize_grow() {
+        let mut allocator = RawAllocator::<Array<68>>::new();
+        let ptr1 = address!(allocator.alloc(4, 4).unwrap());
+        let ptr2 = address!(allocator.alloc(8, 4).unwrap());
+        let _ptr3 = address!(allocator.alloc(4, 4).unwrap());
+        allocator.free(ptr2).unwrap();
+
+        // the following free entry is absorbed partially...
+        assert!(allocator.resize(ptr1, 8));
+        assert_allocations!(
+            allocator,
+            Entry::used(8),
+            Entry::free(4),
+            Entry::used(4),
+            Entry::free(32)
+        );
+        // ... or completely
+        assert!(allocator.resize(ptr1, 16));
+        assert_allocations!(allocator, Entry::used(16), Entry::used(4), Entry::free(32));
+        let used = allocator.buffer.entries().nth(1).unwrap();
+        assert!(!allocator.buffer.entry(used).is_preceded_by_free());
+
+        // the used entry after the block cannot be absorbed
+        assert!(!allocator.resize(ptr1, 20));
+        assert_allocations!(allocator, Entry::used(16), Entry::used(4), Entry::free(32));
+    }
+
+    #[test]
+    fn resize_respects_offset_of_pointer() {
+        let mut allocator = RawAllocator::<Array<68>>::new();
+        let ptr1 = address!(allocator.alloc(16, 4).unwrap());
+
+        // a pointer into the middle of the block (e.g. due to alignment) needs
+        // the memory in front of it as well
+        assert!(allocator.resize(ptr1.wrapping_add(8), 8));
+        assert_allocations!(allocator, Entry::used(16), Entry::free(40));
+        assert!(allocator.resize(ptr1.wrapping_add(8), 12));
+        assert_allocations!(allocator, Entry::used(20), Entry::free(36));
+    }
+
+    #[test]
+    fn tlsf_resize() {
+        let mut allocator = RawAllocator::<Array<68>, Tlsf>::new();
+        let ptr = address!(allocator.alloc(12, 4).unwrap());
+        assert!(allocator.resize(ptr, 20));
+        assert_allocations!(allocator, Entry::used(20), Entry::free(36));
+
+        // the remainder would be too small for the strategy
+        assert!(allocator.resize(ptr, 48));
+        assert_allocations!(allocator, Entry::used(60));
+        assert!(allocator.resize(ptr, 1));
+        assert_allocations!(allocator, Entry::used(12), Entry::free(44));
+    }
+
+    #[test]
+    fn resize_of_free_memory() {
+        let mut allocator = RawAllocator::<Array<64>>::new();
+        let ptr = address!(allocator.alloc(4, 4).unwrap());
+        allocator.free(ptr).unwrap();
+        assert!(!allocator.resize(ptr, 4));
+        assert!(!allocator.resize(core::ptr::null_mut(), 4));
+    }
+
+    /// Check, that all bytes of the given memory are zero.
+    fn is_zeroed(memory: &[MaybeUninit<u8>]) -> bool {
+        // SAFETY: the buffer is zeroed in `Buffer::new()` and the tests only
+        // write initialized bytes into it
+        memory.iter().all(|byte| unsafe { byte.assume_init() } == 0)
+    }
+
+    #[test]
+    fn alloc_zeroed() {
+        let mut allocator = RawAllocator::<Array<128>>::new();
+        let memory = allocator.alloc(16, 4).unwrap();
+        memory.fill(MaybeUninit::new(0xAA));
+        let ptr = address!(memory);
+        let _ptr2 = address!(allocator.alloc(4, 4).unwrap());
+        allocator.free(ptr).unwrap();
+
+        // the reused memory has to be cleared
+        let memory = allocator.alloc_zeroed(8, 4).unwrap();
+        assert!(is_zeroed(memory));
+        // the remainder contained the boundary tag of the freed block
+        let memory = allocator.alloc_zeroed(4, 4).unwrap();
+        assert!(is_zeroed(memory));
+
+        // the last entry contains the last boundary tag
+        let memory = allocator.alloc_zeroed(84, 4).unwrap();
+        assert_eq!(memory.len(), 84);
+        assert!(is_zeroed(memory));
+    }
+
+    #[test]
+    fn alloc_zeroed_tlsf() {
+        // TLSF stores two links in each free entry
+        let mut allocator = RawAllocator::<Array<136>, Tlsf>::new();
+        let memory = allocator.alloc_zeroed(60, 4).unwrap();
+        assert!(is_zeroed(memory));
+        let ptr = address!(memory);
+        let _ptr2 = address!(allocator.alloc(4, 4).unwrap());
+        allocator.free(ptr).unwrap();
+
+        let memory = allocator.alloc_zeroed(60, 4).unwrap();
+        assert!(is_zeroed(memory));
+        let memory = allocator.alloc_zeroed(44, 4).unwrap();
+        assert!(is_zeroed(memory));
+    }
+
+    #[test]
+    fn untouched_memory_is_tracked() {
+        let mut allocator = RawAllocator::<Array<1024>>::new();
+        allocator.alloc_zeroed(100, 4).unwrap();
+        // the allocation and the header of the remainder including its link
+        assert_eq!(allocator.buffer.untouched(), 4 + 100 + 8);
+
+        // freeing does not change the untouched memory
+        let ptr = address!(allocator.alloc(200, 4).unwrap());
+        assert_eq!(allocator.buffer.untouched(), 4 + 100 + 4 + 200 + 8);
+        allocator.free(ptr).unwrap();
+        assert_eq!(allocator.buffer.untouched(), 4 + 100 + 4 + 200 + 8);
+    }
+
+    #[test]
+    fn alloc_aligned() {
+        let mut allocator = RawAllocator::<Array<268>>::new();
+        let ptr = address!(allocator.alloc(8, 64).unwrap());
+        assert_eq!(ptr as usize % 64, 0);
+
+        // the padding in front of the allocation is a separate free entry
+        let start = allocator
+            .buffer
+            .memory_of(allocator.buffer.entries().next().unwrap());
+        let padding = ptr as usize - start.as_ptr() as usize;
+        if padding == 0 {
+            assert_allocations!(allocator, Entry::used(8), Entry::free(240));
+        } else {
+            assert_allocations!(
+                allocator,
+                Entry::free(padding - 4),
+                Entry::used(8),
+                Entry::free(240 - padding)
+            );
+        }
+
+        // freeing restores the whole heap
+        allocator.free(ptr).unwrap();
+        assert_allocations!(allocator, Entry::free(252));
+    }
+
+    #[test]
+    fn tlsf_alloc_aligned() {
+        let mut allocator = RawAllocator::<Array<532>, Tlsf>::new();
+        let ptr = address!(allocator.alloc(8, 64).unwrap());
+        assert_eq!(ptr as usize % 64, 0);
+
+        // the padding in front of the allocation has to be usable by the TLSF
+        let first = allocator.buffer.entries().next().unwrap();
+        let entry = allocator.buffer.entry(first);
+        assert!(entry.state() == State::Used || entry.size() >= 12);
+        allocator.free(ptr).unwrap();
+        assert_allocations!(allocator, Entry::free(508));
+    }
+
+    #[test]
+    fn wide_headers() {
+        // the headers occupy 8 bytes and the sizes are multiples of 8
+        let mut allocator = RawAllocator::<Array<72>, BestFit, Header64>::new();
+        let memory = allocator.alloc(4, 4).unwrap();
+        assert_eq!(memory.len(), 8);
+        let ptr = address!(memory);
+        let ptr2 = address!(allocator.alloc(9, 4).unwrap());
+        assert_eq!(ptr2 as usize - ptr as usize, 16);
+        assert_allocations!(allocator, Entry::used(8), Entry::used(16), Entry::free(16));
+
+        allocator.free(ptr).unwrap();
+        assert_allocations!(allocator, Entry::free(8), Entry::used(16), Entry::free(16));
+        allocator.free(ptr2).unwrap();
+        assert_allocations!(allocator, Entry::free(56));
+    }
+
+    #[test]
+    fn tlsf_wide_headers() {
+        // the minimum block size of the TLSF scales with the header size
+        let mut allocator = RawAllocator::<Array<136>, Tlsf, Header64>::new();
+        let memory = allocator.alloc(1, 4).unwrap();
+        assert_eq!(memory.len(), 24);
+        let ptr = address!(memory);
+        let aligned = address!(allocator.alloc(8, 32).unwrap());
+        assert_eq!(aligned as usize % 32, 0);
+
+        allocator.free(ptr).unwrap();
+        allocator.free(aligned).unwrap();
+        assert_allocations!(allocator, Entry::free(120));
+    }
+
+    #[test]
+    fn compact_headers() {
+        // the headers occupy 2 bytes, the blocks including their header are a
+        // multiple of the granule (`4` here), so that the memory is still
+        // aligned to the granule
+        let mut allocator = RawAllocator::<Array<36>, BestFit, Header16>::new();
+        let memory = allocator.alloc(4, 4).unwrap();
+        assert_eq!(memory.len(), 6);
+        let ptr = address!(memory);
+        assert_eq!(ptr as usize % 4, 0);
+        let ptr2 = address!(allocator.alloc(1, 4).unwrap());
+        assert_eq!(ptr2 as usize - ptr as usize, 8);
+        assert_allocations!(allocator, Entry::used(6), Entry::used(2), Entry::free(14));
+
+        allocator.free(ptr).unwrap();
+        assert_allocations!(allocator, Entry::free(6), Entry::used(2), Entry::free(14));
+        allocator.free(ptr2).unwrap();
+        assert_allocations!(allocator, Entry::free(26));
+    }
+
+    #[test]
+    fn compact_headers_with_alignment_and_resizing() {
+        let mut allocator = RawAllocator::<Array<136>, BestFit, Header16>::new();
+        let small = address!(allocator.alloc(2, 4).unwrap());
+        let aligned = address!(allocator.alloc(8, 32).unwrap());
+        assert_eq!(aligned as usize % 32, 0);
+
+        // growing absorbs the following free block, shrinking splits it off
+        assert!(allocator.resize(aligned, 20));
+        assert_eq!(
+            allocator.buffer.memory_of(find(&allocator, aligned)).len(),
+            22
+        );
+        assert!(allocator.resize(aligned, 3));
+        assert_eq!(
+            allocator.buffer.memory_of(find(&allocator, aligned)).len(),
+            6
+        );
+
+        allocator.free(small).unwrap();
+        allocator.free(aligned).unwrap();
+        assert_allocations!(allocator, Entry::free(122));
+    }
+
+    /// Find the entry of the given allocation.
+    fn find<S: super::Strategy>(
+        allocator: &RawAllocator<Array<136>, S, Header16>,
+        ptr: *mut u8,
+    ) -> super::buffer::ValidatedOffset {
+        allocator.buffer.entry_of(ptr).unwrap()
+    }
+
+    #[test]
+    fn tlsf_compact_headers() {
+        // the minimum block size of the TLSF scales with the header size
+        let mut allocator = RawAllocator::<Array<136>, Tlsf, Header16>::new();
+        let memory = allocator.alloc(1, 4).unwrap();
+        assert_eq!(memory.len(), 6);
+        let ptr = address!(memory);
+        let aligned = address!(allocator.alloc(8, 32).unwrap());
+        assert_eq!(aligned as usize % 32, 0);
+
+        allocator.free(ptr).unwrap();
+        allocator.free(aligned).unwrap();
+        assert_allocations!(allocator, Entry::free(122));
+    }
+
+    #[test]
+    fn automatic_header_format() {
+        // small heaps use the compact format
+        let mut allocator = RawAllocator::<Array<64>, BestFit, AutoHeader>::new();
+        assert_eq!(allocator.alloc(4, 4).unwrap().len(), 6);
+
+        // larger heaps use the default format
+        let mut allocator =
+            Box::new(RawAllocator::<Array<{ 64 * 1024 + 4 }>, BestFit, AutoHeader>::new());
+        assert_eq!(allocator.alloc(4, 4).unwrap().len(), 4);
+    }
+
+    #[test]
+    fn larger_granule() {
+        // all blocks including their header are a multiple of 8, so that the
+        // memory is aligned to 8 without any padding
+        let mut allocator = RawAllocator::<Array<72>, BestFit, Header32<8>>::new();
+        let ptrs = [1, 8, 4]
+            .iter()
+            .map(|&size| address!(allocator.alloc(size, 8).unwrap()))
+            .collect::<Vec<_>>();
+        assert!(ptrs.iter().all(|&ptr| ptr as usize % 8 == 0));
+        assert_allocations!(
+            allocator,
+            Entry::used(4),
+            Entry::used(12),
+            Entry::used(4),
+            Entry::free(20)
+        );
+
+        allocator.free(ptrs[1]).unwrap();
+        allocator.free(ptrs[0]).unwrap();
+        allocator.free(ptrs[2]).unwrap();
+        assert_allocations!(allocator, Entry::free(52));
+    }
+
+    #[test]
+    fn granule_of_16() {
+        let mut allocator = RawAllocator::<Array<144>, BestFit, Header32<16>>::new();
+        let a = address!(allocator.alloc(1, 16).unwrap());
+        let b = address!(allocator.alloc(20, 16).unwrap());
+        assert_eq!(a as usize % 16, 0);
+        assert_eq!(b as usize - a as usize, 16);
+        assert_allocations!(allocator, Entry::used(12), Entry::used(28), Entry::free(52));
+
+        // larger alignments still split off the padding
+        let c = address!(allocator.alloc(4, 32).unwrap());
+        assert_eq!(c as usize % 32, 0);
+        allocator.free(a).unwrap();
+        allocator.free(b).unwrap();
+        allocator.free(c).unwrap();
+        assert_allocations!(allocator, Entry::free(100));
+
+        // the compact headers and the TLSF use the granule as well
+        let mut allocator = RawAllocator::<Array<144>, Tlsf, Header16<16>>::new();
+        let a = address!(allocator.alloc(1, 16).unwrap());
+        let b = address!(allocator.alloc(1, 16).unwrap());
+        assert_eq!(a as usize % 16, 0);
+        assert_eq!(b as usize - a as usize, 16);
+        allocator.free(a).unwrap();
+        allocator.free(b).unwrap();
+        assert_allocations!(allocator, Entry::free(98));
+    }
+
+    /// Obtain two adjacent memory regions of the given sizes.
+    fn adjacent_regions(first: usize, second: usize) -> (Region, Region) {
+        let memory = Box::leak(vec![0_u64; (first + second) / 8].into_boxed_slice());
+        // SAFETY: the leaked memory is valid for the bytes of the `u64`s
+        let memory =
+            unsafe { core::slice::from_raw_parts_mut(memory.as_mut_ptr().cast(), first + second) };
+        let (first, second) = memory.split_at_mut(first);
+        (Region::from(first), Region::from(second))
+    }
+
+    #[test]
+    fn extend_free_last_block() {
+        let (first, second) = adjacent_regions(36, 32);
+        let mut allocator = RawAllocator::<Region>::with_region(first);
+        allocator.alloc(4, 4).unwrap();
+        assert!(allocator.extend(second).is_ok());
+        assert_allocations!(allocator, Entry::used(4), Entry::free(52));
+
+        // the former boundary tag is part of the free memory now, so it has
+        // to be cleared in zeroed allocations.
+        let memory = allocator.alloc_zeroed(52, 4).unwrap();
+        assert!(is_zeroed(memory));
+
+        // pointers into the appended memory are found as well
+        let ptr = address!(memory).wrapping_add(40);
+        allocator.free(ptr).unwrap();
+        assert_allocations!(allocator, Entry::used(4), Entry::free(52));
+    }
+
+    #[test]
+    fn extend_used_last_block() {
+        let (first, second) = adjacent_regions(36, 32);
+        let mut allocator = RawAllocator::<Region>::with_region(first);
+        allocator.alloc(12, 4).unwrap();
+        let ptr = address!(allocator.alloc(12, 4).unwrap());
+        assert!(allocator.extend(second).is_ok());
+        assert_allocations!(allocator, Entry::used(12), Entry::used(12), Entry::free(28));
+
+        allocator.free(ptr).unwrap();
+        assert_allocations!(allocator, Entry::used(12), Entry::free(44));
+    }
+
+    #[test]
+    fn extend_with_unrelated_region() {
+        let (first, second) = adjacent_regions(32, 36);
+        let mut allocator = RawAllocator::<Region>::with_region(second);
+        assert!(allocator.extend(first).is_err());
+        assert_allocations!(allocator, Entry::free(28));
+
+        let (_, second) = adjacent_regions(32, 36);
+        let mut allocator = RawAllocator::<Region>::EMPTY;
+        assert!(allocator.extend(second).is_err());
+        assert!(allocator.is_empty());
+    }
+
+    #[test]
+    fn stats() {
+        let mut allocator = RawAllocator::<Array<68>>::new();
+        let stats = allocator.stats();
+        assert_eq!((stats.used, stats.free, stats.overhead), (0, 60, 8));
+        assert_eq!((stats.allocations, stats.free_blocks), (0, 1));
+        assert_eq!(stats.largest_free, 60);
+
+        let a = address!(allocator.alloc(8, 4).unwrap());
+        let b = address!(allocator.alloc(16, 4).unwrap());
+        assert!(allocator.alloc(64, 4).is_none());
+        allocator.record_failure();
+        allocator.free(a).unwrap();
+        assert!(allocator.resize(b, 4));
+        let stats = allocator.stats();
+        assert_eq!((stats.used, stats.free, stats.overhead), (4, 48, 16));
+        assert_eq!((stats.allocations, stats.free_blocks), (1, 2));
+        assert_eq!(stats.largest_free, 40);
+        assert_eq!(stats.peak_used, 24);
+        assert_eq!(stats.total_allocations, 2);
+        assert_eq!(stats.failed_allocations, 1);
+    }
+
+    #[test]
+    fn walk() {
+        let mut allocator = RawAllocator::<Array<136>>::new();
+        let a = address!(allocator.alloc(8, 4).unwrap());
+        let b = address!(allocator.alloc(4, 4).unwrap());
+        allocator.free(a).unwrap();
+
+        let mut blocks = Vec::new();
+        allocator.walk(|block| blocks.push(block));
+        let block = |address, size, state| BlockInfo {
+            address,
+            size,
+            state,
+        };
+        assert_eq!(
+            blocks,
+            [
+                block(a as usize, 8, BlockState::Free),
+                block(b as usize, 4, BlockState::Used),
+                block(b as usize + 8, 104, BlockState::Free),
+            ]
+        );
+    }
+
+    #[test]
+    fn stats_track_the_used_bytes() {
+        // the peak is derived from the tracked used bytes, which have to match
+        // the used bytes of the heap after all kinds of operations.
+        let mut allocator = RawAllocator::<Array<256>, Tlsf>::new();
+        let a = address!(allocator.alloc(4, 4).unwrap());
+        assert!(allocator.resize(a, 40));
+        let b = address!(allocator.alloc(20, 32).unwrap());
+        allocator.free(b).unwrap();
+        let handle = allocator.alloc_handle(24, 4).unwrap();
+        allocator.compact();
+        let stats = allocator.stats();
+        assert_eq!(stats.used + stats.free + stats.overhead, 256);
+        assert_eq!(stats.used, allocator.counters.used());
+
+        allocator.free_handle(handle).unwrap();
+        allocator.free(a).unwrap();
+        let stats = allocator.stats();
+        assert_eq!(stats.allocations, 0);
+        assert_eq!(stats.used, allocator.counters.used());
+    }
+
+    #[test]
+    fn handle_table_is_internal() {
+        // the table of handles is kept after freeing all handles, but it is
+        // neither counted as an allocation nor as used memory.
+        let mut allocator = RawAllocator::<Array<136>, Tlsf>::new();
+        let handle = allocator.alloc_handle(4, 4).unwrap();
+        allocator.free_handle(handle).unwrap();
+
+        let mut internal = 0;
+        allocator.walk(|block| {
+            if block.state == BlockState::Internal {
+                internal += block.size;
+            }
+        });
+        assert_ne!(internal, 0);
+
+        let stats = allocator.stats();
+        assert_eq!((stats.used, stats.allocations), (0, 0));
+        assert_eq!(stats.total_allocations, 1);
+        assert_eq!(stats.used + stats.free + stats.overhead, 136);
+        assert!(stats.overhead >= internal);
+    }
+
+    /// Perform pseudo-random allocations, resizes and releases and check the
+    /// consistency of the heap after each of them, both completely and with
+    /// single steps, that have to cope with the modifications.
+    fn check_random_operations<S: Strategy, H: Header>() {
+        let mut allocator = RawAllocator::<Array<512>, S, H>::new();
+        let mut pointers = [core::ptr::null_mut::<u8>(); 8];
+        let mut seed = 0x1234_5678_u32;
+        for _ in 0..500 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let slot = (seed >> 8) as usize % pointers.len();
+            let size = (seed >> 16) as usize % 48 + 1;
+            let align = 1 << ((seed >> 24) % 4);
+            let ptr = pointers[slot];
+            if ptr.is_null() {
+                if let Some(memory) = allocator.alloc(size, align) {
+                    pointers[slot] = address!(memory);
+                }
+            } else if seed & 0x80 == 0 {
+                let _resized = allocator.resize(ptr, size);
+            } else {
+                allocator.free(ptr).unwrap();
+                pointers[slot] = core::ptr::null_mut();
+            }
+            assert_eq!(allocator.check(), Ok(()));
+            assert!(allocator.check_step(1).is_ok());
+        }
+    }
+
+    #[test]
+    fn check_consistent_heap() {
+        check_random_operations::<BestFit, Header32>();
+        check_random_operations::<FirstFit, Header32>();
+        check_random_operations::<NextFit, Header32>();
+        check_random_operations::<WorstFit, Header32>();
+        check_random_operations::<Tlsf, Header32>();
+        check_random_operations::<BestFit, Header16>();
+        check_random_operations::<Tlsf, Header16>();
+        check_random_operations::<BestFit, Header64>();
+        check_random_operations::<Tlsf, Header64>();
+    }
+
+    #[test]
+    fn check_empty_heap() {
+        let mut allocator = RawAllocator::<Region>::EMPTY;
+        assert_eq!(allocator.check(), Ok(()));
+        assert_eq!(allocator.check_step(1), Ok(true));
+
+        let mut allocator = RawAllocator::<Array<32>>::new();
+        assert_eq!(allocator.check(), Ok(()));
+        assert_eq!(allocator.check_step(0), Ok(false));
+        assert_eq!(allocator.check_step(1), Ok(true));
+    }
+
+    #[test]
+    fn check_detects_corrupted_headers() {
+        let mut allocator = RawAllocator::<Array<128>>::new();
+        let a = address!(allocator.alloc(8, 4).unwrap());
+        let b = address!(allocator.alloc(8, 4).unwrap());
+        let c = address!(allocator.alloc(8, 4).unwrap());
+        allocator.free(a).unwrap();
+        assert_eq!(allocator.check(), Ok(()));
+        let offsets: Vec<_> = allocator.buffer.entries().collect();
+        let entry = |offset| allocator.buffer.entry(offsets[offset]);
+        let (second, third) = (entry(1), entry(2));
+
+        allocator
+            .buffer
+            .set_entry(offsets[1], Entry::used(128).with_preceding_free(true));
+        let error = CheckError::OutOfBounds {
+            address: b as usize,
+            size: 128,
+        };
+        assert_eq!(allocator.check(), Err(error));
+        assert_eq!(allocator.check_step(2), Err(error));
+
+        allocator.buffer.set_entry(offsets[1], second);
+        allocator.buffer.memory_of_mut(offsets[0])[4..].fill(MaybeUninit::new(0x40));
+        let error = CheckError::InvalidBoundaryTag {
+            address: a as usize,
+        };
+        assert_eq!(allocator.check(), Err(error));
+
+        allocator.buffer.mark_as_free(offsets[0], 8);
+        allocator
+            .buffer
+            .set_entry(offsets[2], third.with_preceding_free(true));
+        let error = CheckError::InconsistentPrecedingState {
+            address: c as usize,
+        };
+        assert_eq!(allocator.check(), Err(error));
+
+        allocator.buffer.set_entry(offsets[2], third);
+        allocator.buffer.mark_as_free(offsets[1], 8);
+        let error = CheckError::UncoalescedFreeBlocks {
+            address: a as usize,
+        };
+        assert_eq!(allocator.check(), Err(error));
+    }
+
+    #[test]
+    fn check_detects_misaligned_blocks() {
+        let mut allocator = RawAllocator::<Array<128>, BestFit, Header64>::new();
+        let a = address!(allocator.alloc(8, 8).unwrap());
+        let offset = allocator.buffer.entries().next().unwrap();
+        allocator.buffer.set_entry(offset, Entry::used(12));
+        let error = CheckError::Misaligned {
+            address: a as usize,
+            size: 12,
+        };
+        assert_eq!(allocator.check(), Err(error));
+    }
+
+    #[test]
+    fn check_step() {
+        let mut allocator = RawAllocator::<Array<128>>::new();
+        let pointers: Vec<_> = (0..6)
+            .map(|_| address!(allocator.alloc(8, 4).unwrap()))
+            .collect();
+        // seven blocks including the remaining free one
+        assert_eq!(allocator.check_step(3), Ok(false));
+        assert_eq!(allocator.check_step(3), Ok(false));
+        assert_eq!(allocator.check_step(3), Ok(true));
+        assert_eq!(allocator.check_step(7), Ok(true));
+
+        // the fourth block (the position of the cursor) is merged into the
+        // preceding one, so the check continues with the merged block.
+        assert_eq!(allocator.check_step(3), Ok(false));
+        allocator.free(pointers[2]).unwrap();
+        allocator.free(pointers[3]).unwrap();
+        allocator.free(pointers[4]).unwrap();
+        assert_eq!(allocator.check_step(2), Ok(false));
+        assert_eq!(allocator.check_step(1), Ok(true));
+
+        // a problem resets the cursor to the first block
+        let offset = allocator.buffer.entries().nth(3).unwrap();
+        let entry = allocator.buffer.entry(offset);
+        allocator
+            .buffer
+            .set_entry(offset, entry.with_preceding_free(false));
+        assert_eq!(allocator.check_step(2), Ok(false));
+        assert!(allocator.check_step(2).is_err());
+        allocator.buffer.set_entry(offset, entry);
+        assert_eq!(allocator.check_step(5), Ok(true));
+    }
+
+    #[test]
+    fn alloc_error_out_of_memory() {
+        let mut allocator = RawAllocator::<Array<68>>::new();
+        assert!(allocator.alloc(40, 4).is_some());
+        assert!(allocator.alloc(32, 4).is_none());
+        let error = AllocError::OutOfMemory {
+            free: 16,
+            largest_free: 16,
+        };
+        assert_eq!(allocator.alloc_error(32, 4), error);
+        assert_eq!(allocator.alloc_error(usize::MAX, 4), error);
+        assert_eq!((error.free(), error.largest_free()), (16, 16));
+    }
+
+    #[test]
+    fn alloc_error_fragmented() {
+        let mut allocator = RawAllocator::<Array<68>>::new();
+        let a = address!(allocator.alloc(12, 4).unwrap());
+        assert!(allocator.alloc(12, 4).is_some());
+        assert!(allocator.alloc(12, 4).is_some());
+        allocator.free(a).unwrap();
+        assert!(allocator.alloc(20, 4).is_none());
+        let error = AllocError::Fragmented {
+            free: 24,
+            largest_free: 12,
+        };
+        assert_eq!(allocator.alloc_error(20, 4), error);
+        assert_eq!((error.free(), error.largest_free()), (24, 12));
+    }
+
+    #[test]
+    fn alloc_error_alignment() {
+        // the heap is placed at 8 bytes after a multiple of 256, so that it
+        // does not contain any address aligned to 256.
+        let memory = Box::leak(vec![MaybeUninit::uninit(); 512].into_boxed_slice());
+        let offset = 8_usize.wrapping_sub(memory.as_ptr() as usize) % 256;
+        let region = Region::from(&mut memory[offset..offset + 136]);
+        let mut allocator = RawAllocator::<Region>::with_region(region);
+
+        assert!(allocator.alloc(8, 256).is_none());
+        let error = AllocError::Alignment {
+            free: 124,
+            largest_free: 124,
+        };
+        assert_eq!(allocator.alloc_error(8, 256), error);
+        assert_eq!((error.free(), error.largest_free()), (124, 124));
+    }
+
+    #[test]
+    fn alloc_error_alignment_reserved_by_tlsf() {
+        // the free block of 60 bytes is large enough for 32 bytes, but TLSF
+        // reserves 32 more bytes for the worst-case padding, so the block is
+        // not considered.
+        let mut allocator = RawAllocator::<Array<68>, Tlsf>::new();
+        assert!(allocator.alloc(32, 16).is_none());
+        let error = AllocError::Alignment {
+            free: 60,
+            largest_free: 60,
+        };
+        assert_eq!(allocator.alloc_error(32, 16), error);
+        assert!(allocator.alloc(32, 4).is_some());
+    }
+
     #[test]
     fn free_error_properties() {
         // pointless and rather dumb test case: check, that the derived traits
@@ -0,0 +1,510 @@
+//! Module providing the red zones of the [`RawAllocator`], which detect buffer
+//! overflows of allocations.
+//!
+//! If red zones are enabled, the memory handed out by an allocation is
+//! surrounded by guard bytes with a known pattern. The memory of such a block
+//! is laid out as follows:
+//!
+//! ```text
+//! | size | start | guard bytes | memory of the allocation | guard bytes |
+//! ```
+//!
+//! The first two words hold the requested size and the offset of the memory of
+//! the allocation inside of the block. The guard bytes in front of it are at
+//! least [`GUARD_SIZE`] bytes long (more, if the alignment requires it), the
+//! ones behind it fill the rest of the block. A write past either end of the
+//! allocation changes the guard bytes, so that the overflow is detected, when
+//! the block is freed or checked, before it reaches the header of the next
+//! block.
+//!
+//! The table of handles is the only used block without red zones, since it is
+//! never handed out.
+use super::buffer::{Buffer, ValidatedOffset};
+use super::entry::State;
+use super::{CheckError, FreeError, Header, Memory, RawAllocator, Strategy};
+
+use core::mem::{size_of, MaybeUninit};
+use core::ops::Range;
+
+/// The minimum number of guard bytes in front of and behind an allocation.
+const GUARD_SIZE: usize = 16;
+
+/// The value of each guard byte.
+const GUARD_PATTERN: u8 = 0xfd;
+
+/// The size of a word of the metadata at the start of a guarded block.
+const WORD: usize = size_of::<usize>();
+
+/// The size of the metadata at the start of a guarded block.
+const METADATA_SIZE: usize = 2 * WORD;
+
+impl<M: Memory, S: Strategy, H: Header> RawAllocator<M, S, H> {
+    /// Enable the red zones around all future allocations.
+    ///
+    /// The allocations are surrounded by guard bytes, which are verified when
+    /// they are freed (reported as [`FreeError::RedZoneViolation`]) and by
+    /// the heap checks (reported as [`CheckError::RedZoneViolation`]). This
+    /// needs [`METADATA_SIZE`] and twice [`GUARD_SIZE`] additional bytes per
+    /// allocation (plus the padding needed for the alignment).
+    ///
+    /// Since allocations without red zones cannot be told apart from guarded
+    /// ones, the red zones can only be enabled, as long as there are no
+    /// allocations. Whether the red zones are enabled is returned.
+    ///
+    /// [`FreeError::RedZoneViolation`]: super::FreeError::RedZoneViolation
+    pub fn enable_red_zones(&mut self) -> bool {
+        self.ensure_initialization();
+
+        let unused = self
+            .buffer
+            .entries()
+            .all(|offset| self.buffer.entry(offset).state() == State::Free);
+        self.red_zones |= unused;
+        self.red_zones
+    }
+
+    /// Check the red zones of the allocation containing `ptr`.
+    ///
+    /// If the pointer does not belong to an allocation or if red zones are
+    /// not enabled, there is nothing to check and `Ok(())` is returned.
+    pub fn check_red_zone(&mut self, ptr: *const u8) -> Result<(), CheckError> {
+        self.ensure_initialization();
+
+        match self.find_entry(ptr as *mut u8) {
+            Some(offset) => self.guard_check()(&self.buffer, offset),
+            None => Ok(()),
+        }
+    }
+
+    /// Build the check of the red zones of a single block for the heap checks.
+    ///
+    /// The check accepts all free blocks, the table of handles and all blocks,
+    /// if red zones are not enabled.
+    pub(super) fn guard_check(
+        &self,
+    ) -> impl Fn(&Buffer<M, H>, ValidatedOffset) -> Result<(), CheckError> {
+        let enabled = self.red_zones;
+        let table = self.handles.get();
+        move |buffer, offset| {
+            if enabled && Some(offset) != table && buffer.entry(offset).state() == State::Used {
+                verify(buffer.memory_of(offset))
+            } else {
+                Ok(())
+            }
+        }
+    }
+
+    /// Verify the red zones of the block at `offset`, before it is freed.
+    ///
+    /// A violation is reported with the address and size of the allocation,
+    /// just like by the heap checks.
+    pub(super) fn verify_before_free(&self, offset: ValidatedOffset) -> Result<(), FreeError> {
+        match self.guard_check()(&self.buffer, offset) {
+            Err(CheckError::RedZoneViolation { address, size }) => {
+                Err(FreeError::RedZoneViolation { address, size })
+            }
+            // the guard check does not report any other errors
+            _ => Ok(()),
+        }
+    }
+
+    /// Query the block size needed for an allocation of `n` bytes with the
+    /// alignment `align`.
+    ///
+    /// The size saturates on overflow, so that the allocation fails regularly.
+    pub(super) const fn guarded_size(&self, n: usize, align: usize) -> usize {
+        if self.red_zones {
+            start_of(align).saturating_add(n).saturating_add(GUARD_SIZE)
+        } else {
+            n
+        }
+    }
+
+    /// Query the block size needed to keep `n` bytes starting at the pointer
+    /// of an allocation, that is resized.
+    ///
+    /// The size saturates on overflow, so that the resize fails regularly.
+    pub(super) const fn guarded_tail_size(&self, n: usize) -> usize {
+        if self.red_zones {
+            n.saturating_add(GUARD_SIZE)
+        } else {
+            n
+        }
+    }
+
+    /// Write the red zones of a new allocation of `n` bytes with the
+    /// alignment `align` into the used block at the given offset and return
+    /// the memory of the allocation.
+    pub(super) fn guard(
+        &mut self,
+        offset: ValidatedOffset,
+        n: usize,
+        align: usize,
+    ) -> &mut [MaybeUninit<u8>] {
+        let memory = self.buffer.memory_of_mut(offset);
+        if !self.red_zones {
+            return memory;
+        }
+
+        let start = start_of(align);
+        write_word(memory, 1, start);
+        memory[METADATA_SIZE..start].fill(MaybeUninit::new(GUARD_PATTERN));
+        Self::guard_end(memory, start, n)
+    }
+
+    /// Query, whether the allocation in the used block at the given offset
+    /// can be resized at `ptr`.
+    ///
+    /// If red zones are enabled, `ptr` has to be the start of the allocation,
+    /// since the guard bytes in front of it have to be kept.
+    pub(super) fn is_resizable_at(&self, offset: ValidatedOffset, ptr: *mut u8) -> bool {
+        if !self.red_zones {
+            return true;
+        }
+        let memory = self.buffer.memory_of(offset);
+        user_range(memory).map_or(false, |range| {
+            ptr as usize == memory.as_ptr() as usize + range.start
+        })
+    }
+
+    /// Write the size `n` of the allocation in the used block at the given
+    /// offset and the guard bytes behind it after a successful resize.
+    pub(super) fn reguard(&mut self, offset: ValidatedOffset, n: usize) {
+        if !self.red_zones {
+            return;
+        }
+        let memory = self.buffer.memory_of_mut(offset);
+        let start = read_word(memory, 1).unwrap_or(METADATA_SIZE + GUARD_SIZE);
+        Self::guard_end(memory, start, n);
+    }
+
+    /// Query the memory of the allocation in the used block at the given
+    /// offset.
+    ///
+    /// If the metadata of the red zones is corrupted, the whole block is
+    /// returned.
+    pub(super) fn allocation_of(&mut self, offset: ValidatedOffset) -> &mut [MaybeUninit<u8>] {
+        let memory = self.buffer.memory_of_mut(offset);
+        if !self.red_zones {
+            return memory;
+        }
+        match user_range(memory) {
+            Some(range) => &mut memory[range],
+            None => memory,
+        }
+    }
+
+    /// Write the size `n` of the allocation starting at `start` and the guard
+    /// bytes behind it into the memory of a block and return the memory of
+    /// the allocation.
+    fn guard_end(memory: &mut [MaybeUninit<u8>], start: usize, n: usize) -> &mut [MaybeUninit<u8>] {
+        write_word(memory, 0, n);
+        memory[start + n..].fill(MaybeUninit::new(GUARD_PATTERN));
+        &mut memory[start..start + n]
+    }
+}
+
+/// Query the offset of the memory of an allocation with the alignment `align`
+/// inside of a guarded block.
+const fn start_of(align: usize) -> usize {
+    (METADATA_SIZE + GUARD_SIZE + align - 1) & !(align - 1)
+}
+
+/// Read the word with the given index of the metadata of a guarded block.
+fn read_word(memory: &[MaybeUninit<u8>], index: usize) -> Option<usize> {
+    let bytes = memory.get(index * WORD..(index + 1) * WORD)?;
+    let mut word = [0; WORD];
+    for (byte, value) in word.iter_mut().zip(bytes) {
+        // SAFETY: the metadata is written when the block is allocated and the
+        // heap memory is initialized, so the bytes are initialized.
+        *byte = unsafe { value.assume_init() };
+    }
+    Some(usize::from_ne_bytes(word))
+}
+
+/// Write the word with the given index of the metadata of a guarded block.
+fn write_word(memory: &mut [MaybeUninit<u8>], index: usize, word: usize) {
+    let bytes = &mut memory[index * WORD..(index + 1) * WORD];
+    for (byte, value) in bytes.iter_mut().zip(word.to_ne_bytes()) {
+        *byte = MaybeUninit::new(value);
+    }
+}
+
+/// Query the range of the allocation inside of the memory of a guarded block
+/// according to its metadata, if the metadata is valid.
+fn user_range(memory: &[MaybeUninit<u8>]) -> Option<Range<usize>> {
+    let size = read_word(memory, 0)?;
+    let start = read_word(memory, 1)?;
+    let end = start.checked_add(size)?;
+    let fits = start >= METADATA_SIZE + GUARD_SIZE && end.checked_add(GUARD_SIZE)? <= memory.len();
+    fits.then(|| start..end)
+}
+
+/// Verify the guard bytes of a guarded block.
+///
+/// A violation is reported with the address and the size of the allocation. If
+/// the metadata itself is corrupted, the address and size of the whole block
+/// are reported instead.
+fn verify(memory: &[MaybeUninit<u8>]) -> Result<(), CheckError> {
+    let address = memory.as_ptr() as usize;
+    let range = user_range(memory).ok_or(CheckError::RedZoneViolation {
+        address,
+        size: memory.len(),
+    })?;
+    let intact = memory[METADATA_SIZE..range.start]
+        .iter()
+        .chain(&memory[range.end..])
+        // SAFETY: the guard bytes are written when the block is allocated, so
+        // they are initialized.
+        .all(|byte| unsafe { byte.assume_init() } == GUARD_PATTERN);
+    if intact {
+        Ok(())
+    } else {
+        Err(CheckError::RedZoneViolation {
+            address: address + range.start,
+            size: range.len(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{
+        Array, BestFit, CheckError, FreeError, Header, Header16, Header32, Header64, RawAllocator,
+        Strategy, Tlsf,
+    };
+    use super::{GUARD_PATTERN, GUARD_SIZE, METADATA_SIZE};
+    use core::mem::MaybeUninit;
+
+    /// Allocate `n` bytes with the alignment `align` and fill them with ones.
+    fn alloc<const N: usize>(
+        allocator: &mut RawAllocator<Array<N>>,
+        n: usize,
+        align: usize,
+    ) -> *mut u8 {
+        let memory = allocator.alloc(n, align).unwrap();
+        assert_eq!(memory.len(), n);
+        memory.fill(MaybeUninit::new(1));
+        memory.as_mut_ptr().cast()
+    }
+
+    /// Create an allocator with enabled red zones.
+    fn allocator<const N: usize, S: Strategy, H: Header>() -> RawAllocator<Array<N>, S, H> {
+        let mut allocator = RawAllocator::new();
+        assert!(allocator.enable_red_zones());
+        allocator
+    }
+
+    #[test]
+    fn red_zones_surround_allocations() {
+        let mut allocator = allocator::<256, BestFit, Header32>();
+        let ptr = alloc(&mut allocator, 5, 32);
+        assert_eq!(ptr as usize % 32, 0);
+
+        let memory = allocator
+            .buffer
+            .memory_of(allocator.find_entry(ptr).unwrap());
+        let start = ptr as usize - memory.as_ptr() as usize;
+        assert!(start >= METADATA_SIZE + GUARD_SIZE);
+        assert!(memory.len() >= start + 5 + GUARD_SIZE);
+        for (index, byte) in memory.iter().enumerate().skip(METADATA_SIZE) {
+            // SAFETY: the block is initialized completely on allocation
+            let byte = unsafe { byte.assume_init() };
+            let expected = if (start..start + 5).contains(&index) {
+                1
+            } else {
+                GUARD_PATTERN
+            };
+            assert_eq!(byte, expected);
+        }
+        assert_eq!(allocator.check(), Ok(()));
+        assert_eq!(allocator.check_red_zone(ptr), Ok(()));
+        assert_eq!(allocator.free(ptr), Ok(()));
+        assert_eq!(allocator.check(), Ok(()));
+    }
+
+    #[test]
+    fn red_zones_of_zeroed_allocations() {
+        let mut allocator = allocator::<256, BestFit, Header32>();
+        let ptr = alloc(&mut allocator, 24, 4);
+        allocator.free(ptr).unwrap();
+
+        let memory = allocator.alloc_zeroed(24, 4).unwrap();
+        assert_eq!(memory.len(), 24);
+        // SAFETY: the memory is zeroed
+        assert!(memory.iter().all(|byte| unsafe { byte.assume_init() } == 0));
+        assert_eq!(allocator.check(), Ok(()));
+    }
+
+    #[test]
+    fn red_zone_overhead() {
+        // the documented cost of the red zones per allocation
+        let mut allocator = allocator::<256, BestFit, Header32>();
+        alloc(&mut allocator, 8, 4);
+        let overhead = if cfg!(target_pointer_width = "64") {
+            48
+        } else {
+            40
+        };
+        assert_eq!(allocator.stats().used, 8 + overhead);
+        assert_eq!(overhead, METADATA_SIZE + 2 * GUARD_SIZE);
+    }
+
+    #[test]
+    fn red_zones_need_an_unused_heap() {
+        let mut allocator = RawAllocator::<Array<256>>::new();
+        let ptr = alloc(&mut allocator, 8, 4);
+        assert!(!allocator.enable_red_zones());
+        assert_eq!(allocator.check_red_zone(ptr), Ok(()));
+
+        allocator.free(ptr).unwrap();
+        assert!(allocator.enable_red_zones());
+        // enabling them again has no effect
+        let ptr = alloc(&mut allocator, 8, 4);
+        assert!(allocator.enable_red_zones());
+        assert_eq!(allocator.check_red_zone(ptr), Ok(()));
+    }
+
+    #[test]
+    fn overflows_are_detected() {
+        let mut allocator = allocator::<256, BestFit, Header32>();
+        let a = alloc(&mut allocator, 8, 4);
+        let b = alloc(&mut allocator, 8, 4);
+        let error = CheckError::RedZoneViolation {
+            address: b as usize,
+            size: 8,
+        };
+
+        // SAFETY: the byte is part of the red zone behind the allocation
+        unsafe { b.add(8).write(0) };
+        assert_eq!(allocator.check_red_zone(a), Ok(()));
+        assert_eq!(allocator.check_red_zone(b), Err(error));
+        assert_eq!(allocator.check(), Err(error));
+        assert_eq!(allocator.check_step(1), Ok(false));
+        assert_eq!(allocator.check_step(1), Err(error));
+
+        let error = FreeError::RedZoneViolation {
+            address: b as usize,
+            size: 8,
+        };
+        assert_eq!(allocator.free(b), Err(error));
+        assert_eq!(allocator.check(), Ok(()));
+        assert_eq!(allocator.free(b), Err(FreeError::DoubleFreeDetected));
+
+        // an underflow is detected as well
+        // SAFETY: the byte is part of the red zone in front of the allocation
+        unsafe { a.sub(1).write(0) };
+        let error = FreeError::RedZoneViolation {
+            address: a as usize,
+            size: 8,
+        };
+        assert_eq!(allocator.free(a), Err(error));
+    }
+
+    #[test]
+    fn corrupted_metadata_is_detected() {
+        let mut allocator = allocator::<256, BestFit, Header32>();
+        let ptr = alloc(&mut allocator, 8, 4);
+        let offset = allocator.find_entry(ptr).unwrap();
+        let memory = allocator.buffer.memory_of_mut(offset);
+        memory[..METADATA_SIZE].fill(MaybeUninit::new(0xff));
+        let (address, size) = (memory.as_ptr() as usize, memory.len());
+        let error = CheckError::RedZoneViolation { address, size };
+        assert_eq!(allocator.check(), Err(error));
+        let error = FreeError::RedZoneViolation { address, size };
+        assert_eq!(allocator.free(ptr), Err(error));
+        assert_eq!(allocator.check(), Ok(()));
+    }
+
+    #[test]
+    fn resizing_moves_the_red_zone() {
+        let mut allocator = allocator::<256, BestFit, Header32>();
+        let ptr = alloc(&mut allocator, 8, 4);
+        assert!(allocator.resize(ptr, 40));
+        // SAFETY: the allocation was grown to 40 bytes
+        unsafe { ptr.add(39).write(1) };
+        assert_eq!(allocator.check(), Ok(()));
+        assert!(allocator.resize(ptr, 2));
+        assert_eq!(allocator.check(), Ok(()));
+
+        // SAFETY: the byte was part of the allocation before it was shrunk
+        unsafe { ptr.add(2).write(1) };
+        let error = CheckError::RedZoneViolation {
+            address: ptr as usize,
+            size: 2,
+        };
+        assert_eq!(allocator.check_red_zone(ptr), Err(error));
+
+        // only the start of the allocation can be resized
+        assert!(!allocator.resize(ptr.wrapping_add(1), 1));
+        assert!(!allocator.resize(ptr.wrapping_sub(1), 1));
+    }
+
+    #[test]
+    fn red_zones_of_handles() {
+        let mut allocator = allocator::<512, BestFit, Header32>();
+        let a = allocator.alloc_handle(8, 4).unwrap();
+        let b = allocator.alloc_handle(12, 4).unwrap();
+        allocator.lock(b).unwrap().fill(MaybeUninit::new(2));
+        assert!(allocator.unlock(b));
+        allocator.free_handle(a).unwrap();
+        allocator.compact();
+        assert_eq!(allocator.check(), Ok(()));
+
+        let memory = allocator.lock(b).unwrap();
+        assert_eq!(memory.len(), 12);
+        // SAFETY: the memory was initialized before the compaction
+        assert!(memory.iter().all(|byte| unsafe { byte.assume_init() } == 2));
+        // SAFETY: the byte is part of the red zone behind the allocation
+        unsafe { memory.as_mut_ptr().add(12).write(MaybeUninit::new(0)) };
+        assert!(matches!(
+            allocator.check(),
+            Err(CheckError::RedZoneViolation { size: 12, .. })
+        ));
+        assert!(matches!(
+            allocator.free_handle(b),
+            Err(FreeError::RedZoneViolation { size: 12, .. })
+        ));
+    }
+
+    /// Perform pseudo-random allocations, resizes and releases, that use all
+    /// of their memory, and check, that the red zones stay intact.
+    fn random_operations<S: Strategy, H: Header>() {
+        let mut allocator = allocator::<1024, S, H>();
+        let mut pointers = [core::ptr::null_mut::<u8>(); 8];
+        let mut seed = 0x8765_4321_u32;
+        for _ in 0..500 {
+            seed = seed.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let slot = (seed >> 8) as usize % pointers.len();
+            let size = (seed >> 16) as usize % 48 + 1;
+            let align = 1 << ((seed >> 24) % 4);
+            let ptr = pointers[slot];
+            if ptr.is_null() {
+                if let Some(memory) = allocator.alloc(size, align) {
+                    memory.fill(MaybeUninit::new(1));
+                    pointers[slot] = memory.as_mut_ptr().cast();
+                }
+            } else if seed & 0x80 == 0 {
+                if allocator.resize(ptr, size) {
+                    // SAFETY: the allocation was resized to `size` bytes
+                    unsafe { ptr.write_bytes(1, size) };
+                }
+            } else {
+                assert_eq!(allocator.free(ptr), Ok(()));
+                pointers[slot] = core::ptr::null_mut();
+            }
+            assert_eq!(allocator.check(), Ok(()));
+        }
+        for ptr in pointers.into_iter().filter(|ptr| !ptr.is_null()) {
+            assert_eq!(allocator.free(ptr), Ok(()));
+        }
+    }
+
+    #[test]
+    fn random_operations_keep_the_red_zones_intact() {
+        random_operations::<BestFit, Header32>();
+        random_operations::<Tlsf, Header32>();
+        random_operations::<BestFit, Header16>();
+        random_operations::<BestFit, Header64>();
+    }
+}
@@ -0,0 +1,180 @@
+//! Module providing the usage statistics of a heap and the information about
+//! its blocks.
+//!
+//! Most of the statistics are a snapshot of the heap, which is obtained by
+//! walking over all blocks. The values, that depend on the history of the heap
+//! (e.g. the peak usage), are tracked by the [`Counters`] of the raw allocator.
+use super::entry::State;
+
+/// The state of a block of the heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BlockState {
+    /// The block is free and can be used for new allocations.
+    Free,
+    /// The block is used by an allocation.
+    Used,
+    /// The block is used by the allocator itself, i.e. it holds the table of
+    /// handles.
+    Internal,
+}
+impl From<State> for BlockState {
+    fn from(state: State) -> Self {
+        match state {
+            State::Free => Self::Free,
+            State::Used => Self::Used,
+        }
+    }
+}
+
+/// Information about a single block of the heap.
+///
+/// This is reported for each block, when walking over the heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockInfo {
+    /// The address of the memory of the block, i.e. directly behind its
+    /// header.
+    ///
+    /// For used blocks this is the pointer returned by the allocation (unless
+    /// the allocation is part of a larger block, e.g. of a
+    /// [`Slab`](crate::Slab), or is surrounded by red zones).
+    pub address: usize,
+    /// The size of the memory of the block in bytes (excluding the header).
+    pub size: usize,
+    /// Whether the block is free, used by an allocation or used by the
+    /// allocator itself.
+    pub state: BlockState,
+}
+
+/// Statistics about the usage of a heap.
+///
+/// The sizes are given in bytes and refer to the memory of the blocks, i.e.
+/// excluding their headers. The used and free bytes plus the overhead always
+/// add up to the size of the heap.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Stats {
+    /// The number of bytes in used blocks.
+    ///
+    /// This is at least the sum of the requested sizes of all live
+    /// allocations, since the blocks are rounded up to the granule.
+    pub used: usize,
+    /// The number of bytes in free blocks.
+    pub free: usize,
+    /// The number of bytes occupied by the block headers, by the bitmap of
+    /// block starts, by the table of handles and by the unusable bytes at the
+    /// edges of the heap.
+    pub overhead: usize,
+    /// The size of the largest free block.
+    ///
+    /// This is the largest allocation, that can succeed (unless it needs a
+    /// larger alignment than the granule).
+    pub largest_free: usize,
+    /// The number of used blocks, i.e. the live allocations.
+    pub allocations: usize,
+    /// The number of free blocks.
+    pub free_blocks: usize,
+    /// The largest number of used bytes at any time.
+    pub peak_used: usize,
+    /// The number of successful allocations (including reallocations, that
+    /// moved the memory) since the creation of the allocator.
+    pub total_allocations: usize,
+    /// The number of failed allocations since the creation of the allocator.
+    ///
+    /// Each request, that returns a null pointer or an error, counts once. A
+    /// request, that succeeds after reclaiming memory, does not count.
+    pub failed_allocations: usize,
+}
+
+/// The counters of the raw allocator, that track the history of the heap.
+///
+/// All counters are zero initially, so that a freshly created allocator is
+/// placed in the `.bss`-section.
+pub struct Counters {
+    /// The number of bytes in used blocks.
+    used: usize,
+    /// The largest value of `used` so far.
+    peak_used: usize,
+    /// The number of successful allocations.
+    allocations: usize,
+    /// The number of failed allocations.
+    failures: usize,
+}
+impl Counters {
+    /// The counters of a new allocator.
+    pub const NEW: Self = Self {
+        used: 0,
+        peak_used: 0,
+        allocations: 0,
+        failures: 0,
+    };
+
+    /// Query the number of bytes in used blocks.
+    pub const fn used(&self) -> usize {
+        self.used
+    }
+
+    /// Record an allocation of a block with the given size.
+    pub fn allocated(&mut self, size: usize) {
+        self.allocations = self.allocations.saturating_add(1);
+        self.used += size;
+        self.peak_used = self.peak_used.max(self.used);
+    }
+
+    /// Record a failed allocation.
+    pub fn failed(&mut self) {
+        self.failures = self.failures.saturating_add(1);
+    }
+
+    /// Record the release of a block with the given size.
+    pub fn released(&mut self, size: usize) {
+        self.used -= size;
+    }
+
+    /// Record the resizing of a block from the `old` to the `new` size.
+    pub fn resized(&mut self, old: usize, new: usize) {
+        self.used = self.used - old + new;
+        self.peak_used = self.peak_used.max(self.used);
+    }
+
+    /// Fill in the counted values into the given statistics.
+    pub const fn apply(&self, stats: Stats) -> Stats {
+        Stats {
+            peak_used: self.peak_used,
+            total_allocations: self.allocations,
+            failed_allocations: self.failures,
+            ..stats
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Counters, Stats};
+
+    #[test]
+    fn counters() {
+        let mut counters = Counters::NEW;
+        counters.allocated(8);
+        counters.allocated(16);
+        counters.failed();
+        counters.released(8);
+        counters.resized(16, 12);
+        counters.allocated(4);
+
+        let stats = Stats {
+            used: 16,
+            free: 0,
+            overhead: 0,
+            largest_free: 0,
+            allocations: 2,
+            free_blocks: 0,
+            peak_used: 0,
+            total_allocations: 0,
+            failed_allocations: 0,
+        };
+        let stats = counters.apply(stats);
+        assert_eq!(stats.peak_used, 24);
+        assert_eq!(stats.total_allocations, 3);
+        assert_eq!(stats.failed_allocations, 1);
+        assert_eq!(counters.used, stats.used);
+    }
+}
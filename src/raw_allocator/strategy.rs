@@ -0,0 +1,335 @@
+//! Module providing the [`Strategy`] trait and the strategies based on a single
+//! list of free entries: [`BestFit`] (the default), [`FirstFit`], [`NextFit`]
+//! and [`WorstFit`].
+//!
+//! A strategy decides, which free entry is used for a new allocation. For that
+//! purpose it keeps track of all free entries in its own data structure, e.g.
+//! a list or a set of size-segregated lists.
+use super::buffer::{Buffer, ValidatedOffset};
+use super::free_list::FreeList;
+use super::header::Header;
+use super::memory::Memory;
+
+use core::cmp::Reverse;
+
+/// A placement strategy for new allocations.
+///
+/// This trait is implemented by the strategies provided by this crate, e.g.
+/// [`BestFit`], [`FirstFit`] and [`Tlsf`](crate::Tlsf). It cannot be implemented outside of
+/// this crate, as the methods operate on crate-internal types. The strategy is
+/// selected via the second type parameter of [`Allocator`](crate::Allocator).
+pub trait Strategy: Sized {
+    /// The state of the strategy without any free entries.
+    ///
+    /// This has to consist of zeroes only, so that a freshly created allocator
+    /// is placed in the `.bss`-section.
+    const EMPTY: Self;
+
+    /// The minimum size of the memory of a block, that can be managed by this
+    /// strategy, as a multiple of the header size.
+    ///
+    /// Allocations smaller than this are enlarged and free entries are never
+    /// split, if this would create a smaller free entry.
+    const MIN_BLOCK_WORDS: usize;
+
+    /// Register a new free entry.
+    fn insert<M: Memory, H: Header>(&mut self, buffer: &mut Buffer<M, H>, offset: ValidatedOffset);
+
+    /// Unregister a free entry, e.g. because it is used or absorbed by another
+    /// free entry.
+    ///
+    /// This is always called before the entry itself is modified.
+    fn remove<M: Memory, H: Header>(&mut self, buffer: &mut Buffer<M, H>, offset: ValidatedOffset);
+
+    /// Search for a free entry with at least `size` bytes of memory, that are
+    /// aligned to `align`.
+    ///
+    /// The aligned memory might not start directly at the beginning of the
+    /// entry, see [`Buffer::alignment_padding()`] for the required padding,
+    /// which has to fit into the entry as well. The entry is not removed from
+    /// the strategy, this is done by a subsequent call to
+    /// [`remove()`](Self::remove).
+    fn find<M: Memory, H: Header>(
+        &mut self,
+        buffer: &Buffer<M, H>,
+        size: usize,
+        align: usize,
+    ) -> Option<ValidatedOffset>;
+}
+
+/// Check, whether an allocation of `size` bytes aligned to `align` fits into
+/// the free entry at the given offset.
+///
+/// This is used by the strategies without a minimum block size.
+fn fits<M: Memory, H: Header>(
+    buffer: &Buffer<M, H>,
+    offset: ValidatedOffset,
+    size: usize,
+    align: usize,
+) -> bool {
+    buffer.alignment_padding(offset, size, align, 0).is_some()
+}
+
+/// The default strategy: use the smallest free entry, that is large enough.
+///
+/// This strategy keeps all free entries in a single list, that is searched for
+/// each allocation. If there are multiple equally sized candidates, the one
+/// with the lowest address is chosen. Therefore the run time of an allocation
+/// is linear in the number of free entries.
+pub struct BestFit {
+    /// The list of all free entries in the buffer.
+    free_list: FreeList,
+}
+impl Strategy for BestFit {
+    const EMPTY: Self = Self {
+        free_list: FreeList::new(),
+    };
+
+    const MIN_BLOCK_WORDS: usize = 0;
+
+    fn insert<M: Memory, H: Header>(&mut self, buffer: &mut Buffer<M, H>, offset: ValidatedOffset) {
+        self.free_list.push(buffer, offset);
+    }
+
+    fn remove<M: Memory, H: Header>(&mut self, buffer: &mut Buffer<M, H>, offset: ValidatedOffset) {
+        self.free_list.remove(buffer, offset);
+    }
+
+    fn find<M: Memory, H: Header>(
+        &mut self,
+        buffer: &Buffer<M, H>,
+        size: usize,
+        align: usize,
+    ) -> Option<ValidatedOffset> {
+        self.free_list
+            .iter(buffer)
+            .filter(|offset| fits(buffer, *offset, size, align))
+            .min_by_key(|offset| (buffer.entry(*offset).size(), *offset))
+    }
+}
+
+/// Use the free entry with the lowest address, that is large enough.
+///
+/// The free entries are kept in a list sorted by address. The search stops at
+/// the first sufficiently large entry, which makes allocations fast, if there
+/// is enough memory at the start of the heap. Freeing memory has to search the
+/// position of the free entry in the list, so it is linear in the number of
+/// free entries. This strategy tends to accumulate small fragments at the start
+/// of the heap, while keeping large blocks at its end.
+pub struct FirstFit {
+    /// The list of all free entries in the buffer sorted by address.
+    free_list: FreeList,
+}
+impl Strategy for FirstFit {
+    const EMPTY: Self = Self {
+        free_list: FreeList::new(),
+    };
+
+    const MIN_BLOCK_WORDS: usize = 0;
+
+    fn insert<M: Memory, H: Header>(&mut self, buffer: &mut Buffer<M, H>, offset: ValidatedOffset) {
+        self.free_list.insert_sorted(buffer, offset);
+    }
+
+    fn remove<M: Memory, H: Header>(&mut self, buffer: &mut Buffer<M, H>, offset: ValidatedOffset) {
+        self.free_list.remove(buffer, offset);
+    }
+
+    fn find<M: Memory, H: Header>(
+        &mut self,
+        buffer: &Buffer<M, H>,
+        size: usize,
+        align: usize,
+    ) -> Option<ValidatedOffset> {
+        self.free_list
+            .iter(buffer)
+            .find(|offset| fits(buffer, *offset, size, align))
+    }
+}
+
+/// Use the next free entry, that is large enough, starting at the position of
+/// the last allocation.
+///
+/// This works like [`FirstFit`], but the search does not start at the lowest
+/// address, but where the previous allocation was placed. If there is no
+/// suitable entry until the end of the heap, the search wraps around. This
+/// spreads the allocations over the whole heap instead of fragmenting its
+/// start, but large free blocks are split up more frequently.
+pub struct NextFit {
+    /// The list of all free entries in the buffer sorted by address.
+    free_list: FreeList,
+    /// The offset of the previous allocation, where the next search starts.
+    ///
+    /// This is only used as a position, the entry at this offset might not
+    /// exist anymore (e.g. due to coalescing).
+    rover: Option<ValidatedOffset>,
+}
+impl Strategy for NextFit {
+    const EMPTY: Self = Self {
+        free_list: FreeList::new(),
+        rover: None,
+    };
+
+    const MIN_BLOCK_WORDS: usize = 0;
+
+    fn insert<M: Memory, H: Header>(&mut self, buffer: &mut Buffer<M, H>, offset: ValidatedOffset) {
+        self.free_list.insert_sorted(buffer, offset);
+    }
+
+    fn remove<M: Memory, H: Header>(&mut self, buffer: &mut Buffer<M, H>, offset: ValidatedOffset) {
+        self.free_list.remove(buffer, offset);
+    }
+
+    fn find<M: Memory, H: Header>(
+        &mut self,
+        buffer: &Buffer<M, H>,
+        size: usize,
+        align: usize,
+    ) -> Option<ValidatedOffset> {
+        let rover = self.rover;
+        let suitable = |offset: &ValidatedOffset| fits(buffer, *offset, size, align);
+        let found = self
+            .free_list
+            .iter(buffer)
+            .filter(suitable)
+            .find(|offset| rover.map_or(true, |rover| *offset >= rover))
+            .or_else(|| self.free_list.iter(buffer).find(suitable))?;
+        self.rover = Some(found);
+        Some(found)
+    }
+}
+
+/// Use the largest free entry.
+///
+/// This strategy keeps all free entries in a single list, that is searched for
+/// each allocation (like [`BestFit`]). If there are multiple equally sized
+/// candidates, the one with the lowest address is chosen. The idea is, that
+/// the remainder of the split block is still large enough to be useful, but
+/// this quickly consumes the large blocks needed for large allocations.
+pub struct WorstFit {
+    /// The list of all free entries in the buffer.
+    free_list: FreeList,
+}
+impl Strategy for WorstFit {
+    const EMPTY: Self = Self {
+        free_list: FreeList::new(),
+    };
+
+    const MIN_BLOCK_WORDS: usize = 0;
+
+    fn insert<M: Memory, H: Header>(&mut self, buffer: &mut Buffer<M, H>, offset: ValidatedOffset) {
+        self.free_list.push(buffer, offset);
+    }
+
+    fn remove<M: Memory, H: Header>(&mut self, buffer: &mut Buffer<M, H>, offset: ValidatedOffset) {
+        self.free_list.remove(buffer, offset);
+    }
+
+    fn find<M: Memory, H: Header>(
+        &mut self,
+        buffer: &Buffer<M, H>,
+        size: usize,
+        align: usize,
+    ) -> Option<ValidatedOffset> {
+        self.free_list
+            .iter(buffer)
+            .filter(|offset| fits(buffer, *offset, size, align))
+            .max_by_key(|offset| (buffer.entry(*offset).size(), Reverse(*offset)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::{Array, RawAllocator, State};
+    use super::{BestFit, FirstFit, NextFit, Strategy, WorstFit};
+
+    /// Bring a fresh allocator into the following layout:
+    /// `free(16) used(4) free(8) used(4) free(24) used(4) free(12) used(16)`.
+    ///
+    /// The addresses of the free blocks are returned in the order of memory.
+    fn fragment<S: Strategy>(allocator: &mut RawAllocator<Array<136>, S>) -> [usize; 4] {
+        let mut free = [0; 4];
+        for (index, &size) in [16, 8, 24, 12].iter().enumerate() {
+            free[index] = allocator.alloc(size, 4).unwrap().as_ptr() as usize;
+            allocator.alloc(4, 4).unwrap();
+        }
+        allocator.alloc(16, 4).unwrap();
+        assert!(allocator.alloc(0, 4).is_none(), "heap has to be full");
+
+        // free in a scrambled order, so that the order does not matter
+        for &index in &[2, 0, 3, 1] {
+            allocator.free(free[index] as *mut u8).unwrap();
+        }
+        free
+    }
+
+    /// Allocate 8, 8 and 4 bytes and return the resulting addresses.
+    fn placements<S: Strategy>(allocator: &mut RawAllocator<Array<136>, S>) -> [usize; 3] {
+        let mut address = |size| allocator.alloc(size, 4).unwrap().as_ptr() as usize;
+        [address(8), address(8), address(4)]
+    }
+
+    #[test]
+    fn best_fit_placement() {
+        let mut allocator = RawAllocator::<Array<136>, BestFit>::new();
+        let [a, b, _c, d] = fragment(&mut allocator);
+        assert_eq!(placements(&mut allocator), [b, d, a]);
+    }
+
+    #[test]
+    fn first_fit_placement() {
+        // the remainder of the first block is used for the last allocation
+        let mut allocator = RawAllocator::<Array<136>, FirstFit>::new();
+        let [a, b, _c, _d] = fragment(&mut allocator);
+        assert_eq!(placements(&mut allocator), [a, b, a + 12]);
+    }
+
+    #[test]
+    fn next_fit_placement() {
+        // the search wraps around at the end of the heap and continues after
+        // the previous allocation afterwards
+        let mut allocator = RawAllocator::<Array<136>, NextFit>::new();
+        let [a, b, c, _d] = fragment(&mut allocator);
+        assert_eq!(placements(&mut allocator), [a, b, c]);
+    }
+
+    #[test]
+    fn worst_fit_placement() {
+        // the remainder of the largest block is as large as the fourth block,
+        // the one with the lower address is used then
+        let mut allocator = RawAllocator::<Array<136>, WorstFit>::new();
+        let [a, _b, c, _d] = fragment(&mut allocator);
+        assert_eq!(placements(&mut allocator), [c, a, c + 12]);
+    }
+
+    #[test]
+    fn free_list_contains_exactly_the_free_entries() {
+        let mut allocator = RawAllocator::<Array<128>>::new();
+        let ptrs = [4, 8, 12, 4, 4, 16, 8, 4]
+            .iter()
+            .map(|&size| allocator.alloc(size, 4).unwrap().as_mut_ptr().cast())
+            .collect::<Vec<_>>();
+        for &index in &[1, 3, 6, 4, 0] {
+            allocator.free(ptrs[index]).unwrap();
+        }
+        let _ = allocator.alloc(4, 4).unwrap();
+        let _ = allocator.alloc(8, 4).unwrap();
+
+        let mut listed = allocator
+            .strategy
+            .free_list
+            .iter(&allocator.buffer)
+            .collect::<Vec<_>>();
+        listed.sort();
+        // free entries of size 0 are never part of the list
+        let scanned = allocator
+            .buffer
+            .entries()
+            .map(|offset| (offset, allocator.buffer.entry(offset)))
+            .filter(|(_offset, entry)| entry.state() == State::Free)
+            .filter(|(_offset, entry)| entry.size() > 0)
+            .map(|(offset, _entry)| offset)
+            .collect::<Vec<_>>();
+        assert_eq!(listed, scanned);
+    }
+}
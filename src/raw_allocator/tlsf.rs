@@ -0,0 +1,315 @@
+//! Module providing the [`Tlsf`] strategy ("Two-Level Segregated Fit").
+//!
+//! This strategy keeps the free entries in many doubly linked lists, one per
+//! size class. The size classes are organized in two levels: the first level
+//! splits the sizes in powers of two, the second level splits each of those
+//! ranges into [`SECOND_LEVELS`] equally sized sub-ranges. Two bitmaps store,
+//! which lists are non-empty. Searching a suitable list is therefore done with
+//! a few bit operations instead of a loop, which makes the run time of
+//! inserting, removing and finding a free entry constant, i.e. independent of
+//! the number of entries.
+//!
+//! Small sizes (below `SECOND_LEVELS * 4` bytes) are handled by the first
+//! first-level class, which is split linearly into classes of four bytes each.
+use super::buffer::{Buffer, Link, ValidatedOffset};
+use super::header::Header;
+use super::memory::Memory;
+use super::strategy::Strategy;
+
+/// The number of bits used to index the second level.
+const SECOND_LEVEL_BITS: usize = 4;
+/// The number of second-level classes per first-level class.
+const SECOND_LEVELS: usize = 1 << SECOND_LEVEL_BITS;
+/// The width of the linearly split classes in bytes.
+const LINEAR_CLASS_SIZE: usize = 4;
+/// The smallest size, that is not part of the linearly split class.
+const LINEAR_LIMIT: usize = SECOND_LEVELS * LINEAR_CLASS_SIZE;
+/// The number of first-level classes.
+///
+/// The first class holds the sizes below [`LINEAR_LIMIT`] (`2^6`), the others
+/// one power of two each up to the maximum entry size (less than `2^BITS`).
+const FIRST_LEVELS: usize = usize::BITS as usize - 6 + 1;
+
+/// The "Two-Level Segregated Fit" strategy with a constant run time.
+///
+/// This strategy guarantees a bounded worst-case execution time for allocating
+/// memory and for freeing a pointer returned by the allocator, which does not
+/// depend on the number or sizes of the current allocations. This makes it
+/// suitable for hard real-time systems. The bound does not cover the work,
+/// that is proportional to the size of a block (zeroing memory in
+/// `alloc_zeroed()`, copying it in `realloc()` or freeing a pointer into the
+/// middle of a block), nor determining the reason of a failed allocation or
+/// the functions inspecting the whole heap (e.g. `stats()`).
+///
+/// Instead of searching the best fitting block, this strategy uses a "good
+/// fit": the request is rounded up to the next size class and a block of that
+/// class (or a larger one) is used. Since blocks are split after the
+/// allocation, the remaining memory is not lost. Compared to the best-fit
+/// strategy, the fragmentation is bounded as follows:
+/// - requests below 64 bytes are served exactly, since those size classes are
+///   four bytes wide.
+/// - the size classes of larger requests are at most 1/16th of their size
+///   wide. A free block is therefore only overlooked, if it is less than
+///   `n/16` bytes larger than the request of `n` bytes, i.e. at most 6.25% of
+///   the request are wasted per allocation (an allocation of `n` bytes might
+///   fail, although there is a free block of a size less than `n + n/16`
+///   bytes).
+/// - allocations with an alignment above the granule reserve room for the
+///   worst-case padding while searching: the block has to be `align` plus four
+///   header sizes (`align + 16` bytes with the default
+///   [`Header32`](super::Header32)) larger than the request. Only the needed
+///   padding is used afterwards, but the allocation might fail, although a
+///   block with enough room for the actual padding exists.
+///
+/// Each free block has to store two links and a boundary tag, therefore each
+/// allocation occupies at least three header sizes (12 bytes with the default
+/// [`Header32`](super::Header32)) of memory plus the header. A block is only
+/// split, if the remainder can hold such a minimal block, otherwise the
+/// remainder (less than 16 bytes by default) is handed out as part of the
+/// allocation.
+///
+/// The padding of aligned allocations is split off as a free block, so only
+/// the needed padding is used by the allocation.
+///
+/// The bookkeeping data (the list heads and bitmaps) is part of each allocator
+/// in addition to the heap itself. It requires less than 2KiB of memory on
+/// 32-bit targets and about 7.5KiB on 64-bit targets, since there is a list
+/// head for each of the 16 second-level classes of every bit of a `usize`.
+pub struct Tlsf {
+    /// The bitmap of non-empty first-level classes.
+    first_level: usize,
+    /// The bitmaps of non-empty second-level classes for each first level.
+    second_level: [u16; FIRST_LEVELS],
+    /// The heads of the lists of free entries per size class.
+    heads: [[Link; SECOND_LEVELS]; FIRST_LEVELS],
+}
+impl Tlsf {
+    /// Compute the size class, to which a free entry of `size` belongs.
+    #[allow(clippy::cast_possible_truncation)] // the bit index is small
+    const fn class_of(size: usize) -> (usize, usize) {
+        if size < LINEAR_LIMIT {
+            (0, size / LINEAR_CLASS_SIZE)
+        } else {
+            let most_significant_bit = (usize::BITS - 1 - size.leading_zeros()) as usize;
+            let first = most_significant_bit - (LINEAR_LIMIT.trailing_zeros() as usize) + 1;
+            let second = (size >> (most_significant_bit - SECOND_LEVEL_BITS)) % SECOND_LEVELS;
+            (first, second)
+        }
+    }
+
+    /// Compute the smallest size class, whose entries are all at least `size`
+    /// bytes large.
+    ///
+    /// If there is no such class, `None` is returned.
+    #[allow(clippy::cast_possible_truncation)] // the bit index is small
+    fn search_class_of(size: usize) -> Option<(usize, usize)> {
+        let size = if size < LINEAR_LIMIT {
+            size
+        } else {
+            let most_significant_bit = (usize::BITS - 1 - size.leading_zeros()) as usize;
+            let class_width = 1 << (most_significant_bit - SECOND_LEVEL_BITS);
+            size.checked_add(class_width - 1)?
+        };
+        let (first, second) = Self::class_of(size);
+        (first < FIRST_LEVELS).then(|| (first, second))
+    }
+}
+impl Strategy for Tlsf {
+    const EMPTY: Self = Self {
+        first_level: 0,
+        second_level: [0; FIRST_LEVELS],
+        heads: [[Link::NONE; SECOND_LEVELS]; FIRST_LEVELS],
+    };
+
+    const MIN_BLOCK_WORDS: usize = 3;
+
+    fn insert<M: Memory, H: Header>(&mut self, buffer: &mut Buffer<M, H>, offset: ValidatedOffset) {
+        let size = buffer.entry(offset).size();
+        if size < Self::MIN_BLOCK_WORDS * Buffer::<M, H>::HEADER_SIZE {
+            return; // only possible for tiny heaps, the block is unusable anyway
+        }
+
+        let (first, second) = Self::class_of(size);
+        let head = self.heads[first][second].get();
+        buffer.set_next_free(offset, head);
+        buffer.set_previous_free(offset, None);
+        if let Some(head) = head {
+            buffer.set_previous_free(head, Some(offset));
+        }
+        self.heads[first][second] = Link::new(Some(offset));
+        self.first_level |= 1 << first;
+        self.second_level[first] |= 1 << second;
+    }
+
+    fn remove<M: Memory, H: Header>(&mut self, buffer: &mut Buffer<M, H>, offset: ValidatedOffset) {
+        let size = buffer.entry(offset).size();
+        if size < Self::MIN_BLOCK_WORDS * Buffer::<M, H>::HEADER_SIZE {
+            return;
+        }
+
+        let (first, second) = Self::class_of(size);
+        let previous = buffer.previous_free(offset);
+        let next = buffer.next_free(offset);
+        match previous {
+            Some(previous) => buffer.set_next_free(previous, next),
+            None => self.heads[first][second] = Link::new(next),
+        }
+        if let Some(next) = next {
+            buffer.set_previous_free(next, previous);
+        }
+
+        if self.heads[first][second] == Link::NONE {
+            self.second_level[first] &= !(1 << second);
+            if self.second_level[first] == 0 {
+                self.first_level &= !(1 << first);
+            }
+        }
+    }
+
+    fn find<M: Memory, H: Header>(
+        &mut self,
+        _: &Buffer<M, H>,
+        size: usize,
+        align: usize,
+    ) -> Option<ValidatedOffset> {
+        // for larger alignments search a block, that is large enough for the
+        // worst-case padding in front of the allocation. This way the found
+        // block is known to be suitable without looking at its address.
+        let header_size = Buffer::<M, H>::HEADER_SIZE;
+        let size = if align > Buffer::<M, H>::ALIGNMENT {
+            size.checked_add(align + header_size + Self::MIN_BLOCK_WORDS * header_size)?
+        } else {
+            size
+        };
+        let (first, second) = Self::search_class_of(size)?;
+
+        // search the requested first-level class for a non-empty second-level
+        // class, that is large enough. If there is none, use the next larger
+        // non-empty first-level class, where any entry is large enough.
+        let second_level = self.second_level[first] & (u16::MAX << second);
+        let (first, second_level) = if second_level == 0 {
+            let first_level = self.first_level & (usize::MAX << (first + 1));
+            if first_level == 0 {
+                return None;
+            }
+            let first = first_level.trailing_zeros() as usize;
+            (first, self.second_level[first])
+        } else {
+            (first, second_level)
+        };
+        let second = second_level.trailing_zeros() as usize;
+        self.heads[first][second].get()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::super::buffer::{Buffer, ValidatedOffset};
+    use super::super::memory::Array;
+    use super::super::strategy::Strategy;
+    use super::{Tlsf, FIRST_LEVELS};
+
+    #[test]
+    fn size_classes() {
+        // the small sizes are split linearly
+        assert_eq!(Tlsf::class_of(12), (0, 3));
+        assert_eq!(Tlsf::class_of(60), (0, 15));
+        // afterwards each power of two is split into 16 classes
+        assert_eq!(Tlsf::class_of(64), (1, 0));
+        assert_eq!(Tlsf::class_of(68), (1, 1));
+        assert_eq!(Tlsf::class_of(124), (1, 15));
+        assert_eq!(Tlsf::class_of(128), (2, 0));
+        assert_eq!(Tlsf::class_of(4096 + 256), (7, 1));
+        assert_eq!(Tlsf::class_of(0xFFFF_FFFC), (26, 15));
+        assert_eq!(Tlsf::class_of(usize::MAX - 3), (FIRST_LEVELS - 1, 15));
+    }
+
+    #[test]
+    fn search_classes() {
+        // the linear classes are exact
+        assert_eq!(Tlsf::search_class_of(12), Some((0, 3)));
+        // otherwise the request is rounded up to the next class
+        assert_eq!(Tlsf::search_class_of(64), Some((1, 0)));
+        assert_eq!(Tlsf::search_class_of(68), Some((1, 1)));
+        assert_eq!(Tlsf::search_class_of(72), Some((1, 2)));
+        assert_eq!(Tlsf::search_class_of(4096 + 4), Some((7, 1)));
+        // there is no class for too large requests
+        assert_eq!(Tlsf::search_class_of(usize::MAX - 3), None);
+    }
+
+    /// Create a buffer with free entries of the given sizes separated by used
+    /// entries of size 4.
+    fn buffer_with_free_entries<const N: usize>(
+        sizes: &[usize],
+    ) -> (Buffer<Array<N>>, Vec<ValidatedOffset>) {
+        let mut buffer = Buffer::<Array<N>>::new();
+        let mut next = buffer.ensure_initialization().unwrap();
+        let mut free = Vec::new();
+        for &size in sizes {
+            let used = buffer.mark_as_used(next, size).unwrap();
+            free.push(next);
+            next = buffer.mark_as_used(used, 4).unwrap();
+        }
+        for &offset in &free {
+            let size = buffer.entry(offset).size();
+            buffer.mark_as_free(offset, size);
+        }
+        (buffer, free)
+    }
+
+    #[test]
+    fn find_good_fit() {
+        let (mut buffer, free) = buffer_with_free_entries::<512>(&[12, 64, 20, 128, 72]);
+        let mut tlsf = Tlsf::EMPTY;
+        for &offset in &free {
+            tlsf.insert(&mut buffer, offset);
+        }
+
+        assert_eq!(tlsf.find(&buffer, 12, 4), Some(free[0]));
+        assert_eq!(tlsf.find(&buffer, 16, 4), Some(free[2]));
+        assert_eq!(tlsf.find(&buffer, 20, 4), Some(free[2]));
+        assert_eq!(tlsf.find(&buffer, 24, 4), Some(free[1]));
+        assert_eq!(tlsf.find(&buffer, 64, 4), Some(free[1]));
+        // 68 is rounded up to the class of 68..72, so the 72 block is used
+        assert_eq!(tlsf.find(&buffer, 68, 4), Some(free[4]));
+        assert_eq!(tlsf.find(&buffer, 76, 4), Some(free[3]));
+        assert_eq!(tlsf.find(&buffer, 128, 4), Some(free[3]));
+        assert_eq!(tlsf.find(&buffer, 132, 4), None);
+    }
+
+    #[test]
+    fn remove_updates_lists_and_bitmaps() {
+        let (mut buffer, free) = buffer_with_free_entries::<512>(&[64, 64, 64, 12]);
+        let mut tlsf = Tlsf::EMPTY;
+        for &offset in &free {
+            tlsf.insert(&mut buffer, offset);
+        }
+
+        // remove from the middle, the front and the end of the list
+        assert_eq!(tlsf.find(&buffer, 64, 4), Some(free[2]));
+        tlsf.remove(&mut buffer, free[1]);
+        assert_eq!(tlsf.find(&buffer, 64, 4), Some(free[2]));
+        tlsf.remove(&mut buffer, free[2]);
+        assert_eq!(tlsf.find(&buffer, 64, 4), Some(free[0]));
+        tlsf.remove(&mut buffer, free[0]);
+        assert_eq!(tlsf.find(&buffer, 64, 4), None);
+
+        // the bitmaps must be cleared, but the other class is unaffected
+        assert_eq!(tlsf.first_level, 0b1);
+        assert_eq!(tlsf.second_level[1], 0);
+        assert_eq!(tlsf.find(&buffer, 4, 4), Some(free[3]));
+        tlsf.remove(&mut buffer, free[3]);
+        assert_eq!(tlsf.first_level, 0);
+        assert_eq!(tlsf.find(&buffer, 4, 4), None);
+    }
+
+    #[test]
+    fn too_small_entries_are_ignored() {
+        let (mut buffer, free) = buffer_with_free_entries::<64>(&[8]);
+        let mut tlsf = Tlsf::EMPTY;
+        tlsf.insert(&mut buffer, free[0]);
+        assert_eq!(tlsf.find(&buffer, 4, 4), None);
+        tlsf.remove(&mut buffer, free[0]);
+        assert_eq!(tlsf.first_level, 0);
+    }
+}
@@ -0,0 +1,220 @@
+//! Module providing the reclaiming of memory, if an allocation fails.
+//!
+//! Many programs keep memory, that is not strictly needed, e.g. caches of
+//! decoded data. Instead of failing an allocation, if the heap is exhausted,
+//! the allocator calls the registered [`ReclaimHook`]s, which can drop such
+//! memory, and retries the allocation.
+use crate::AbortOnUnwind;
+
+use core::alloc::Layout;
+
+/// A function called to reclaim memory, if an allocation fails.
+///
+/// The function receives the layout of the failed allocation and should free
+/// memory (e.g. by dropping a cache). It returns, whether any memory was
+/// freed, since the allocation is only retried in that case. The function may
+/// use the allocator, but allocations from within the function never reclaim
+/// memory themselves. If the function panics, the program is aborted, since
+/// the allocator must not unwind.
+pub type ReclaimHook = fn(Layout) -> bool;
+
+/// The number of reclaim hooks, that can be registered per allocator.
+pub const MAX_RECLAIM_HOOKS: usize = 8;
+
+/// A registered reclaim hook.
+#[derive(Clone, Copy)]
+struct Reclaimer {
+    /// The priority of the hook, hooks with a higher priority are called
+    /// first.
+    priority: u8,
+    /// The hook itself or `None` for an unused entry.
+    hook: Option<ReclaimHook>,
+}
+
+/// The registry of the reclaim hooks of an allocator.
+///
+/// The registry consists of zeroes only initially, so that a freshly created
+/// allocator is placed in the `.bss`-section.
+pub struct Reclaimers {
+    /// The registered hooks sorted by descending priority followed by the
+    /// unused entries.
+    hooks: [Reclaimer; MAX_RECLAIM_HOOKS],
+    /// Whether the hooks are currently called.
+    ///
+    /// This prevents the recursive reclaiming of memory, if an allocation
+    /// from within a hook fails.
+    active: bool,
+}
+impl Reclaimers {
+    /// The registry of a new allocator without any hooks.
+    pub const NEW: Self = Self {
+        hooks: [Reclaimer {
+            priority: 0,
+            hook: None,
+        }; MAX_RECLAIM_HOOKS],
+        active: false,
+    };
+
+    /// Register the given hook with the given priority.
+    ///
+    /// The hook is called after the already registered hooks with the same
+    /// or a higher priority. If all entries are in use, `false` is returned.
+    pub fn register(&mut self, priority: u8, hook: ReclaimHook) -> bool {
+        let count = self
+            .hooks
+            .iter()
+            .filter(|reclaimer| reclaimer.hook.is_some())
+            .count();
+        if count == MAX_RECLAIM_HOOKS {
+            return false;
+        }
+
+        let index = self.hooks[..count]
+            .iter()
+            .take_while(|reclaimer| reclaimer.priority >= priority)
+            .count();
+        self.hooks[index..=count].rotate_right(1);
+        self.hooks[index] = Reclaimer {
+            priority,
+            hook: Some(hook),
+        };
+        true
+    }
+
+    /// Query the hook at the given position in the calling order.
+    fn hook(&self, index: usize) -> Option<ReclaimHook> {
+        self.hooks.get(index)?.hook
+    }
+}
+
+/// Perform an allocation and reclaim memory, if it fails.
+///
+/// If the allocation fails, the registered hooks are called in the order of
+/// their priority and the allocation is retried after each hook, that freed
+/// memory, until it succeeds. The lock of the registry is not held while
+/// calling the hooks, so they can use the allocator. Failed allocations from
+/// within a hook (or from other threads during the reclaiming) do not reclaim
+/// memory and fail immediately.
+///
+/// The failure of the last attempt is returned, so that it can carry state of
+/// the failed attempt (e.g. the lock of the allocator, to examine the reason of
+/// the failure). It is dropped before calling the next hook and after all the
+/// hooks were called, the allocation is attempted a last time, so that the
+/// returned failure reflects the memory after reclaiming.
+pub fn with_reclaim<T, F>(
+    reclaimers: &spin::Mutex<Reclaimers>,
+    layout: Layout,
+    mut alloc: impl FnMut() -> Result<T, F>,
+) -> Result<T, F> {
+    let failure = match alloc() {
+        Ok(allocation) => return Ok(allocation),
+        Err(failure) => failure,
+    };
+
+    {
+        let mut reclaimers = reclaimers.lock();
+        if reclaimers.active {
+            return Err(failure);
+        }
+        reclaimers.active = true;
+    }
+    drop(failure);
+
+    let mut index = 0;
+    let result = loop {
+        // the registry is only locked to query the next hook
+        let hook = reclaimers.lock().hook(index);
+        let hook = match hook {
+            Some(hook) => hook,
+            None => break alloc(),
+        };
+        let abort = AbortOnUnwind;
+        let reclaimed = hook(layout);
+        abort.disarm();
+        if reclaimed {
+            if let Ok(allocation) = alloc() {
+                break Ok(allocation);
+            }
+        }
+        index += 1;
+    };
+
+    reclaimers.lock().active = false;
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{with_reclaim, Reclaimers, MAX_RECLAIM_HOOKS};
+    use core::alloc::Layout;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    #[test]
+    fn hooks_are_sorted_by_priority() {
+        let mut reclaimers = Reclaimers::NEW;
+        assert!(reclaimers.register(1, |_| true));
+        assert!(reclaimers.register(3, |_| true));
+        assert!(reclaimers.register(1, |_| false));
+        assert!(reclaimers.register(2, |_| true));
+
+        let priorities: Vec<_> = reclaimers.hooks.iter().map(|hook| hook.priority).collect();
+        assert_eq!(priorities, [3, 2, 1, 1, 0, 0, 0, 0]);
+        // the later one of two hooks with the same priority is called later
+        let layout = Layout::new::<u8>();
+        assert!(reclaimers.hook(2).unwrap()(layout));
+        assert!(!reclaimers.hook(3).unwrap()(layout));
+        assert!(reclaimers.hook(4).is_none());
+    }
+
+    #[test]
+    fn limited_number_of_hooks() {
+        let mut reclaimers = Reclaimers::NEW;
+        for _ in 0..MAX_RECLAIM_HOOKS {
+            assert!(reclaimers.register(0, |_| true));
+        }
+        assert!(!reclaimers.register(0, |_| true));
+    }
+
+    #[test]
+    fn allocation_is_retried_after_each_hook() {
+        static CALLS: AtomicUsize = AtomicUsize::new(0);
+        let reclaimers = spin::Mutex::new(Reclaimers::NEW);
+        reclaimers.lock().register(2, |_| {
+            CALLS.fetch_add(1, Ordering::Relaxed);
+            true
+        });
+        reclaimers.lock().register(1, |_| false);
+        reclaimers.lock().register(0, |_| {
+            CALLS.fetch_add(10, Ordering::Relaxed);
+            true
+        });
+
+        // the allocation succeeds after the second freeing hook
+        let mut attempts = 0;
+        let allocation = with_reclaim(&reclaimers, Layout::new::<u8>(), || {
+            attempts += 1;
+            (attempts == 3).then(|| attempts).ok_or(())
+        });
+        assert_eq!(allocation, Ok(3));
+        assert_eq!(CALLS.load(Ordering::Relaxed), 11);
+        assert!(!reclaimers.lock().active);
+
+        // no hook frees enough memory
+        let allocation = with_reclaim(&reclaimers, Layout::new::<u8>(), || Err::<(), ()>(()));
+        assert_eq!(allocation, Err(()));
+        assert_eq!(CALLS.load(Ordering::Relaxed), 22);
+    }
+
+    #[test]
+    fn no_reclaiming_while_reclaiming() {
+        let reclaimers = spin::Mutex::new(Reclaimers::NEW);
+        reclaimers
+            .lock()
+            .register(0, |_| panic!("no hook may be called"));
+        reclaimers.lock().active = true;
+        assert_eq!(
+            with_reclaim(&reclaimers, Layout::new::<u8>(), || Err::<(), ()>(())),
+            Err(())
+        );
+    }
+}
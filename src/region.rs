@@ -0,0 +1,809 @@
+//! Module providing the [`RegionAllocator`], whose heap memory is provided at
+//! runtime.
+//!
+//! The heap memory of an [`Allocator`](crate::Allocator) is part of the
+//! allocator itself, so its size has to be known at compile time. On many
+//! embedded systems the heap should span the RAM, that is not used otherwise,
+//! e.g. between the end of the static data and the stack. Its bounds are only
+//! known from linker symbols (or are even detected at runtime), so the heap is
+//! handed to the allocator once the program has started.
+//!
+//! Some microcontrollers have several separate RAM blocks (e.g. `SRAM1`,
+//! `SRAM2` and a core-coupled RAM), so the allocator can manage multiple
+//! disjoint regions.
+use crate::free_error::FreeErrors;
+use crate::raw_allocator::{
+    AllocError, BestFit, BlockInfo, CheckError, FreeError, Header, Header32, RawAllocator, Region,
+    Stats, Strategy,
+};
+use crate::reclaim::{self, Reclaimers};
+use crate::{FreeErrorLog, FreePolicy, ReclaimHook};
+
+use core::alloc::Layout;
+use core::mem::MaybeUninit;
+use core::ptr::NonNull;
+
+/// The memory allocator for a heap, that is provided at runtime.
+///
+/// This allocator works exactly like the [`Allocator`](crate::Allocator), but
+/// it does not own its heap memory. Instead, it is created empty (so that it
+/// can be placed in a `static` variable) and initialized with a memory region
+/// via [`init()`](Self::init) or [`init_from_raw_parts()`][raw] early in the
+/// program. All allocations before the initialization fail.
+///
+/// The heap can consist of up to `R` disjoint memory regions (one by default),
+/// which are added by calling `init()` once per region. Each region has its
+/// own blocks, i.e. an allocation never spans two regions. Allocations try
+/// the regions in the order, in which they were added, so the preferred (e.g.
+/// the fastest) memory should be added first. Deallocations are routed to the
+/// region containing the pointer.
+///
+/// The start of the region is rounded up and its size is rounded down to
+/// multiples of the granule of the header format `H`, so the region does not
+/// need to be aligned. Since the size of the region is not known at compile
+/// time, the [`AutoHeader`](crate::AutoHeader) format always uses 32-bit
+/// headers. The [`Header16`](crate::Header16) format is limited to regions of
+/// up to 64KiB, like for the `Allocator`.
+///
+/// The statistics, the heap checks and the red zones are available like for
+/// the `Allocator`, but they cover all regions together, e.g. the failed
+/// allocations and the peak usage are tracked for the whole heap instead of
+/// per region. The [handles](crate::Handle) and the `Allocator`-traits of the
+/// `allocator_api` and `allocator-api2` features are not supported.
+///
+/// # Example
+/// ```no_run
+/// #[global_allocator]
+/// static ALLOCATOR: emballoc::RegionAllocator = emballoc::RegionAllocator::empty();
+///
+/// extern "C" {
+///     // provided by the linker script
+///     static mut __heap_start: u8;
+///     static mut __heap_end: u8;
+/// }
+///
+/// fn main() {
+///     // SAFETY: the memory between the linker symbols is not used otherwise
+///     unsafe {
+///         let start = core::ptr::addr_of_mut!(__heap_start);
+///         let end = core::ptr::addr_of_mut!(__heap_end);
+///         ALLOCATOR.init_from_raw_parts(start, end as usize - start as usize);
+///     }
+///     // from now on, allocations are possible
+/// }
+/// ```
+///
+/// Multiple regions are used like this:
+/// ```no_run
+/// # use core::mem::MaybeUninit;
+/// #[global_allocator]
+/// static ALLOCATOR: emballoc::RegionAllocator<emballoc::BestFit, emballoc::Header32, 2> =
+///     emballoc::RegionAllocator::empty();
+///
+/// #[link_section = ".ccmram"]
+/// static mut FAST: [MaybeUninit<u8>; 4096] = [MaybeUninit::uninit(); 4096];
+/// #[link_section = ".sram2"]
+/// static mut SLOW: [MaybeUninit<u8>; 8192] = [MaybeUninit::uninit(); 8192];
+///
+/// fn main() {
+///     // SAFETY: the arrays are only used by the allocator
+///     unsafe {
+///         ALLOCATOR.init(&mut *core::ptr::addr_of_mut!(FAST));
+///         ALLOCATOR.init(&mut *core::ptr::addr_of_mut!(SLOW));
+///     }
+/// }
+/// ```
+///
+/// [raw]: Self::init_from_raw_parts
+pub struct RegionAllocator<S = BestFit, H: Header = Header32, const R: usize = 1> {
+    /// The internal raw allocators of the regions.
+    ///
+    /// The raw allocators handle allocations of contiguous and aligned byte
+    /// slices without needing to worry about layouts. They are protected by a
+    /// `spin::Mutex` to make them usable with shared references (requirement
+    /// of [`GlobalAlloc`](core::alloc::GlobalAlloc)).
+    raw: spin::Mutex<Regions<S, H, R>>,
+    /// The handling of invalid frees, see [`FreePolicy`].
+    free_errors: spin::Mutex<FreeErrors>,
+    /// The hooks to reclaim memory, if an allocation fails.
+    reclaimers: spin::Mutex<Reclaimers>,
+}
+impl<S: Strategy, H: Header, const R: usize> RegionAllocator<S, H, R> {
+    /// Create a new [`RegionAllocator`] without any heap memory.
+    ///
+    /// The allocator has to be initialized with [`init()`](Self::init) or
+    /// [`init_from_raw_parts()`](Self::init_from_raw_parts) before it can
+    /// serve any allocation. This function is a `const fn`, therefore it can be
+    /// used to create a `static` allocator.
+    #[must_use = "assign the allocator to a static variable and apply the `#[global_allocator]`-attribute to make it the global allocator"]
+    pub const fn empty() -> Self {
+        let raw = spin::Mutex::new(Regions {
+            raws: [RawAllocator::EMPTY; R],
+            peak_used: 0,
+            failures: 0,
+            red_zones: false,
+            checked: 0,
+        });
+        let free_errors = spin::Mutex::new(FreeErrors::NEW);
+        let reclaimers = spin::Mutex::new(Reclaimers::NEW);
+        Self {
+            raw,
+            free_errors,
+            reclaimers,
+        }
+    }
+
+    /// Create a new [`RegionAllocator`], whose heap is the given memory.
+    ///
+    /// Further regions can be added via [`init()`](Self::init), if `R` is
+    /// larger than one.
+    ///
+    /// # Panics
+    /// This function panics, if the memory is too small for a single block
+    /// (two headers, i.e. `8` bytes by default, after the rounding to the
+    /// granule) or if it is too large for the header format.
+    /// ```should_panic
+    /// # use core::mem::MaybeUninit;
+    /// let memory = Box::leak(Box::new([MaybeUninit::uninit(); 4]));
+    /// emballoc::RegionAllocator::<emballoc::BestFit>::new(memory); // too small
+    /// ```
+    #[must_use]
+    pub fn new(memory: &'static mut [MaybeUninit<u8>]) -> Self {
+        let allocator = Self::empty();
+        allocator.init(memory);
+        allocator
+    }
+
+    /// Add the given memory as a region of the heap.
+    ///
+    /// # Panics
+    /// This function panics, if all `R` regions are initialized already, if the
+    /// memory is too small for a single block (two headers, i.e. `8` bytes by
+    /// default, after the rounding to the granule) or if it is too large for
+    /// the header format.
+    pub fn init(&self, memory: &'static mut [MaybeUninit<u8>]) {
+        self.init_region(Region::from(memory));
+    }
+
+    /// Add the `size` bytes starting at `start` as a region of the heap.
+    ///
+    /// # Safety
+    /// The memory has to be valid for reads and writes of `size` bytes and
+    /// must not be accessed by anything else than the allocator for the rest
+    /// of the program. It does not need to be initialized.
+    ///
+    /// # Panics
+    /// This function panics under the same conditions as [`init()`](Self::init)
+    /// and if `start` is a null pointer.
+    pub unsafe fn init_from_raw_parts(&self, start: *mut u8, size: usize) {
+        let start = NonNull::new(start.cast()).expect("the heap memory starts at a null pointer");
+        // SAFETY: the caller guarantees, that the memory is valid and borrowed
+        // exclusively by the allocator.
+        self.init_region(unsafe { Region::new(start, size) });
+    }
+
+    /// Extend the heap by the given memory.
+    ///
+    /// This can be used to hand memory to the heap, that becomes available
+    /// later on (e.g. a buffer of a bootloader). If the memory directly follows
+    /// one of the regions of the heap, that region is enlarged: the memory is
+    /// appended as a free block, or the last block of the region grows in
+    /// place, if it is free. Otherwise the memory is added as a new region like
+    /// with [`init()`](Self::init).
+    ///
+    /// # Panics
+    /// This function panics, if the memory has to be added as a new region
+    /// and the conditions of [`init()`](Self::init) are not met or if the
+    /// enlarged region is too large for the header format.
+    pub fn extend(&self, memory: &'static mut [MaybeUninit<u8>]) {
+        self.raw.lock().extend(Region::from(memory));
+    }
+
+    /// Extend the heap by the `size` bytes starting at `start`.
+    ///
+    /// See [`extend()`](Self::extend) for details.
+    ///
+    /// # Safety
+    /// The memory has to be valid for reads and writes of `size` bytes and
+    /// must not be accessed by anything else than the allocator for the rest
+    /// of the program. It does not need to be initialized.
+    ///
+    /// # Panics
+    /// This function panics under the same conditions as
+    /// [`extend()`](Self::extend) and if `start` is a null pointer.
+    pub unsafe fn extend_from_raw_parts(&self, start: *mut u8, size: usize) {
+        let start = NonNull::new(start.cast()).expect("the heap memory starts at a null pointer");
+        // SAFETY: the caller guarantees, that the memory is valid and borrowed
+        // exclusively by the allocator.
+        self.raw.lock().extend(unsafe { Region::new(start, size) });
+    }
+
+    /// Allocate memory for the given layout and report the reason of a
+    /// failure.
+    ///
+    /// See [`Allocator::try_alloc()`](crate::Allocator::try_alloc) for details.
+    /// The reason of a failure is the one of the region with the largest free
+    /// block, while the free bytes are the total of all regions.
+    ///
+    /// # Errors
+    /// The reason of the failure is returned, if the allocation fails.
+    pub fn try_alloc(&self, layout: Layout) -> Result<NonNull<[u8]>, AllocError> {
+        let memory = reclaim::with_reclaim(&self.reclaimers, layout, || {
+            let mut raw = self.raw.lock();
+            match raw.alloc(layout.size(), layout.align()) {
+                Some(memory) => {
+                    NonNull::new(memory as *mut [MaybeUninit<u8>] as *mut [u8]).ok_or(raw)
+                }
+                None => Err(raw),
+            }
+        });
+        memory.map_err(|mut raw| {
+            raw.record_failure();
+            raw.alloc_error(layout.size(), layout.align())
+        })
+    }
+
+    /// Query the usage statistics of the heap.
+    ///
+    /// See [`Allocator::stats()`](crate::Allocator::stats) for details. The
+    /// sizes and numbers are summed up over all regions, the largest free
+    /// block is the largest one of all regions and the peak usage refers to
+    /// the used bytes of all regions together.
+    pub fn stats(&self) -> Stats {
+        self.raw.lock().stats()
+    }
+
+    /// Walk over all blocks of the heap.
+    ///
+    /// See [`Allocator::walk()`](crate::Allocator::walk) for details. The
+    /// regions are walked in the order, in which they were added, so the
+    /// addresses only ascend within each region.
+    pub fn walk(&self, f: impl FnMut(BlockInfo)) {
+        self.raw.lock().walk(f);
+    }
+
+    /// Check the consistency of all regions of the heap.
+    ///
+    /// See [`Allocator::check()`](crate::Allocator::check) for details.
+    ///
+    /// # Errors
+    /// The first inconsistency of the heap is returned, see [`CheckError`].
+    pub fn check(&self) -> Result<(), CheckError> {
+        self.raw.lock().check()
+    }
+
+    /// Check the consistency of the next `count` blocks of the heap.
+    ///
+    /// See [`Allocator::check_step()`](crate::Allocator::check_step) for
+    /// details. The regions are checked one after the other and a single call
+    /// checks the blocks of a single region only (so it might check less than
+    /// `count` blocks). `Ok(true)` is returned, once the last block of the last
+    /// region was checked.
+    ///
+    /// # Errors
+    /// The first inconsistency of the checked blocks is returned, see
+    /// [`CheckError`]. The next call starts over at the first block of the
+    /// first region.
+    pub fn check_step(&self, count: usize) -> Result<bool, CheckError> {
+        self.raw.lock().check_step(count)
+    }
+
+    /// Surround all future allocations with red zones to detect buffer
+    /// overflows.
+    ///
+    /// See [`Allocator::enable_red_zones()`](crate::Allocator::enable_red_zones)
+    /// for details. The red zones are enabled in all regions, including the
+    /// ones added later, so they can only be enabled, as long as none of the
+    /// regions has an allocation. Whether the red zones are enabled is
+    /// returned.
+    pub fn enable_red_zones(&self) -> bool {
+        self.raw.lock().enable_red_zones()
+    }
+
+    /// Verify the red zones of the allocation containing `ptr`.
+    ///
+    /// See [`Allocator::check_red_zone()`](crate::Allocator::check_red_zone)
+    /// for details.
+    ///
+    /// # Errors
+    /// If the guard bytes around the allocation were overwritten,
+    /// [`CheckError::RedZoneViolation`] is returned with the address and size
+    /// of the allocation.
+    pub fn check_red_zone(&self, ptr: *const u8) -> Result<(), CheckError> {
+        self.raw.lock().check_red_zone(ptr)
+    }
+
+    /// Select the reaction to invalid frees.
+    ///
+    /// See [`Allocator::set_free_policy()`](crate::Allocator::set_free_policy)
+    /// for details.
+    pub fn set_free_policy(&self, policy: FreePolicy) {
+        self.free_errors.lock().set_policy(policy);
+    }
+
+    /// Query the log of the invalid frees recorded by the
+    /// [`FreePolicy::Record`] policy.
+    pub fn free_error_log(&self) -> FreeErrorLog {
+        self.free_errors.lock().log()
+    }
+
+    /// Register a hook to reclaim memory, if an allocation fails.
+    ///
+    /// See [`Allocator::register_reclaim()`](crate::Allocator::register_reclaim)
+    /// for details.
+    #[must_use = "the hook is not registered, if too many hooks are registered already"]
+    pub fn register_reclaim(&self, priority: u8, hook: ReclaimHook) -> bool {
+        self.reclaimers.lock().register(priority, hook)
+    }
+
+    /// Add the given region to the heap.
+    fn init_region(&self, region: Region) {
+        self.raw.lock().init(region);
+    }
+}
+crate::impl_global_alloc!([S: Strategy, H: Header, const R: usize] RegionAllocator<S, H, R>);
+
+/// The raw allocators of the regions of a [`RegionAllocator`].
+///
+/// This dispatches the requests of the
+/// [`GlobalAlloc`](core::alloc::GlobalAlloc)-implementation to the raw
+/// allocators: allocations are tried in each region in order, all other
+/// requests are forwarded to the region containing the pointer. The values of
+/// the statistics, that refer to the whole heap, are tracked here, since a
+/// single request might involve several regions.
+struct Regions<S, H: Header, const R: usize> {
+    /// The raw allocators of the regions followed by the empty ones.
+    raws: [RawAllocator<Region, S, H>; R],
+    /// The largest number of used bytes in all regions together so far.
+    ///
+    /// The used bytes only grow by allocations and resizes, so the peak is
+    /// updated, before they might shrink (and when it is queried).
+    peak_used: usize,
+    /// The number of failed allocations.
+    failures: usize,
+    /// Whether the red zones are enabled, so that they are enabled in the
+    /// regions added later as well.
+    red_zones: bool,
+    /// The index of the region, that is checked by the next check step.
+    checked: usize,
+}
+impl<S: Strategy, H: Header, const R: usize> Regions<S, H, R> {
+    /// Replace the first empty raw allocator with one managing the given
+    /// region.
+    fn init(&mut self, region: Region) {
+        let raw = self.raws.iter_mut().find(|raw| raw.is_empty());
+        let raw = raw.expect("all regions of the allocator are initialized already");
+        *raw = RawAllocator::with_region(region);
+        if self.red_zones {
+            // the new region has no allocations, so this always succeeds
+            raw.enable_red_zones();
+        }
+    }
+
+    /// Append the given region to the region, that it directly follows, or
+    /// add it as a new region.
+    fn extend(&mut self, mut region: Region) {
+        for raw in &mut self.raws {
+            match raw.extend(region) {
+                Ok(()) => return,
+                Err(unused) => region = unused,
+            }
+        }
+        self.init(region);
+    }
+
+    /// Allocate a memory block in the first region, that has enough memory.
+    fn alloc(&mut self, n: usize, align: usize) -> Option<&mut [MaybeUninit<u8>]> {
+        self.raws.iter_mut().find_map(|raw| raw.alloc(n, align))
+    }
+
+    /// Allocate a zeroed memory block in the first region, that has enough
+    /// memory.
+    fn alloc_zeroed(&mut self, n: usize, align: usize) -> Option<&mut [MaybeUninit<u8>]> {
+        self.raws
+            .iter_mut()
+            .find_map(|raw| raw.alloc_zeroed(n, align))
+    }
+
+    /// Determine, why an allocation of `n` bytes with the alignment `align`
+    /// failed in all regions.
+    ///
+    /// The reason is the one of the region with the largest free block (the
+    /// first one, if several are equally large), but the free bytes are the
+    /// total of all regions.
+    fn alloc_error(&mut self, n: usize, align: usize) -> AllocError {
+        let mut free = 0;
+        let mut error = AllocError::OutOfMemory {
+            free: 0,
+            largest_free: 0,
+        };
+        for raw in self.initialized() {
+            let region_error = raw.alloc_error(n, align);
+            free += region_error.free();
+            if region_error.largest_free() > error.largest_free() {
+                error = region_error;
+            }
+        }
+        match error {
+            AllocError::OutOfMemory { largest_free, .. } => {
+                AllocError::OutOfMemory { free, largest_free }
+            }
+            AllocError::Fragmented { largest_free, .. } => {
+                AllocError::Fragmented { free, largest_free }
+            }
+            AllocError::Alignment { largest_free, .. } => {
+                AllocError::Alignment { free, largest_free }
+            }
+        }
+    }
+
+    /// Record a failed allocation.
+    ///
+    /// The allocation failed in all regions, so it is recorded once for the
+    /// whole heap.
+    fn record_failure(&mut self) {
+        self.failures = self.failures.saturating_add(1);
+    }
+
+    /// Free the memory block containing `ptr` in its region.
+    fn free(&mut self, ptr: *mut u8) -> Result<(), FreeError> {
+        self.track_peak();
+        self.owner(ptr)
+            .ok_or(FreeError::AllocationNotFound)?
+            .free(ptr)
+    }
+
+    /// Resize the memory block containing `ptr` in place in its region.
+    fn resize(&mut self, ptr: *mut u8, n: usize) -> bool {
+        self.track_peak();
+        self.owner(ptr).map_or(false, |raw| raw.resize(ptr, n))
+    }
+
+    /// Query the usage statistics of all regions together.
+    fn stats(&mut self) -> Stats {
+        self.track_peak();
+        let mut total = Stats {
+            used: 0,
+            free: 0,
+            overhead: 0,
+            largest_free: 0,
+            allocations: 0,
+            free_blocks: 0,
+            peak_used: self.peak_used,
+            total_allocations: 0,
+            failed_allocations: self.failures,
+        };
+        for raw in self.initialized() {
+            let stats = raw.stats();
+            total.used += stats.used;
+            total.free += stats.free;
+            total.overhead += stats.overhead;
+            total.largest_free = total.largest_free.max(stats.largest_free);
+            total.allocations += stats.allocations;
+            total.free_blocks += stats.free_blocks;
+            total.total_allocations += stats.total_allocations;
+        }
+        total
+    }
+
+    /// Call the given function with the information about each block of all
+    /// regions.
+    fn walk(&mut self, mut f: impl FnMut(BlockInfo)) {
+        for raw in self.initialized() {
+            raw.walk(&mut f);
+        }
+    }
+
+    /// Check the consistency of all regions.
+    fn check(&mut self) -> Result<(), CheckError> {
+        self.initialized().try_for_each(RawAllocator::check)
+    }
+
+    /// Check the consistency of the next `count` blocks of the region, that
+    /// is checked currently.
+    ///
+    /// Whether the last block of the last region was checked by this call is
+    /// returned.
+    fn check_step(&mut self, count: usize) -> Result<bool, CheckError> {
+        let result = match self.raws.get_mut(self.checked) {
+            Some(raw) if !raw.is_empty() => raw.check_step(count),
+            // there is no region, so there is nothing to check
+            _ => Ok(true),
+        };
+        match result {
+            Ok(true) => {
+                self.checked += 1;
+                let done = self
+                    .raws
+                    .get(self.checked)
+                    .map_or(true, RawAllocator::is_empty);
+                if done {
+                    self.checked = 0;
+                }
+                Ok(done)
+            }
+            Ok(false) => Ok(false),
+            Err(error) => {
+                self.checked = 0;
+                Err(error)
+            }
+        }
+    }
+
+    /// Enable the red zones in all regions, if none of them has allocations.
+    fn enable_red_zones(&mut self) -> bool {
+        if !self.red_zones {
+            let unused = self.initialized().all(|raw| raw.stats().allocations == 0);
+            self.red_zones = unused;
+        }
+        if self.red_zones {
+            for raw in self.initialized() {
+                raw.enable_red_zones();
+            }
+        }
+        self.red_zones
+    }
+
+    /// Check the red zones of the allocation containing `ptr` in its region.
+    fn check_red_zone(&mut self, ptr: *const u8) -> Result<(), CheckError> {
+        self.owner(ptr)
+            .map_or(Ok(()), |raw| raw.check_red_zone(ptr))
+    }
+
+    /// Update the peak usage with the used bytes of all regions together.
+    fn track_peak(&mut self) {
+        let used = self.raws.iter().map(RawAllocator::used).sum();
+        self.peak_used = self.peak_used.max(used);
+    }
+
+    /// Iterate over the raw allocators of the initialized regions.
+    fn initialized(&mut self) -> impl Iterator<Item = &mut RawAllocator<Region, S, H>> + '_ {
+        self.raws.iter_mut().filter(|raw| !raw.is_empty())
+    }
+
+    /// Find the raw allocator of the region containing `ptr`.
+    fn owner(&mut self, ptr: *const u8) -> Option<&mut RawAllocator<Region, S, H>> {
+        self.raws.iter_mut().find(|raw| raw.owns(ptr))
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::undocumented_unsafe_blocks)] // the tests use the `unsafe` allocator API
+mod tests {
+    use super::RegionAllocator;
+    use crate::raw_allocator::{AllocError, CheckError, FreeError};
+    use crate::{BestFit, FreePolicy, Header16, Header32, Tlsf};
+    use core::alloc::{GlobalAlloc, Layout};
+    use core::mem::MaybeUninit;
+
+    /// Obtain a static memory region of the given size.
+    fn memory(size: usize) -> &'static mut [MaybeUninit<u8>] {
+        Box::leak(vec![MaybeUninit::uninit(); size].into_boxed_slice())
+    }
+
+    #[test]
+    fn empty_allocator_fails() {
+        let allocator = RegionAllocator::<BestFit>::empty();
+        let ptr = unsafe { allocator.alloc(Layout::new::<u32>()) };
+        assert!(ptr.is_null());
+    }
+
+    #[test]
+    fn allocations_after_initialization() {
+        let allocator = RegionAllocator::<BestFit>::empty();
+        allocator.init(memory(64));
+
+        let layout = Layout::from_size_align(56, 4).unwrap();
+        let ptr = unsafe { allocator.alloc_zeroed(layout) };
+        assert!(!ptr.is_null());
+        assert!(unsafe { core::slice::from_raw_parts(ptr, 56) }
+            .iter()
+            .all(|byte| *byte == 0));
+        assert!(unsafe { allocator.alloc(Layout::new::<u32>()) }.is_null());
+
+        unsafe { allocator.dealloc(ptr, layout) };
+        assert!(!unsafe { allocator.alloc(layout) }.is_null());
+    }
+
+    #[test]
+    fn unaligned_region() {
+        let memory = memory(67);
+        let start = memory.as_mut_ptr().cast::<u8>();
+        let allocator = RegionAllocator::<Tlsf, Header32<8>>::empty();
+        unsafe { allocator.init_from_raw_parts(start.add(1), 66) };
+
+        let ptr = unsafe { allocator.alloc(Layout::from_size_align(8, 8).unwrap()) };
+        assert!(!ptr.is_null());
+        assert_eq!(ptr as usize % 8, 0);
+        assert!(ptr > start && ptr < start.wrapping_add(67));
+    }
+
+    #[test]
+    fn region_allocator_on_the_stack() {
+        let allocator = RegionAllocator::<BestFit>::new(memory(128));
+        let ptr = unsafe { allocator.alloc(Layout::new::<[u32; 4]>()) };
+        assert!(!ptr.is_null());
+        let ptr = unsafe { allocator.realloc(ptr, Layout::new::<[u32; 4]>(), 64) };
+        assert!(!ptr.is_null());
+    }
+
+    #[test]
+    fn invalid_free_outside_of_the_regions() {
+        let allocator = RegionAllocator::<BestFit>::new(memory(64));
+        allocator.set_free_policy(FreePolicy::Record);
+
+        let mut outside = 0_u32;
+        let outside = core::ptr::addr_of_mut!(outside).cast::<u8>();
+        unsafe { allocator.dealloc(outside, Layout::new::<u32>()) };
+        let log = allocator.free_error_log();
+        assert_eq!(log.count(), 1);
+        assert_eq!(log.last().unwrap().address, outside as usize);
+        assert_eq!(log.last().unwrap().error, FreeError::AllocationNotFound);
+    }
+
+    #[test]
+    #[should_panic(expected = "all regions of the allocator are initialized already")]
+    fn double_initialization() {
+        let allocator = RegionAllocator::<BestFit>::empty();
+        allocator.init(memory(64));
+        allocator.init(memory(64));
+    }
+
+    #[test]
+    fn multiple_regions() {
+        let allocator = RegionAllocator::<BestFit, Header32, 3>::empty();
+        let first = memory(32);
+        let second = memory(64);
+        let (first_start, second_start) = (first.as_ptr() as usize, second.as_ptr() as usize);
+        allocator.init(first);
+        allocator.init(second);
+
+        // the first region is preferred, the second one is used, once the first
+        // one is exhausted. The third region is unused.
+        let layout = Layout::from_size_align(24, 4).unwrap();
+        let a = unsafe { allocator.alloc(layout) };
+        let b = unsafe { allocator.alloc(layout) };
+        let c = unsafe { allocator.alloc(layout) };
+        assert!(!a.is_null() && !b.is_null() && !c.is_null());
+        assert!((first_start..first_start + 32).contains(&(a as usize)));
+        assert!((second_start..second_start + 64).contains(&(b as usize)));
+        assert!((second_start..second_start + 64).contains(&(c as usize)));
+        assert!(unsafe { allocator.alloc(layout) }.is_null());
+
+        // deallocations are routed to the owning region, so the memory can be
+        // reused afterwards.
+        unsafe { allocator.dealloc(b, layout) };
+        unsafe { allocator.dealloc(a, layout) };
+        assert_eq!(unsafe { allocator.alloc(layout) }, a);
+        assert_eq!(unsafe { allocator.alloc(layout) }, b);
+        let mut regions = allocator.raw.lock();
+        assert_eq!(
+            regions.free(core::ptr::null_mut()),
+            Err(FreeError::AllocationNotFound)
+        );
+    }
+
+    #[test]
+    fn resize_in_the_owning_region() {
+        let allocator = RegionAllocator::<BestFit, Header32, 2>::empty();
+        allocator.init(memory(16));
+        allocator.init(memory(64));
+
+        let layout = Layout::from_size_align(16, 4).unwrap();
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert_eq!(unsafe { allocator.realloc(ptr, layout, 48) }, ptr);
+        assert_eq!(unsafe { allocator.realloc(ptr, layout, 8) }, ptr);
+    }
+
+    #[test]
+    fn extend_a_region() {
+        let (first, second) = memory(104).split_at_mut(36);
+        let (second, third) = second.split_at_mut(32);
+        let allocator = RegionAllocator::<BestFit>::new(first);
+        let layout = Layout::from_size_align(60, 4).unwrap();
+        assert!(unsafe { allocator.alloc(layout) }.is_null());
+
+        // the contiguous memory enlarges the free block of the only region
+        allocator.extend(second);
+        let ptr = unsafe { allocator.alloc(layout) };
+        assert!(!ptr.is_null());
+
+        // the region is full now, so the next extension becomes a free block
+        unsafe { allocator.extend_from_raw_parts(third.as_mut_ptr().cast(), third.len()) };
+        assert!(!unsafe { allocator.alloc(Layout::new::<[u32; 7]>()) }.is_null());
+    }
+
+    #[test]
+    fn extend_with_a_new_region() {
+        let allocator = RegionAllocator::<BestFit, Header32, 2>::empty();
+        allocator.extend(memory(36));
+        allocator.extend(memory(36));
+        let layout = Layout::from_size_align(28, 4).unwrap();
+        assert!(!unsafe { allocator.alloc(layout) }.is_null());
+        assert!(!unsafe { allocator.alloc(layout) }.is_null());
+    }
+
+    #[test]
+    fn statistics_of_all_regions() {
+        let allocator = RegionAllocator::<BestFit, Header32, 3>::empty();
+        allocator.init(memory(36));
+        allocator.init(memory(36));
+        let layout = Layout::from_size_align(28, 4).unwrap();
+        let a = unsafe { allocator.alloc(layout) };
+        let b = unsafe { allocator.alloc(layout) };
+        assert!(!a.is_null() && !b.is_null());
+        assert!(unsafe { allocator.alloc(layout) }.is_null());
+        unsafe { allocator.dealloc(a, layout) };
+
+        let stats = allocator.stats();
+        assert_eq!((stats.used, stats.free, stats.largest_free), (28, 28, 28));
+        assert_eq!((stats.allocations, stats.free_blocks), (1, 1));
+        assert_eq!(stats.used + stats.free + stats.overhead, 72);
+        // the peak refers to both regions together and the failure is counted
+        // once, although it failed in each region
+        assert_eq!((stats.peak_used, stats.total_allocations), (56, 2));
+        assert_eq!(stats.failed_allocations, 1);
+
+        let mut blocks = [None; 2];
+        let mut count = 0;
+        allocator.walk(|block| {
+            blocks[count] = Some(block.address);
+            count += 1;
+        });
+        assert_eq!(blocks, [Some(a as usize), Some(b as usize)]);
+    }
+
+    #[test]
+    fn alloc_error_of_all_regions() {
+        let allocator = RegionAllocator::<BestFit, Header32, 2>::empty();
+        allocator.init(memory(36));
+        allocator.init(memory(68));
+
+        let error = AllocError::OutOfMemory {
+            free: 28 + 60,
+            largest_free: 60,
+        };
+        assert_eq!(allocator.try_alloc(Layout::new::<[u8; 64]>()), Err(error));
+        assert_eq!(allocator.stats().failed_allocations, 1);
+        let memory = allocator.try_alloc(Layout::new::<[u8; 60]>()).unwrap();
+        assert_eq!(memory.len(), 60);
+    }
+
+    #[test]
+    fn red_zones_and_checks_of_all_regions() {
+        let allocator = RegionAllocator::<BestFit, Header32, 2>::empty();
+        allocator.init(memory(136));
+        assert!(allocator.enable_red_zones());
+        // the red zones are enabled in regions added later as well
+        allocator.init(memory(136));
+
+        let a = unsafe { allocator.alloc(Layout::new::<[u8; 64]>()) };
+        let b = unsafe { allocator.alloc(Layout::new::<u32>()) };
+        assert!(!a.is_null() && !b.is_null());
+        assert_eq!(allocator.check_step(100), Ok(false));
+        assert_eq!(allocator.check_step(100), Ok(true));
+        assert_eq!(allocator.check(), Ok(()));
+
+        unsafe { b.add(4).write(0) }; // a buffer overflow in the second region
+        let error = CheckError::RedZoneViolation {
+            address: b as usize,
+            size: 4,
+        };
+        assert_eq!(allocator.check_red_zone(a), Ok(()));
+        assert_eq!(allocator.check_red_zone(b), Err(error));
+        assert_eq!(allocator.check(), Err(error));
+        assert_eq!(allocator.check_step(100), Ok(false));
+        assert_eq!(allocator.check_step(100), Err(error));
+        assert!(allocator.enable_red_zones());
+
+        let allocator = RegionAllocator::<BestFit>::new(memory(64));
+        assert!(!unsafe { allocator.alloc(Layout::new::<u32>()) }.is_null());
+        assert!(!allocator.enable_red_zones());
+    }
+
+    #[test]
+    #[should_panic(expected = "too large for the header format")]
+    fn too_large_region_for_compact_headers() {
+        let _allocator = RegionAllocator::<BestFit, Header16>::new(memory(64 * 1024 + 4));
+    }
+}
@@ -0,0 +1,666 @@
+//! Module providing the [`Slab`] front-end for small allocations.
+//!
+//! Small objects are served from pages, that are allocated from the inner
+//! allocator. Each page is dedicated to a single size class and is split into
+//! equally sized objects. The free objects of a page are tracked in a bitmap,
+//! which is stored next to the page address outside of the heap memory.
+//! Therefore the objects don't need a header.
+use crate::free_error::{self, FreeErrors};
+use crate::raw_allocator::FreeError;
+use crate::{FreeErrorLog, FreePolicy};
+
+use core::alloc::{GlobalAlloc, Layout};
+use core::ptr::{self, NonNull};
+
+/// The object sizes of the size classes.
+///
+/// Those are powers of two, so that an object of a class is aligned to its
+/// size (since the pages are aligned to the page size).
+const CLASSES: [usize; 4] = [8, 16, 32, 64];
+
+/// The size (and alignment) of a single page in bytes.
+///
+/// This is chosen, so that the smallest class has exactly 32 objects per page,
+/// i.e. one bit per object in a `u32`.
+const PAGE_SIZE: usize = 256;
+
+/// The layout of the memory of a page, as allocated from the inner allocator.
+// SAFETY: the page size is a power of two and rounding it up to the alignment
+// (i.e. itself) does not overflow.
+const PAGE_LAYOUT: Layout = unsafe { Layout::from_size_align_unchecked(PAGE_SIZE, PAGE_SIZE) };
+
+/// A small-object front-end for another allocator.
+///
+/// Most allocations in a typical program are tiny (e.g. a `Box<Node>` or a
+/// short `String`). With the plain [`Allocator`](crate::Allocator) each of
+/// those costs a 4-byte header and a search for a free block. This type wraps
+/// an allocator and serves requests of up to 64 bytes from pages, that are
+/// dedicated to the size classes 8, 16, 32 and 64 bytes. Those pages are
+/// allocated from the inner allocator, i.e. they are carved from its heap, and
+/// hand out their objects without any per-object header. All other requests
+/// (larger sizes or alignments) are forwarded to the inner allocator.
+///
+/// The second type parameter `P` limits the number of pages (16 by default),
+/// since the bookkeeping data of the pages is stored in this type. If all pages
+/// are in use, small requests are served by the inner allocator as well. A page
+/// is given back to the inner allocator, once it is empty and there is another
+/// page of the same class, so that frequent allocations and deallocations of a
+/// single object don't allocate and free a page each time. The pages are not
+/// locked while a page is allocated from the inner allocator, so its reclaim
+/// hooks (see [`Allocator::register_reclaim()`]) may use this type as well.
+///
+/// A page, that cannot be allocated, counts as a failed allocation in the
+/// statistics of the inner allocator, even if the small request is served by
+/// the inner allocator afterwards.
+///
+/// The objects of a page have no header, so the inner allocator does not know
+/// about them: invalid and double frees of pointers into a page are detected
+/// by this type and handled by its own [`FreePolicy`] (see
+/// [`set_free_policy()`](Self::set_free_policy)), while the invalid frees of
+/// all other pointers are handled by the inner allocator.
+///
+/// The usage of each size class is available via [`usage()`](Self::usage).
+///
+/// [`Allocator::register_reclaim()`]: crate::Allocator::register_reclaim
+///
+/// # Example
+/// ```no_run
+/// #[global_allocator]
+/// static ALLOCATOR: emballoc::Slab<emballoc::Allocator<4096>> =
+///     emballoc::Slab::new(emballoc::Allocator::new());
+/// ```
+pub struct Slab<A, const P: usize = 16> {
+    /// The allocator, that serves the pages and the larger requests.
+    inner: A,
+    /// The bookkeeping data of the pages.
+    pages: spin::Mutex<Pages<P>>,
+    /// The handling of invalid frees of pointers into the pages.
+    free_errors: spin::Mutex<FreeErrors>,
+}
+impl<A, const P: usize> Slab<A, P> {
+    /// Create a new [`Slab`] in front of the given allocator.
+    ///
+    /// This function is a `const fn`, therefore you can call it directly when
+    /// creating the allocator. Without any allocations all pages are unused,
+    /// so this doesn't reserve any memory of the inner allocator.
+    #[must_use = "assign the allocator to a static variable and apply the `#[global_allocator]`-attribute to make it the global allocator"]
+    pub const fn new(inner: A) -> Self {
+        Self {
+            inner,
+            pages: spin::Mutex::new(Pages([Page::UNUSED; P])),
+            free_errors: spin::Mutex::new(FreeErrors::NEW),
+        }
+    }
+
+    /// Access the inner allocator.
+    pub const fn inner(&self) -> &A {
+        &self.inner
+    }
+
+    /// Select the reaction to invalid frees of pointers into the pages.
+    ///
+    /// A pointer into a page, that is not the start of an allocated object, is
+    /// reported as [`FreeError::DoubleFreeDetected`], if it points to the start
+    /// of a free object, and as [`FreeError::AllocationNotFound`] otherwise.
+    /// See [`Allocator::set_free_policy()`](crate::Allocator::set_free_policy)
+    /// for details.
+    pub fn set_free_policy(&self, policy: FreePolicy) {
+        self.free_errors.lock().set_policy(policy);
+    }
+
+    /// Query the log of the invalid frees of pointers into the pages recorded
+    /// by the [`FreePolicy::Record`] policy.
+    pub fn free_error_log(&self) -> FreeErrorLog {
+        self.free_errors.lock().log()
+    }
+
+    /// Query the usage of the size classes.
+    ///
+    /// There is one entry per size class sorted by the object size.
+    pub fn usage(&self) -> [ClassUsage; 4] {
+        let mut usage = CLASSES.map(|object_size| ClassUsage {
+            object_size,
+            pages: 0,
+            objects: 0,
+            capacity: 0,
+        });
+        for page in self.pages.lock().used() {
+            let class = &mut usage[usize::from(page.class)];
+            class.pages += 1;
+            class.objects += page.used.count_ones() as usize;
+            class.capacity += PAGE_SIZE / class.object_size;
+        }
+        usage
+    }
+}
+impl<A: GlobalAlloc, const P: usize> Slab<A, P> {
+    /// Allocate an object of the given size class.
+    ///
+    /// If there is no page of that class with a free object, an unused page
+    /// is reserved and its memory is allocated from the inner allocator. The
+    /// lock is released in the meantime, since the inner allocator might call
+    /// reclaim hooks, that free memory through this allocator. `None` is
+    /// returned, if there is no unused page left or the inner allocator is out
+    /// of memory.
+    fn alloc_object(&self, class: u8) -> Option<NonNull<u8>> {
+        let index = match self.pages.lock().alloc(class) {
+            Claim::Object(object) => return Some(object),
+            Claim::Reserved(index) => index,
+            Claim::Exhausted => return None,
+        };
+        // SAFETY: the page layout has a non-zero size
+        let memory = NonNull::new(unsafe { self.inner.alloc(PAGE_LAYOUT) });
+        self.pages.lock().install(index, class, memory)
+    }
+}
+// SAFETY: small requests are served by distinct objects of the pages, which
+// are allocated from the inner allocator and are suitably aligned due to the
+// page alignment. All other requests are forwarded to the inner allocator.
+unsafe impl<A: GlobalAlloc, const P: usize> GlobalAlloc for Slab<A, P> {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if let Some(class) = class_of(layout) {
+            if let Some(object) = self.alloc_object(class) {
+                return object.as_ptr();
+            }
+        }
+        // SAFETY: the caller upholds the contract of `GlobalAlloc`
+        unsafe { self.inner.alloc(layout) }
+    }
+
+    unsafe fn alloc_zeroed(&self, layout: Layout) -> *mut u8 {
+        if let Some(class) = class_of(layout) {
+            if let Some(object) = self.alloc_object(class) {
+                // SAFETY: the object is at least `layout.size()` bytes large
+                unsafe { object.as_ptr().write_bytes(0, layout.size()) };
+                return object.as_ptr();
+            }
+        }
+        // SAFETY: the caller upholds the contract of `GlobalAlloc`
+        unsafe { self.inner.alloc_zeroed(layout) }
+    }
+
+    unsafe fn dealloc(&self, ptr: *mut u8, layout: Layout) {
+        // the lock is released at the end of the statement, so the inner
+        // allocator is not called while holding it.
+        let release = self.pages.lock().free(ptr);
+        match release {
+            Release::Object => {}
+            Release::Invalid(error) => free_error::handle(&self.free_errors, ptr, layout, error),
+            // SAFETY: the page was allocated from the inner allocator with the
+            // page layout and is not used anymore.
+            Release::Page(memory) => unsafe { self.inner.dealloc(memory.as_ptr(), PAGE_LAYOUT) },
+            // SAFETY: the caller guarantees, that `ptr` was allocated by this
+            // allocator, which means, that it came from the inner allocator.
+            Release::NotInPage => unsafe { self.inner.dealloc(ptr, layout) },
+        }
+    }
+
+    unsafe fn realloc(&self, ptr: *mut u8, layout: Layout, new_size: usize) -> *mut u8 {
+        let object_size = self.pages.lock().object_size(ptr);
+        match object_size {
+            // the object is large enough, so it can stay where it is
+            Some(object_size) if new_size <= object_size => return ptr,
+            Some(_) => {}
+            // SAFETY: the caller guarantees, that `ptr` was allocated by this
+            // allocator, which means, that it came from the inner allocator.
+            None => return unsafe { self.inner.realloc(ptr, layout, new_size) },
+        }
+
+        // SAFETY: the caller guarantees, that `new_size` does not overflow when
+        // rounded up to the alignment, which is a power of two as it comes from
+        // the original layout.
+        let new_layout = unsafe { Layout::from_size_align_unchecked(new_size, layout.align()) };
+        // SAFETY: the caller guarantees, that `new_size` is greater than zero.
+        let new_ptr = unsafe { self.alloc(new_layout) };
+        if !new_ptr.is_null() {
+            // SAFETY: both blocks are valid for the smaller of the two sizes and
+            // the old block is still allocated, so they cannot overlap.
+            unsafe { ptr::copy_nonoverlapping(ptr, new_ptr, layout.size().min(new_size)) };
+            // SAFETY: the caller guarantees, that `ptr` was allocated by this
+            // allocator with the given `layout`.
+            unsafe { self.dealloc(ptr, layout) };
+        }
+        new_ptr
+    }
+}
+
+/// The usage of a single size class of a [`Slab`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClassUsage {
+    /// The size of each object of this class in bytes.
+    pub object_size: usize,
+    /// The number of pages currently dedicated to this class.
+    pub pages: usize,
+    /// The number of allocated objects.
+    pub objects: usize,
+    /// The number of objects fitting into the pages of this class.
+    pub capacity: usize,
+}
+
+/// Determine the size class of an allocation, if it is small enough.
+///
+/// The class is chosen to be at least as large as the alignment, as the objects
+/// are aligned to their size.
+fn class_of(layout: Layout) -> Option<u8> {
+    let size = layout.size().max(layout.align());
+    (0..)
+        .zip(CLASSES)
+        .find(|&(_class, object_size)| size <= object_size)
+        .map(|(class, _object_size)| class)
+}
+
+/// The bookkeeping data of a single page.
+#[derive(Clone, Copy)]
+struct Page {
+    /// The start of the page memory or `None`, if the page is unused.
+    memory: Option<NonNull<u8>>,
+    /// Whether the memory of this (unused) page is currently allocated from
+    /// the inner allocator, so that the page must not be used otherwise.
+    reserved: bool,
+    /// The index of the size class in [`CLASSES`].
+    class: u8,
+    /// The bitmap of the allocated objects: bit `i` is set, if the `i`-th
+    /// object of the page is in use.
+    ///
+    /// Using a bitmap of the used objects (instead of the free ones) allows to
+    /// represent an unused page with zeroes only, so that a freshly created
+    /// allocator is placed in the `.bss`-section.
+    used: u32,
+}
+impl Page {
+    /// A page without any memory.
+    const UNUSED: Self = Self {
+        memory: None,
+        reserved: false,
+        class: 0,
+        used: 0,
+    };
+
+    /// The number of objects in this page.
+    const fn capacity(&self) -> usize {
+        PAGE_SIZE / CLASSES[self.class as usize]
+    }
+
+    /// Check, whether all objects of this page are in use.
+    const fn is_full(&self) -> bool {
+        self.used == u32::MAX >> (32 - self.capacity())
+    }
+
+    /// Allocate the first free object of this page.
+    ///
+    /// The page must not be full. `None` is returned, if the page is unused.
+    fn alloc(&mut self) -> Option<NonNull<u8>> {
+        debug_assert!(!self.is_full());
+        let memory = self.memory?;
+        let object = self.used.trailing_ones() as usize;
+        self.used |= 1 << object;
+        let object_size = CLASSES[usize::from(self.class)];
+        // SAFETY: the object lies inside of the page memory, since the page is
+        // not full. Therefore the offset is in bounds and the result non-null.
+        Some(unsafe { NonNull::new_unchecked(memory.as_ptr().add(object * object_size)) })
+    }
+
+    /// Get the offset of the pointer in the memory of this page.
+    ///
+    /// `None` is returned, if the pointer does not point into this page.
+    fn offset_of(&self, ptr: *mut u8) -> Option<usize> {
+        let memory = self.memory?.as_ptr() as usize;
+        let offset = (ptr as usize).wrapping_sub(memory);
+        (offset < PAGE_SIZE).then(|| offset)
+    }
+}
+
+/// The result of an allocation in the pages.
+enum Claim {
+    /// A free object of a page was allocated.
+    Object(NonNull<u8>),
+    /// All pages of the class are full, so the unused page with the given
+    /// index was reserved. Its memory has to be allocated from the inner
+    /// allocator and installed via [`Pages::install()`].
+    Reserved(usize),
+    /// All pages of the class are full and there is no unused page left.
+    Exhausted,
+}
+
+/// The result of a deallocation in the pages.
+enum Release {
+    /// The pointer does not belong to any page.
+    NotInPage,
+    /// The object was freed.
+    Object,
+    /// The pointer points into a page, but not to an allocated object, so
+    /// nothing was freed.
+    Invalid(FreeError),
+    /// The object was freed and the page is no longer needed, so its memory has
+    /// to be given back to the inner allocator.
+    Page(NonNull<u8>),
+}
+
+/// The bookkeeping data of all pages.
+struct Pages<const P: usize>([Page; P]);
+// SAFETY: the pointers are only used to refer to memory owned by the `Slab`,
+// which is only accessed while holding the lock around this type.
+unsafe impl<const P: usize> Send for Pages<P> {}
+impl<const P: usize> Pages<P> {
+    /// Iterate over the pages, that have some memory.
+    fn used(&self) -> impl Iterator<Item = &Page> {
+        self.0.iter().filter(|page| page.memory.is_some())
+    }
+
+    /// Allocate an object of the given size class.
+    ///
+    /// If there is no page of that class with a free object, an unused page is
+    /// reserved, so that the caller can allocate its memory without holding
+    /// the lock.
+    fn alloc(&mut self, class: u8) -> Claim {
+        let available = self
+            .0
+            .iter_mut()
+            .find(|page| page.memory.is_some() && page.class == class && !page.is_full());
+        if let Some(page) = available {
+            // the page has some memory, so this always allocates an object
+            return page.alloc().map_or(Claim::Exhausted, Claim::Object);
+        }
+
+        let unused = self
+            .0
+            .iter()
+            .position(|page| page.memory.is_none() && !page.reserved);
+        match unused {
+            Some(index) => {
+                self.0[index].reserved = true;
+                Claim::Reserved(index)
+            }
+            None => Claim::Exhausted,
+        }
+    }
+
+    /// Install the memory of the reserved page with the given index.
+    ///
+    /// The page is dedicated to the given size class and its first object is
+    /// allocated. If the memory could not be allocated, the page is unused
+    /// again and `None` is returned.
+    fn install(
+        &mut self,
+        index: usize,
+        class: u8,
+        memory: Option<NonNull<u8>>,
+    ) -> Option<NonNull<u8>> {
+        let page = &mut self.0[index];
+        debug_assert!(page.reserved && page.memory.is_none());
+        *page = Page {
+            memory,
+            class,
+            ..Page::UNUSED
+        };
+        page.alloc()
+    }
+
+    /// Free the object at the given pointer.
+    ///
+    /// Pointers into a page, that are not the start of an allocated object,
+    /// are rejected with the detected error (e.g. on a double free). They are
+    /// not passed to the inner allocator, which only knows the page as a whole.
+    fn free(&mut self, ptr: *mut u8) -> Release {
+        let found = self
+            .0
+            .iter()
+            .enumerate()
+            .find_map(|(index, page)| Some((index, page.offset_of(ptr)?)));
+        let (index, offset) = match found {
+            Some(found) => found,
+            None => return Release::NotInPage,
+        };
+
+        let page = &mut self.0[index];
+        let (class, memory) = (page.class, page.memory);
+        let object_size = CLASSES[usize::from(class)];
+        let bit = 1 << (offset / object_size);
+        if offset % object_size != 0 {
+            return Release::Invalid(FreeError::AllocationNotFound);
+        }
+        if page.used & bit == 0 {
+            return Release::Invalid(FreeError::DoubleFreeDetected);
+        }
+        page.used &= !bit;
+
+        // keep the last page of a class, even if it is empty
+        let last_page = self.used().filter(|page| page.class == class).count() == 1;
+        match memory {
+            Some(memory) if self.0[index].used == 0 && !last_page => {
+                self.0[index] = Page::UNUSED;
+                Release::Page(memory)
+            }
+            _ => Release::Object,
+        }
+    }
+
+    /// Query the object size of the object at the given pointer.
+    ///
+    /// `None` is returned, if the pointer does not belong to any page.
+    fn object_size(&self, ptr: *mut u8) -> Option<usize> {
+        let page = self.0.iter().find(|page| page.offset_of(ptr).is_some())?;
+        Some(CLASSES[usize::from(page.class)])
+    }
+}
+
+#[cfg(test)]
+#[allow(clippy::undocumented_unsafe_blocks)] // the tests use the `unsafe` allocator API
+mod tests {
+    use super::{ClassUsage, Slab};
+    use crate::{Allocator, FreeError, FreePolicy};
+    use core::alloc::{GlobalAlloc, Layout};
+    use core::ptr;
+    use core::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Allocate memory with the given size and alignment.
+    fn alloc<A: GlobalAlloc>(allocator: &A, size: usize, align: usize) -> *mut u8 {
+        let layout = Layout::from_size_align(size, align).unwrap();
+        unsafe { allocator.alloc(layout) }
+    }
+
+    /// Free memory with the given size and alignment.
+    fn dealloc<A: GlobalAlloc>(allocator: &A, ptr: *mut u8, size: usize, align: usize) {
+        let layout = Layout::from_size_align(size, align).unwrap();
+        unsafe { allocator.dealloc(ptr, layout) };
+    }
+
+    #[test]
+    fn small_objects_have_no_header() {
+        let slab = Slab::<_>::new(Allocator::<1024>::new());
+        let a = alloc(&slab, 8, 4);
+        let b = alloc(&slab, 5, 1);
+        let c = alloc(&slab, 1, 8);
+        assert_eq!(b as usize - a as usize, 8);
+        assert_eq!(c as usize - b as usize, 8);
+        assert_eq!(a as usize % 256, 0, "page has to be aligned");
+    }
+
+    #[test]
+    fn size_classes() {
+        let slab = Slab::<_>::new(Allocator::<2048>::new());
+        alloc(&slab, 8, 1);
+        alloc(&slab, 9, 1);
+        alloc(&slab, 4, 32);
+        alloc(&slab, 33, 1);
+        alloc(&slab, 64, 64);
+        let usage = slab.usage();
+        let objects = usage.iter().map(|class| class.objects).collect::<Vec<_>>();
+        assert_eq!(objects, [1, 1, 1, 2]);
+
+        // larger requests are not served by the pages
+        let large = alloc(&slab, 65, 4);
+        assert_ne!(large, ptr::null_mut());
+        assert_eq!(slab.usage(), usage);
+        let aligned = alloc(&slab, 4, 128);
+        assert_eq!(aligned as usize % 128, 0);
+        assert_eq!(slab.usage(), usage);
+    }
+
+    #[test]
+    fn objects_are_reused() {
+        let slab = Slab::<_>::new(Allocator::<1024>::new());
+        let a = alloc(&slab, 16, 4);
+        let b = alloc(&slab, 16, 4);
+        dealloc(&slab, a, 16, 4);
+        assert_eq!(alloc(&slab, 16, 4), a);
+
+        // double frees are ignored (and not reported to the inner allocator)
+        dealloc(&slab, b, 16, 4);
+        dealloc(&slab, b, 16, 4);
+        assert_eq!(slab.usage()[1].objects, 1);
+    }
+
+    #[test]
+    fn invalid_frees_are_recorded() {
+        let slab = Slab::<_>::new(Allocator::<1024>::new());
+        slab.set_free_policy(FreePolicy::Record);
+        slab.inner().set_free_policy(FreePolicy::Record);
+        let a = alloc(&slab, 16, 4);
+        let b = alloc(&slab, 16, 4);
+
+        dealloc(&slab, a, 16, 4);
+        dealloc(&slab, a, 16, 4);
+        let log = slab.free_error_log();
+        assert_eq!(log.count(), 1);
+        let invalid_free = log.last().unwrap();
+        assert_eq!(invalid_free.address, a as usize);
+        assert_eq!(invalid_free.error, FreeError::DoubleFreeDetected);
+
+        // a pointer into the middle of an object does not free it
+        dealloc(&slab, b.wrapping_add(4), 16, 4);
+        let log = slab.free_error_log();
+        assert_eq!(log.count(), 2);
+        assert_eq!(log.last().unwrap().error, FreeError::AllocationNotFound);
+        assert_eq!(slab.usage()[1].objects, 1);
+        // the inner allocator did not see any of them
+        assert_eq!(slab.inner().free_error_log().count(), 0);
+    }
+
+    #[test]
+    fn usage() {
+        let slab = Slab::<_>::new(Allocator::<2048>::new());
+        for _ in 0..40 {
+            alloc(&slab, 8, 8);
+        }
+        let usage = slab.usage();
+        assert_eq!(
+            usage[0],
+            ClassUsage {
+                object_size: 8,
+                pages: 2,
+                objects: 40,
+                capacity: 64,
+            }
+        );
+        assert_eq!(usage[3].pages, 0);
+    }
+
+    #[test]
+    fn empty_pages_are_released() {
+        let slab = Slab::<_>::new(Allocator::<1024>::new());
+        let objects = (0..5).map(|_| alloc(&slab, 64, 8)).collect::<Vec<_>>();
+        assert_eq!(slab.usage()[3].pages, 2);
+
+        // the second page only contains the last object
+        dealloc(&slab, objects[4], 64, 8);
+        assert_eq!(slab.usage()[3].pages, 1);
+
+        // the last page of a class is kept
+        for &object in &objects[..4] {
+            dealloc(&slab, object, 64, 8);
+        }
+        assert_eq!(slab.usage()[3].pages, 1);
+        assert_eq!(slab.usage()[3].objects, 0);
+    }
+
+    #[test]
+    fn fallback_if_pages_are_exhausted() {
+        let slab = Slab::<_, 1>::new(Allocator::<1024>::new());
+        let a = alloc(&slab, 32, 4);
+        assert_eq!(a as usize % 256, 0);
+
+        // the only page is used by another class, so the inner allocator has to
+        // serve this request
+        let b = alloc(&slab, 8, 4);
+        assert_ne!(b, ptr::null_mut());
+        assert_eq!(slab.usage()[0].pages, 0);
+        dealloc(&slab, b, 8, 4);
+        dealloc(&slab, a, 32, 4);
+    }
+
+    #[test]
+    fn fallback_if_inner_allocator_is_full() {
+        // there is not enough memory for an aligned page
+        let slab = Slab::<_>::new(Allocator::<64>::new());
+        let ptr = alloc(&slab, 8, 4);
+        assert_ne!(ptr, ptr::null_mut());
+        assert_eq!(slab.usage()[0].pages, 0);
+    }
+
+    #[test]
+    fn reclaim_hooks_can_use_the_slab() {
+        static SLAB: Slab<Allocator<1024>> = Slab::new(Allocator::new());
+        static CACHE: AtomicUsize = AtomicUsize::new(0);
+        CACHE.store(alloc(&SLAB, 800, 4) as usize, Ordering::Relaxed);
+
+        // there is no room for a page, so the hook is called while a page is
+        // allocated. It must be able to free through the slab.
+        let registered = SLAB.inner().register_reclaim(0, |_| {
+            let cache = CACHE.swap(0, Ordering::Relaxed) as *mut u8;
+            dealloc(&SLAB, cache, 800, 4);
+            !cache.is_null()
+        });
+        assert!(registered);
+        let object = alloc(&SLAB, 8, 4);
+        assert_eq!(object as usize % 256, 0, "object has to be in a page");
+        assert_eq!(CACHE.load(Ordering::Relaxed), 0);
+        assert_eq!(SLAB.usage()[0].objects, 1);
+    }
+
+    #[test]
+    fn alloc_zeroed() {
+        let slab = Slab::<_>::new(Allocator::<1024>::new());
+        let ptr = alloc(&slab, 32, 4);
+        unsafe { ptr.write_bytes(0xFF, 32) };
+        dealloc(&slab, ptr, 32, 4);
+
+        let layout = Layout::from_size_align(32, 4).unwrap();
+        let zeroed = unsafe { slab.alloc_zeroed(layout) };
+        assert_eq!(zeroed, ptr);
+        assert!((0..32).all(|i| unsafe { *zeroed.add(i) } == 0));
+
+        let layout = Layout::from_size_align(100, 4).unwrap();
+        let large = unsafe { slab.alloc_zeroed(layout) };
+        assert!((0..100).all(|i| unsafe { *large.add(i) } == 0));
+    }
+
+    #[test]
+    fn realloc() {
+        let slab = Slab::<_>::new(Allocator::<1060>::new());
+        let layout = Layout::from_size_align(10, 4).unwrap();
+
+        unsafe {
+            let ptr = slab.alloc(layout);
+            for i in 0..10 {
+                ptr.add(i).write(i as u8);
+            }
+            // the object has a size of 16, so it can grow in place
+            assert_eq!(slab.realloc(ptr, layout, 16), ptr);
+
+            let layout = Layout::from_size_align(16, 4).unwrap();
+            let moved = slab.realloc(ptr, layout, 40);
+            assert_ne!(moved, ptr);
+            assert!((0..10).all(|i| *moved.add(i) == i as u8));
+            assert_eq!(slab.usage()[1].objects, 0);
+            assert_eq!(slab.usage()[3].objects, 1);
+
+            // grow out of the pages
+            let layout = Layout::from_size_align(40, 4).unwrap();
+            let large = slab.realloc(moved, layout, 200);
+            assert_ne!(large, ptr::null_mut());
+            assert!((0..10).all(|i| *large.add(i) == i as u8));
+            assert_eq!(slab.usage()[3].objects, 0);
+        }
+    }
+}
@@ -0,0 +1,61 @@
+//! This test uses local heaps via the `allocator-api2` crate next to the
+//! global allocator.
+#![cfg(feature = "allocator-api2")]
+#![no_std]
+
+use allocator_api2::alloc::{AllocError, Allocator as _, Layout};
+use allocator_api2::boxed::Box;
+use allocator_api2::vec::Vec;
+
+const HEAP_SIZE: usize = 1024 * 1024;
+
+#[global_allocator]
+static ALLOCATOR: emballoc::Allocator<HEAP_SIZE> = emballoc::Allocator::new();
+
+extern crate alloc;
+
+#[test]
+fn collections_in_local_heap() {
+    static LOCAL: emballoc::Allocator<4096> = emballoc::Allocator::new();
+
+    let mut local = Vec::new_in(&LOCAL);
+    local.extend(0..100_u32);
+    let boxed = Box::new_in([1_u8; 16], &LOCAL);
+    let global: alloc::vec::Vec<u32> = local.iter().map(|x| x * 2).collect();
+
+    assert_eq!(local.iter().sum::<u32>(), 4950);
+    assert_eq!(global.iter().sum::<u32>(), 9900);
+    assert_eq!(boxed.iter().map(|x| u32::from(*x)).sum::<u32>(), 16);
+}
+
+#[test]
+fn local_heap_on_the_stack() {
+    let local = emballoc::Allocator::<4096>::new();
+
+    let mut strings = Vec::new_in(&local);
+    for i in 0..10 {
+        strings.push(alloc::format!("string {i}"));
+    }
+    let mut numbers = Vec::with_capacity_in(10, &local);
+    numbers.extend(strings.iter().map(alloc::string::String::len));
+    numbers.shrink_to_fit();
+
+    assert_eq!(numbers.iter().sum::<usize>(), 10 * 8);
+}
+
+#[test]
+fn local_heap_is_independent_of_the_global_one() {
+    let local = emballoc::Allocator::<4096>::new();
+
+    // the local heap is exhausted, but the global one is still usable
+    let large = Layout::from_size_align(4096, 4).unwrap();
+    assert_eq!((&local).allocate(large), Err(AllocError));
+    let mut vec = Vec::<u8, _>::new_in(&local);
+    assert!(vec.try_reserve(4096).is_err());
+    let global = alloc::vec![0_u8; 4096];
+    assert_eq!(global.len(), 4096);
+
+    // the collection can still grow up to the size of the local heap
+    vec.extend((0..=255).cycle().take(2048));
+    assert_eq!(vec.len(), 2048);
+}
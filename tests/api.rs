@@ -1,9 +1,16 @@
 #[test]
 fn is_usable_in_const_contexts() {
+    #[allow(clippy::declare_interior_mutable_const)] // this usage is what is tested
     const _ALLOCATOR1: emballoc::Allocator<32> = emballoc::Allocator::new();
     static _ALLOCATOR2: emballoc::Allocator<32> = emballoc::Allocator::new();
 }
 
+#[test]
+fn compact_headers_support_64kib() {
+    static _ALLOCATOR: emballoc::Allocator<{ 64 * 1024 }, emballoc::BestFit, emballoc::Header16> =
+        emballoc::Allocator::new();
+}
+
 #[test]
 fn supports_global_alloc() {
     fn assert<T: core::alloc::GlobalAlloc>(_: T) {}
@@ -21,3 +28,22 @@ fn min_heap_size_of_at_least_8() {
 fn heap_size_must_be_a_multiple_of_4() {
     let _allocator = emballoc::Allocator::<31>::new(); // panic here
 }
+
+#[test]
+#[should_panic(expected = "too small heap memory")]
+fn min_heap_size_of_wide_headers() {
+    let _allocator = emballoc::Allocator::<8, emballoc::BestFit, emballoc::Header64>::new();
+}
+
+#[test]
+#[should_panic(expected = "divisible by the header size")]
+fn heap_size_must_be_a_multiple_of_the_header_size() {
+    let _allocator = emballoc::Allocator::<36, emballoc::BestFit, emballoc::Header64>::new();
+}
+
+#[test]
+#[should_panic(expected = "too large for the header format")]
+fn compact_headers_are_limited_to_64kib() {
+    let _allocator =
+        emballoc::Allocator::<{ 64 * 1024 + 4 }, emballoc::BestFit, emballoc::Header16>::new();
+}
@@ -10,8 +10,8 @@
 //!
 //! The aforementioned behavior is bad for the allocator: if the allocator is
 //! located in the `.data`-section, the whole initial heap is also stored in the
-//! non-volatile flash, despite the fact, that _all but the first four bytes are
-//! uninitialized_!
+//! non-volatile flash, despite the fact, that _the initial heap consists of
+//! zeroes only_!
 //!
 //! Therefore this test makes sure, that an global allocator is not placed in
 //! the `.data`-section. This ensures, that issues like [#30] won't pop up
@@ -53,6 +53,7 @@ struct MemoryMap {
     bss_start: usize,
 }
 impl MemoryMap {
+    #[allow(unused_unsafe)] // older compilers require `unsafe` for `addr_of!` on `extern` statics
     pub fn new() -> Self {
         // The symbols defined in the (default) linker script
         extern "C" {
@@ -0,0 +1,65 @@
+//! This test uses the small-object front-end as the global allocator.
+#![no_std]
+
+const HEAP_SIZE: usize = 1024 * 1024;
+
+#[global_allocator]
+static ALLOCATOR: emballoc::Slab<emballoc::Allocator<HEAP_SIZE>> =
+    emballoc::Slab::new(emballoc::Allocator::new());
+
+extern crate alloc;
+
+use alloc::boxed::Box;
+use alloc::string::String;
+use alloc::vec::Vec;
+
+#[test]
+fn vec() {
+    let mut v = alloc::vec![1, 2, 3];
+    v.push(4);
+
+    assert_eq!((1..=4).collect::<Vec<_>>(), v);
+}
+
+#[test]
+fn map_and_formatting() {
+    let mut map = alloc::collections::BTreeMap::new();
+    map.insert(10, "Hello");
+    map.insert(11, "world");
+    map.insert(-1, "english");
+
+    let english = alloc::format!("[{}]: {}, {}!", map[&-1], map[&10], map[&11]);
+    assert_eq!(english, "[english]: Hello, world!");
+}
+
+#[test]
+fn linked_list_of_small_nodes() {
+    struct Node {
+        value: u32,
+        next: Option<Box<Node>>,
+    }
+
+    let mut list = None;
+    for value in 0..1000 {
+        list = Some(Box::new(Node { value, next: list }));
+    }
+    let mut sum = 0;
+    let mut current = &list;
+    while let Some(node) = current {
+        sum += node.value;
+        current = &node.next;
+    }
+    assert_eq!(sum, 999 * 1000 / 2);
+}
+
+#[test]
+fn growing_strings() {
+    let mut strings = (0..100).map(|_| String::new()).collect::<Vec<_>>();
+    for round in 0..20 {
+        for (index, string) in strings.iter_mut().enumerate() {
+            string.push(char::from(b'a' + ((index + round) % 26) as u8));
+        }
+    }
+    assert!(strings.iter().all(|string| string.len() == 20));
+    assert!(strings[1].starts_with("bcd"));
+}
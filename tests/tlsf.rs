@@ -0,0 +1,66 @@
+//! This test uses the TLSF strategy as the global allocator.
+#![no_std]
+
+use core::alloc::{GlobalAlloc, Layout};
+
+const HEAP_SIZE: usize = 1024 * 1024;
+
+#[global_allocator]
+static ALLOCATOR: emballoc::Allocator<HEAP_SIZE, emballoc::Tlsf> = emballoc::Allocator::new();
+
+extern crate alloc;
+
+#[test]
+fn vec() {
+    let mut v = alloc::vec![1, 2, 3];
+    v.push(4);
+
+    assert_eq!((1..=4).collect::<alloc::vec::Vec<_>>(), v);
+}
+
+#[test]
+fn map_and_formatting() {
+    let mut map = alloc::collections::BTreeMap::new();
+    map.insert(10, "Hello");
+    map.insert(11, "world");
+    map.insert(-1, "english");
+
+    let english = alloc::format!("[{}]: {}, {}!", map[&-1], map[&10], map[&11]);
+    assert_eq!(english, "[english]: Hello, world!");
+}
+
+#[test]
+fn interleaved_allocations_of_different_sizes() {
+    // a local heap, so that the result is independent of the other tests
+    let allocator = emballoc::Allocator::<4096, emballoc::Tlsf>::new();
+
+    let sizes = [1, 200, 17, 64, 3, 1000, 48, 12, 512, 7];
+    let mut pointers = [core::ptr::null_mut::<u8>(); 10];
+    for round in 0..10 {
+        for (index, &size) in sizes.iter().enumerate() {
+            let layout = Layout::from_size_align(size, 4).unwrap();
+            if (index + round) % 3 == 0 && !pointers[index].is_null() {
+                // SAFETY: the pointer was allocated with the same layout
+                unsafe { allocator.dealloc(pointers[index], layout) };
+                pointers[index] = core::ptr::null_mut();
+            } else if pointers[index].is_null() {
+                // SAFETY: the layout has a non-zero size
+                pointers[index] = unsafe { allocator.alloc(layout) };
+                assert!(!pointers[index].is_null());
+            }
+        }
+    }
+
+    // after freeing everything, the heap has to be available again (except for
+    // the size class rounding of the good fit)
+    for (index, &size) in sizes.iter().enumerate() {
+        if !pointers[index].is_null() {
+            let layout = Layout::from_size_align(size, 4).unwrap();
+            // SAFETY: the pointer was allocated with the same layout
+            unsafe { allocator.dealloc(pointers[index], layout) };
+        }
+    }
+    let layout = Layout::from_size_align(3800, 4).unwrap();
+    // SAFETY: the layout has a non-zero size
+    assert!(!unsafe { allocator.alloc(layout) }.is_null());
+}